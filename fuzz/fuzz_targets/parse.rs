@@ -0,0 +1,52 @@
+//! Feeds arbitrary strings to the color parsers. None of them may panic, and whatever
+//! they accept must already be in range. Run with `cargo +nightly fuzz run parse`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use oklch_pixel::parse::{
+    HexAlphaPosition, InputScale, parse_color_in, parse_f64, parse_hex, parse_hue, parse_l,
+    parse_non_negative, parse_unit_range,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    if let Ok(l) = parse_l(input) {
+        assert!((0.0..=1.0).contains(&l), "{input:?} gave L {l}");
+    }
+    if let Ok(h) = parse_hue(input) {
+        assert!(h.is_finite(), "{input:?} gave H {h}");
+    }
+    if let Ok(value) = parse_f64(input, "x") {
+        assert!(value.is_finite() && !value.is_subnormal());
+    }
+    if let Ok(c) = parse_non_negative(input, "C") {
+        assert!(c >= 0.0);
+    }
+    if let Ok(a) = parse_unit_range(input, "A") {
+        assert!((0.0..=1.0).contains(&a));
+    }
+    for position in [HexAlphaPosition::First, HexAlphaPosition::Last] {
+        if let Ok((_, Some(alpha))) = parse_hex(input, position) {
+            assert!((0.0..=1.0).contains(&alpha));
+        }
+    }
+
+    // Split like a command line would, so the whole `L C H [A]` path is reached too.
+    let mut words = input.split_whitespace();
+    let (Some(l), Some(c), Some(h)) = (words.next(), words.next(), words.next()) else {
+        return;
+    };
+    let a = words.next();
+    for scale in [InputScale::Css, InputScale::Percent100] {
+        if let Ok((l, c, h, a)) = parse_color_in(scale, l, c, h, a) {
+            assert!((0.0..=1.0).contains(&l));
+            assert!(c >= 0.0 && c.is_finite());
+            assert!(h.is_finite());
+            assert!(a.is_none_or(|a| (0.0..=1.0).contains(&a)));
+        }
+    }
+});
@@ -0,0 +1,952 @@
+use crate::sample::{to_byte, to_web_safe_byte};
+use crate::{Error, Pixel};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    #[default]
+    DisplayP3,
+    Srgb,
+}
+
+/// How samples are encoded for storage.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Transfer {
+    /// The sRGB curve, which Display P3 shares.
+    #[default]
+    Srgb,
+    /// Linear light, with no curve applied.
+    Linear,
+    /// A curve of the sRGB shape with other constants.
+    Custom(CustomTransfer),
+}
+
+impl Transfer {
+    /// Re-encodes a sample stored with the sRGB curve, as every conversion here produces
+    /// them, with this transfer.
+    pub fn from_srgb(self, encoded: f64) -> f64 {
+        match self {
+            Transfer::Srgb => encoded,
+            Transfer::Linear => srgb_decode(encoded),
+            Transfer::Custom(curve) => curve.encode(srgb_decode(encoded)),
+        }
+    }
+
+    /// Decodes a sample stored with this transfer back to linear light.
+    pub fn to_linear(self, encoded: f64) -> f64 {
+        match self {
+            Transfer::Srgb => srgb_decode(encoded),
+            Transfer::Linear => encoded,
+            Transfer::Custom(curve) => curve.decode(encoded),
+        }
+    }
+}
+
+/// A piecewise transfer shaped like sRGB's: `slope * V` up to the linear-light `threshold`,
+/// then `(1 + offset) * V^(1 / gamma) - offset`. The offset isn't given; it is solved so
+/// the two pieces meet at the threshold, which is how sRGB's 0.055 relates to its other
+/// constants.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CustomTransfer {
+    pub gamma: f64,
+    pub threshold: f64,
+    pub slope: f64,
+    offset: f64,
+}
+
+impl Default for CustomTransfer {
+    /// The sRGB curve, rebuilt from its constants.
+    fn default() -> Self {
+        CustomTransfer::new(
+            CustomTransfer::SRGB_GAMMA,
+            CustomTransfer::SRGB_THRESHOLD,
+            CustomTransfer::SRGB_SLOPE,
+        )
+        .expect("sRGB's constants give a valid curve")
+    }
+}
+
+impl CustomTransfer {
+    /// The sRGB curve's constants, the defaults for any that aren't given.
+    pub const SRGB_GAMMA: f64 = 2.4;
+    pub const SRGB_THRESHOLD: f64 = 0.0031308;
+    pub const SRGB_SLOPE: f64 = 12.92;
+
+    /// Builds the curve, or returns `None` if no offset makes it continuous and rising:
+    /// the linear piece must stay below 1 at the threshold, with `gamma` > 0,
+    /// `threshold` in 0..1, and `slope` >= 0.
+    pub fn new(gamma: f64, threshold: f64, slope: f64) -> Option<Self> {
+        if !(gamma > 0.0 && (0.0..1.0).contains(&threshold) && slope >= 0.0) {
+            return None;
+        }
+        let power = threshold.powf(1.0 / gamma);
+        // slope * t = (1 + offset) * t^(1/gamma) - offset, solved for offset.
+        let offset = (power - slope * threshold) / (1.0 - power);
+        (offset.is_finite() && 1.0 + offset > 0.0).then_some(CustomTransfer {
+            gamma,
+            threshold,
+            slope,
+            offset,
+        })
+    }
+
+    /// The offset solved for in [`CustomTransfer::new`]; about 0.055 with sRGB's
+    /// constants.
+    pub fn offset(&self) -> f64 {
+        self.offset
+    }
+
+    /// Encodes one linear-light value.
+    pub fn encode(&self, linear: f64) -> f64 {
+        if linear <= self.threshold {
+            self.slope * linear
+        } else {
+            (1.0 + self.offset) * linear.powf(1.0 / self.gamma) - self.offset
+        }
+    }
+
+    /// Decodes one encoded value; the inverse of [`CustomTransfer::encode`].
+    pub fn decode(&self, encoded: f64) -> f64 {
+        if encoded <= self.slope * self.threshold {
+            if self.slope > 0.0 {
+                encoded / self.slope
+            } else {
+                0.0
+            }
+        } else {
+            ((encoded + self.offset) / (1.0 + self.offset)).powf(self.gamma)
+        }
+    }
+
+    /// The slope of the power piece where it meets the linear one, which for a smooth
+    /// curve is close to `slope`. `None` when the threshold is 0 and there is no linear
+    /// piece.
+    pub fn power_slope_at_threshold(&self) -> Option<f64> {
+        (self.threshold > 0.0)
+            .then(|| (1.0 + self.offset) / self.gamma * self.threshold.powf(1.0 / self.gamma - 1.0))
+    }
+}
+
+/// Luma coefficients for Y'CbCr output.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum YcbcrMatrix {
+    Bt709,
+    Bt2020,
+}
+
+/// Converts encoded R'G'B' in 0..1 to Y' in 0..1 and Cb, Cr centered on 0.5.
+pub fn rgb_to_ycbcr(matrix: YcbcrMatrix, r: f64, g: f64, b: f64) -> (f64, f64, f64) {
+    let (kr, kb) = match matrix {
+        YcbcrMatrix::Bt709 => (0.2126, 0.0722),
+        YcbcrMatrix::Bt2020 => (0.2627, 0.0593),
+    };
+    let y = kr * r + (1.0 - kr - kb) * g + kb * b;
+    let cb = (b - y) / (2.0 * (1.0 - kb)) + 0.5;
+    let cr = (r - y) / (2.0 * (1.0 - kr)) + 0.5;
+    (y, cb, cr)
+}
+
+/// Which XYZ to linear Display P3 matrix to use. sRGB output never goes through XYZ, so
+/// it is unaffected.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum P3Matrix {
+    /// The rounded coefficients in [`xyz_to_lin_display_p3`].
+    #[default]
+    Baked,
+    /// Derived at full `f64` precision by [`derived_xyz_to_display_p3_matrix`].
+    Derived,
+}
+
+/// Converts OKLCH to clipped, sRGB-transfer-encoded samples in `space`. The flag is
+/// set if any channel had to be clipped.
+pub fn encoded_pixel(
+    space: ColorSpace,
+    matrix: P3Matrix,
+    l: f64,
+    c: f64,
+    h: f64,
+    alpha: f64,
+) -> Result<(Pixel, bool), Error> {
+    let (r_lin, g_lin, b_lin, clipped) = match space {
+        ColorSpace::DisplayP3 => oklch_to_display_p3_linear_with(matrix, l, c, h)?,
+        ColorSpace::Srgb => oklch_to_srgb_linear(l, c, h)?,
+    };
+    let pixel = Pixel {
+        r: srgb_encode(r_lin),
+        g: srgb_encode(g_lin),
+        b: srgb_encode(b_lin),
+        a: alpha,
+    };
+    Ok((pixel, clipped))
+}
+
+/// The color as 8-bit sRGB, clipped to the sRGB gamut.
+pub fn srgb_bytes(l: f64, c: f64, h: f64) -> Result<[u8; 3], Error> {
+    let (r, g, b, _) = oklch_to_srgb_linear(l, c, h)?;
+    Ok([
+        to_byte(srgb_encode(r)),
+        to_byte(srgb_encode(g)),
+        to_byte(srgb_encode(b)),
+    ])
+}
+
+/// The color snapped to the 6x6x6 web-safe palette: each sRGB channel, clipped to the
+/// sRGB gamut, rounded to the nearest multiple of 0x33.
+pub fn web_safe_bytes(l: f64, c: f64, h: f64) -> Result<[u8; 3], Error> {
+    let (r, g, b, _) = oklch_to_srgb_linear(l, c, h)?;
+    Ok([
+        to_web_safe_byte(srgb_encode(r)),
+        to_web_safe_byte(srgb_encode(g)),
+        to_web_safe_byte(srgb_encode(b)),
+    ])
+}
+
+/// `#rrggbb`, or `#rrggbbaa` when alpha is given.
+pub fn srgb_hex(l: f64, c: f64, h: f64, a: Option<f64>) -> Result<String, Error> {
+    let [r, g, b] = srgb_bytes(l, c, h)?;
+    let mut hex = format!("#{r:02x}{g:02x}{b:02x}");
+    if let Some(alpha) = a {
+        hex.push_str(&format!("{:02x}", to_byte(alpha)));
+    }
+    Ok(hex)
+}
+
+/// Chroma below which the binary treats a color as exactly neutral unless
+/// `--neutral-epsilon` says otherwise; well under anything visible, but above the noise a
+/// computed chroma of "zero" tends to carry.
+pub const DEFAULT_NEUTRAL_EPSILON: f64 = 1e-6;
+
+/// How close to zero chroma, and to L = 0 or L = 1, a color must be for
+/// [`snap_neutral_extremes`] to treat it as exact black or white.
+pub const NEUTRAL_SNAP_EPSILON: f64 = 1e-4;
+
+/// Replaces the samples of achromatic near-black and near-white colors with exact 0 or 1,
+/// undoing the rounding error from the matrix round trip. Colors with more chroma than
+/// [`NEUTRAL_SNAP_EPSILON`] are returned unchanged.
+pub fn snap_neutral_extremes(l: f64, c: f64, pixel: Pixel) -> Pixel {
+    if c > NEUTRAL_SNAP_EPSILON {
+        return pixel;
+    }
+    let value = if l <= NEUTRAL_SNAP_EPSILON {
+        0.0
+    } else if l >= 1.0 - NEUTRAL_SNAP_EPSILON {
+        1.0
+    } else {
+        return pixel;
+    };
+    Pixel {
+        r: value,
+        g: value,
+        b: value,
+        a: pixel.a,
+    }
+}
+
+/// Snaps alpha within `epsilon` of 0 or 1 to exactly 0 or 1.
+pub fn snap_alpha(alpha: f64, epsilon: f64) -> f64 {
+    if alpha < epsilon {
+        0.0
+    } else if alpha > 1.0 - epsilon {
+        1.0
+    } else {
+        alpha
+    }
+}
+
+/// Composites an encoded pixel over an opaque encoded background, mixing in linear
+/// light. The result is opaque.
+pub fn composite_over(pixel: Pixel, background: Pixel) -> Pixel {
+    let mix = |fg: f64, bg: f64| {
+        srgb_encode(srgb_decode(fg) * pixel.a + srgb_decode(bg) * (1.0 - pixel.a))
+    };
+    Pixel {
+        r: mix(pixel.r, background.r),
+        g: mix(pixel.g, background.g),
+        b: mix(pixel.b, background.b),
+        a: 1.0,
+    }
+}
+
+/// Multiplies an encoded pixel's color by its alpha in linear light and re-encodes it.
+pub fn premultiply(pixel: Pixel) -> Pixel {
+    let scale = |value: f64| srgb_encode(srgb_decode(value) * pixel.a);
+    Pixel {
+        r: scale(pixel.r),
+        g: scale(pixel.g),
+        b: scale(pixel.b),
+        a: pixel.a,
+    }
+}
+
+/// The stored pixel for an encoded color: composited over `background` when there is
+/// one, then premultiplied when asked. This is the only place alpha is changed, and it
+/// works before quantizing, so every bit depth gets the same alpha.
+pub fn compose_pixel(pixel: Pixel, background: Option<Pixel>, premultiplied: bool) -> Pixel {
+    let pixel = match background {
+        Some(background) => composite_over(pixel, background),
+        None => pixel,
+    };
+    if premultiplied {
+        premultiply(pixel)
+    } else {
+        pixel
+    }
+}
+
+/// Rounds `value` to the nearest multiple of `step`, then to as many decimal places as
+/// `step` has, so that 0.3 rounded to 0.1 is 0.3 and not 0.30000000000000004.
+pub fn round_to_step(value: f64, step: f64) -> f64 {
+    let rounded = (value / step).round() * step;
+    let decimals = (0..12)
+        .find(|&places| {
+            let scaled = step * 10f64.powi(places);
+            (scaled - scaled.round()).abs() < 1e-9 * scaled.max(1.0)
+        })
+        .unwrap_or(12);
+    let scale = 10f64.powi(decimals);
+    (rounded * scale).round() / scale + 0.0
+}
+
+pub fn snap_hue(h_deg: f64, steps: u32) -> f64 {
+    let step = 360.0 / f64::from(steps);
+    normalize_hue((h_deg / step).round() * step)
+}
+
+/// Hues this close below 360° are treated as 0°.
+const HUE_WRAP_EPSILON: f64 = 1e-9;
+
+/// Maps a hue into `0.0..360.0`. Values within [`HUE_WRAP_EPSILON`] of 360°, which
+/// `rem_euclid` produces for tiny negative hues, and `-0.0` all come out as exactly `0.0`,
+/// so 360, -0, and 720 give the same a and b as 0.
+pub fn normalize_hue(h_deg: f64) -> f64 {
+    let h = h_deg.rem_euclid(360.0);
+    if 360.0 - h < HUE_WRAP_EPSILON {
+        0.0
+    } else {
+        h + 0.0
+    }
+}
+
+pub fn oklch_to_oklab(l: f64, c: f64, h_deg: f64) -> (f64, f64, f64) {
+    let h = normalize_hue(h_deg).to_radians();
+    (l, c * h.cos(), c * h.sin())
+}
+
+pub fn oklch_to_display_p3_linear(
+    l: f64,
+    c: f64,
+    h_deg: f64,
+) -> Result<(f64, f64, f64, bool), Error> {
+    oklch_to_display_p3_linear_with(P3Matrix::Baked, l, c, h_deg)
+}
+
+pub fn oklch_to_display_p3_linear_with(
+    matrix: P3Matrix,
+    l: f64,
+    c: f64,
+    h_deg: f64,
+) -> Result<(f64, f64, f64, bool), Error> {
+    let (l, a, b) = oklch_to_oklab(l, c, h_deg);
+    check_oklab(l, a, b)?;
+    let (r, g, b) = if a == 0.0 && b == 0.0 {
+        neutral_linear(l)
+    } else {
+        let (x, y, z) = oklab_to_xyz(l, a, b);
+        xyz_to_lin_display_p3_with(matrix, x, y, z)
+    };
+
+    clamp_linear_rgb(r, g, b)
+}
+
+/// The linear light of Oklab `(l, 0, 0)` in any RGB space with a D65 white: the same
+/// value in every channel. Going through the matrices instead leaves their rounding in
+/// the result, enough to tint a gray by one 16-bit step.
+pub fn neutral_linear(l: f64) -> (f64, f64, f64) {
+    let y = l * l * l;
+    (y, y, y)
+}
+
+/// How far each linear Display P3 channel lies outside 0..1 before clipping: negative
+/// below 0, positive above 1, and zero for channels that are in range.
+pub fn display_p3_overshoot(
+    matrix: P3Matrix,
+    l: f64,
+    c: f64,
+    h_deg: f64,
+) -> Result<(f64, f64, f64), Error> {
+    let (r, g, b) = oklch_to_linear(ColorSpace::DisplayP3, matrix, l, c, h_deg)?;
+
+    let overshoot = |value: f64| {
+        if value < 0.0 {
+            value
+        } else if value > 1.0 {
+            value - 1.0
+        } else {
+            0.0
+        }
+    };
+    Ok((overshoot(r), overshoot(g), overshoot(b)))
+}
+
+/// Linear RGB in `space` before any clipping.
+pub fn oklch_to_linear(
+    space: ColorSpace,
+    matrix: P3Matrix,
+    l: f64,
+    c: f64,
+    h_deg: f64,
+) -> Result<(f64, f64, f64), Error> {
+    let (l, a, b) = oklch_to_oklab(l, c, h_deg);
+    check_oklab(l, a, b)?;
+    let (r, g, b) = match space {
+        _ if a == 0.0 && b == 0.0 => neutral_linear(l),
+        ColorSpace::DisplayP3 => {
+            let (x, y, z) = oklab_to_xyz(l, a, b);
+            xyz_to_lin_display_p3_with(matrix, x, y, z)
+        }
+        ColorSpace::Srgb => oklab_to_lin_srgb(l, a, b),
+    };
+    if !r.is_finite() || !g.is_finite() || !b.is_finite() {
+        return Err(Error::Conversion);
+    }
+    Ok((r, g, b))
+}
+
+/// The inverse of [`oklch_to_linear`], back to Oklab rather than OKLCH.
+pub fn linear_to_oklab(
+    space: ColorSpace,
+    matrix: P3Matrix,
+    r: f64,
+    g: f64,
+    b: f64,
+) -> (f64, f64, f64) {
+    let (r, g, b) = match space {
+        ColorSpace::DisplayP3 => {
+            let to_xyz = match matrix {
+                P3Matrix::Baked => DISPLAY_P3_TO_XYZ,
+                P3Matrix::Derived => invert_matrix(&derived_xyz_to_display_p3_matrix()),
+            };
+            let xyz = apply_matrix(&to_xyz, (r, g, b));
+            apply_matrix(&XYZ_TO_SRGB, xyz)
+        }
+        ColorSpace::Srgb => (r, g, b),
+    };
+    lin_srgb_to_oklab(r, g, b)
+}
+
+pub fn lin_srgb_to_oklab(r: f64, g: f64, b: f64) -> (f64, f64, f64) {
+    let l = 0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b;
+    let m = 0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b;
+    let s = 0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b;
+
+    let (l_, m_, s_) = (l.cbrt(), m.cbrt(), s.cbrt());
+
+    (
+        0.2104542553 * l_ + 0.7936177850 * m_ - 0.0040720468 * s_,
+        1.9779984951 * l_ - 2.4285922050 * m_ + 0.4505937099 * s_,
+        0.0259040371 * l_ + 0.7827717662 * m_ - 0.8086757660 * s_,
+    )
+}
+
+/// The OKLCH coordinates of an 8-bit sRGB color.
+pub fn srgb_to_oklch([r, g, b]: [u8; 3]) -> (f64, f64, f64) {
+    let decode = |v: u8| srgb_decode(f64::from(v) / 255.0);
+    let (l, a, b) = lin_srgb_to_oklab(decode(r), decode(g), decode(b));
+    (l, a.hypot(b), b.atan2(a).to_degrees().rem_euclid(360.0))
+}
+
+pub fn oklch_to_srgb_linear(l: f64, c: f64, h_deg: f64) -> Result<(f64, f64, f64, bool), Error> {
+    let (l, a, b) = oklch_to_oklab(l, c, h_deg);
+    check_oklab(l, a, b)?;
+    let (r, g, b) = if a == 0.0 && b == 0.0 {
+        neutral_linear(l)
+    } else {
+        oklab_to_lin_srgb(l, a, b)
+    };
+
+    clamp_linear_rgb(r, g, b)
+}
+
+/// Oklab to linear LMS cone responses.
+///
+/// These are the cubed values, the intermediate where huge chroma first overflows.
+pub fn oklab_to_lms(l: f64, a: f64, b: f64) -> (f64, f64, f64) {
+    let l_ = l + 0.3963377774 * a + 0.2158037573 * b;
+    let m_ = l - 0.1055613458 * a - 0.0638541728 * b;
+    let s_ = l - 0.0894841775 * a - 1.2914855480 * b;
+
+    (l_ * l_ * l_, m_ * m_ * m_, s_ * s_ * s_)
+}
+
+/// Checks that converting this Oklab color won't overflow, naming the first cone
+/// response that does. Everything after the cube is a plain matrix, so a finite result
+/// here means only the final channels can still be non-finite.
+pub fn check_oklab(l: f64, a: f64, b: f64) -> Result<(), Error> {
+    let (l, m, s) = oklab_to_lms(l, a, b);
+    for (component, value) in [("L", l), ("M", m), ("S", s)] {
+        if !value.is_finite() {
+            return Err(Error::Overflow {
+                component: component.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn clamp_linear_rgb(r: f64, g: f64, b: f64) -> Result<(f64, f64, f64, bool), Error> {
+    if !r.is_finite() || !g.is_finite() || !b.is_finite() {
+        return Err(Error::Conversion);
+    }
+
+    let mut clipped = false;
+    let r = clamp01(r, &mut clipped);
+    let g = clamp01(g, &mut clipped);
+    let b = clamp01(b, &mut clipped);
+
+    Ok((r, g, b, clipped))
+}
+
+pub fn oklab_to_xyz(l: f64, a: f64, b: f64) -> (f64, f64, f64) {
+    // Oklab is defined over linear sRGB; convert to linear sRGB, then to XYZ D65.
+    let (r_lin, g_lin, b_lin) = oklab_to_lin_srgb(l, a, b);
+
+    let x = 0.4124564 * r_lin + 0.3575761 * g_lin + 0.1804375 * b_lin;
+    let y = 0.2126729 * r_lin + 0.7151522 * g_lin + 0.0721750 * b_lin;
+    let z = 0.0193339 * r_lin + 0.1191920 * g_lin + 0.9503041 * b_lin;
+
+    (x, y, z)
+}
+
+pub fn oklab_to_lin_srgb(l: f64, a: f64, b: f64) -> (f64, f64, f64) {
+    let (l, m, s) = oklab_to_lms(l, a, b);
+
+    let r_lin = 4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s;
+    let g_lin = -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s;
+    let b_lin = -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s;
+
+    (r_lin, g_lin, b_lin)
+}
+
+pub fn xyz_to_lin_display_p3(x: f64, y: f64, z: f64) -> (f64, f64, f64) {
+    let r = 2.493496911941425 * x - 0.9313836179191239 * y - 0.40271078445071684 * z;
+    let g = -0.8294889695615747 * x + 1.7626640603183463 * y + 0.023624685841943577 * z;
+    let b = 0.03584583024378447 * x - 0.07617238926804182 * y + 0.9568845240076872 * z;
+    (r, g, b)
+}
+
+pub fn xyz_to_lin_display_p3_with(matrix: P3Matrix, x: f64, y: f64, z: f64) -> (f64, f64, f64) {
+    match matrix {
+        P3Matrix::Baked => xyz_to_lin_display_p3(x, y, z),
+        P3Matrix::Derived => apply_matrix(&derived_xyz_to_display_p3_matrix(), (x, y, z)),
+    }
+}
+
+/// A CRC-32 over the linear Display P3 values of a fixed set of Oklab probes, run through
+/// `matrix`. Any change to the Oklab, XYZ or Display P3 constants changes it, so it
+/// identifies the color math that produced an image.
+pub fn conversion_fingerprint(matrix: P3Matrix) -> u32 {
+    const PROBES: [(f64, f64, f64); 5] = [
+        (1.0, 0.0, 0.0),
+        (0.5, 0.1, -0.1),
+        (0.7, -0.2, 0.15),
+        (0.3, 0.05, 0.2),
+        (0.9, -0.05, -0.25),
+    ];
+    let mut hasher = crc32fast::Hasher::new();
+    for (l, a, b) in PROBES {
+        let (x, y, z) = oklab_to_xyz(l, a, b);
+        let (r, g, b) = xyz_to_lin_display_p3_with(matrix, x, y, z);
+        for value in [r, g, b] {
+            hasher.update(&value.to_bits().to_le_bytes());
+        }
+    }
+    hasher.finalize()
+}
+
+/// Linear Display P3 to XYZ D65; the inverse of [`xyz_to_lin_display_p3`].
+const DISPLAY_P3_TO_XYZ: [[f64; 3]; 3] = [
+    [0.4865709486482162, 0.26566769316909306, 0.1982172852343625],
+    [0.2289745640697488, 0.6917385218365064, 0.079286914093745],
+    [0.0, 0.04511338185890264, 1.043944368900976],
+];
+
+/// The relative luminance (CIE Y, 0..1) of encoded Display P3 samples.
+pub fn display_p3_luminance(pixel: Pixel) -> f64 {
+    linear_luminance(
+        ColorSpace::DisplayP3,
+        (
+            srgb_decode(pixel.r),
+            srgb_decode(pixel.g),
+            srgb_decode(pixel.b),
+        ),
+    )
+}
+
+/// The relative luminance of linear, unclipped samples in `space`.
+pub fn linear_luminance(space: ColorSpace, (r, g, b): (f64, f64, f64)) -> f64 {
+    let [kr, kg, kb] = match space {
+        ColorSpace::DisplayP3 => DISPLAY_P3_TO_XYZ[1],
+        ColorSpace::Srgb => [0.2126729, 0.7151522, 0.0721750],
+    };
+    kr * r + kg * g + kb * b
+}
+
+/// Binary-searches for the OKLCH lightness at which chroma `c` and hue `h`, clipped to
+/// Display P3, have relative luminance `target`. Luminance rises with L at a fixed
+/// chroma and hue, so this converges; the result is within 1e-9 of the L that hits the
+/// target, or the nearest end of 0..1 if no L reaches it.
+pub fn lightness_for_luminance(
+    matrix: P3Matrix,
+    c: f64,
+    h: f64,
+    target: f64,
+) -> Result<f64, Error> {
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..32 {
+        let mid = (low + high) / 2.0;
+        let (pixel, _) = encoded_pixel(ColorSpace::DisplayP3, matrix, mid, c, h, 1.0)?;
+        if display_p3_luminance(pixel) < target {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Ok((low + high) / 2.0)
+}
+
+/// The inverse of [`oklab_to_xyz`].
+pub fn xyz_to_oklab(x: f64, y: f64, z: f64) -> (f64, f64, f64) {
+    let (r, g, b) = apply_matrix(&XYZ_TO_SRGB, (x, y, z));
+    lin_srgb_to_oklab(r, g, b)
+}
+
+/// XYZ D65 to linear sRGB; the inverse of the matrix in [`oklab_to_xyz`].
+const XYZ_TO_SRGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+const TOE_K1: f64 = 0.206;
+const TOE_K2: f64 = 0.03;
+const TOE_K3: f64 = (1.0 + TOE_K1) / (1.0 + TOE_K2);
+
+/// Björn Ottosson's toe, which maps Oklab L to the "reference" lightness Lr that some
+/// color pickers show: `Lr = (k3·L − k1 + √((k3·L − k1)² + 4·k2·k3·L)) / 2` with
+/// k1 = 0.206, k2 = 0.03, and k3 = (1 + k1) / (1 + k2). It darkens the midtones and
+/// shadows, bringing Lr closer to CIELAB L*, and keeps 0 and 1 fixed.
+pub fn toe(l: f64) -> f64 {
+    let x = TOE_K3 * l - TOE_K1;
+    0.5 * (x + (x * x + 4.0 * TOE_K2 * TOE_K3 * l).sqrt())
+}
+
+/// The inverse of [`toe`]: `L = (Lr² + k1·Lr) / (k3·(Lr + k2))`.
+pub fn toe_inv(lr: f64) -> f64 {
+    (lr * lr + TOE_K1 * lr) / (TOE_K3 * (lr + TOE_K2))
+}
+
+/// Linear sRGB to XYZ D65; the matrix in [`oklab_to_xyz`].
+const SRGB_TO_XYZ: [[f64; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041],
+];
+
+/// Re-encodes sRGB samples (0..1) as Display P3 samples, going through linear light and
+/// XYZ. sRGB lies inside Display P3, so only float noise is clamped away.
+pub fn srgb_to_display_p3(r: f64, g: f64, b: f64) -> (f64, f64, f64) {
+    let xyz = apply_matrix(
+        &SRGB_TO_XYZ,
+        (srgb_decode(r), srgb_decode(g), srgb_decode(b)),
+    );
+    let (r, g, b) = xyz_to_lin_display_p3(xyz.0, xyz.1, xyz.2);
+    let encode = |v: f64| srgb_encode(v).clamp(0.0, 1.0);
+    (encode(r), encode(g), encode(b))
+}
+
+/// sRGB samples (0..1) for an HSL color: hue in degrees, saturation and lightness in
+/// 0..1.
+pub fn hsl_to_srgb(h: f64, s: f64, l: f64) -> (f64, f64, f64) {
+    let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    hue_chroma_to_srgb(h, chroma, l - chroma / 2.0)
+}
+
+/// sRGB samples (0..1) for an HSV color: hue in degrees, saturation and value in 0..1.
+pub fn hsv_to_srgb(h: f64, s: f64, v: f64) -> (f64, f64, f64) {
+    let chroma = v * s;
+    hue_chroma_to_srgb(h, chroma, v - chroma)
+}
+
+/// The shared last step of HSL and HSV: place `chroma` on the hexagon at `h`, then lift
+/// every channel by `min`.
+fn hue_chroma_to_srgb(h: f64, chroma: f64, min: f64) -> (f64, f64, f64) {
+    let sector = normalize_hue(h) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    (r + min, g + min, b + min)
+}
+
+/// Display P3 red, green, and blue primaries as CIE 1931 xy chromaticities.
+pub const DISPLAY_P3_PRIMARIES: [(f64, f64); 3] = [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060)];
+
+/// The D65 white point as CIE 1931 xy chromaticities.
+pub const D65_WHITE: (f64, f64) = (0.3127, 0.3290);
+
+/// The XYZ to linear Display P3 matrix, built from [`DISPLAY_P3_PRIMARIES`] and
+/// [`D65_WHITE`] with the usual construction: scale each primary's XYZ so the three sum
+/// to the white point, then invert.
+pub fn derived_xyz_to_display_p3_matrix() -> [[f64; 3]; 3] {
+    let xyz = |(x, y): (f64, f64)| (x / y, 1.0, (1.0 - x - y) / y);
+    let [red, green, blue] = DISPLAY_P3_PRIMARIES.map(xyz);
+    let primaries = [
+        [red.0, green.0, blue.0],
+        [red.1, green.1, blue.1],
+        [red.2, green.2, blue.2],
+    ];
+    let scale = apply_matrix(&invert_matrix(&primaries), xyz(D65_WHITE));
+    let scale = [scale.0, scale.1, scale.2];
+    let rgb_to_xyz = primaries.map(|row| [0, 1, 2].map(|i| row[i] * scale[i]));
+    invert_matrix(&rgb_to_xyz)
+}
+
+fn apply_matrix(m: &[[f64; 3]; 3], (x, y, z): (f64, f64, f64)) -> (f64, f64, f64) {
+    let row = |r: [f64; 3]| r[0] * x + r[1] * y + r[2] * z;
+    (row(m[0]), row(m[1]), row(m[2]))
+}
+
+/// Inverts a 3x3 matrix by cofactors. Only called on the well-conditioned primary
+/// matrices above, so there's no singularity check.
+fn invert_matrix(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor = |r: usize, c: usize| {
+        let (r1, r2) = ((r + 1) % 3, (r + 2) % 3);
+        let (c1, c2) = ((c + 1) % 3, (c + 2) % 3);
+        m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1]
+    };
+    let det = m[0][0] * cofactor(0, 0) + m[0][1] * cofactor(0, 1) + m[0][2] * cofactor(0, 2);
+    // The inverse is the transposed cofactor matrix over the determinant.
+    [0, 1, 2].map(|r| [0, 1, 2].map(|c| cofactor(c, r) / det))
+}
+
+fn clamp01(value: f64, clipped: &mut bool) -> f64 {
+    if value < 0.0 {
+        *clipped = true;
+        0.0
+    } else if value > 1.0 {
+        *clipped = true;
+        1.0
+    } else {
+        value
+    }
+}
+
+/// The inverse of [`srgb_encode`].
+pub fn srgb_decode(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn srgb_encode(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_p3_matrix_matches_the_baked_constants() {
+        let derived = derived_xyz_to_display_p3_matrix();
+        let columns = [(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)]
+            .map(|(x, y, z)| xyz_to_lin_display_p3(x, y, z));
+        let baked = [0, 1, 2].map(|r| columns.map(|(red, green, blue)| [red, green, blue][r]));
+        let max_difference = (0..3)
+            .flat_map(|r| (0..3).map(move |c| (r, c)))
+            .map(|(r, c)| (derived[r][c] - baked[r][c]).abs())
+            .fold(0.0, f64::max);
+        assert!(max_difference < 1e-13, "max difference {max_difference:e}");
+    }
+
+    fn overflow(component: &str) -> Error {
+        Error::Overflow {
+            component: component.to_string(),
+        }
+    }
+
+    #[test]
+    fn huge_chroma_overflows_in_the_first_cone_response() {
+        assert_eq!(check_oklab(0.5, 1e300, 0.0), Err(overflow("L")));
+        for space in [ColorSpace::Srgb, ColorSpace::DisplayP3] {
+            for matrix in [P3Matrix::Baked, P3Matrix::Derived] {
+                assert_eq!(
+                    encoded_pixel(space, matrix, 0.5, 1e300, 30.0, 1.0),
+                    Err(overflow("L"))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn overflow_names_the_cone_response_that_overflowed() {
+        // Only S's weight on b is large enough to push its cube past f64::MAX here.
+        assert_eq!(check_oklab(0.5, 0.0, 1e103), Err(overflow("S")));
+        assert_eq!(check_oklab(0.5, 0.0, -1e103), Err(overflow("S")));
+        assert_eq!(check_oklab(0.5, 1e100, 1e100), Ok(()));
+    }
+
+    #[test]
+    fn normalize_hue_wraps_onto_zero_to_360() {
+        for (input, expected) in [
+            (360.0, 0.0),
+            (-0.0, 0.0),
+            (720.0, 0.0),
+            (-90.0, 270.0),
+            (-1e-12, 0.0),
+            (359.5, 359.5),
+        ] {
+            let h = normalize_hue(input);
+            assert_eq!(h.to_bits(), f64::to_bits(expected), "{input} gave {h}");
+        }
+    }
+
+    #[test]
+    fn wrapped_hues_give_the_same_oklab() {
+        let zero = oklch_to_oklab(0.7, 0.1, 0.0);
+        for h in [360.0, -0.0, 720.0] {
+            assert_eq!(oklch_to_oklab(0.7, 0.1, h), zero);
+        }
+        assert_eq!(
+            oklch_to_oklab(0.7, 0.1, -90.0),
+            oklch_to_oklab(0.7, 0.1, 270.0)
+        );
+    }
+
+    fn bytes(pixel: Pixel) -> [u8; 4] {
+        [pixel.r, pixel.g, pixel.b, pixel.a].map(crate::sample::to_byte)
+    }
+
+    #[test]
+    fn premultiplying_in_linear_light_differs_from_encoded_values() {
+        let mid = Pixel {
+            r: 0.5,
+            g: 0.5,
+            b: 0.5,
+            a: 0.5,
+        };
+        // Premultiply in linear light, then encode: what compose_pixel does.
+        let linear = compose_pixel(mid, None, true);
+        assert_eq!(linear, premultiply(mid));
+        // Encode first, then premultiply the encoded values.
+        let encoded = Pixel {
+            r: mid.r * mid.a,
+            g: mid.g * mid.a,
+            b: mid.b * mid.a,
+            a: mid.a,
+        };
+        assert_ne!(bytes(linear), bytes(encoded));
+        assert_eq!(bytes(encoded)[0], 64);
+        assert!(bytes(linear)[0] > 64);
+    }
+
+    #[test]
+    fn composite_happens_before_premultiply() {
+        let translucent = Pixel {
+            r: 0.8,
+            g: 0.3,
+            b: 0.2,
+            a: 0.5,
+        };
+        let gray = Pixel {
+            r: 0.5,
+            g: 0.5,
+            b: 0.5,
+            a: 1.0,
+        };
+        let composed = compose_pixel(translucent, Some(gray), true);
+        assert_eq!(composed, composite_over(translucent, gray));
+        // The other order darkens the color twice: once by premultiplying and again
+        // when the premultiplied color is composited as if it were straight.
+        let reversed = composite_over(premultiply(translucent), gray);
+        assert_ne!(bytes(composed), bytes(reversed));
+        assert!(bytes(reversed)[0] < bytes(composed)[0]);
+    }
+
+    #[test]
+    fn compose_pixel_alpha_is_the_same_at_both_bit_depths() {
+        let gray = Pixel {
+            r: 0.5,
+            g: 0.5,
+            b: 0.5,
+            a: 1.0,
+        };
+        for alpha in [0.0, 0.5, 1.0] {
+            let pixel = Pixel {
+                r: 0.9,
+                g: 0.4,
+                b: 0.1,
+                a: alpha,
+            };
+            for background in [None, Some(gray)] {
+                for premultiplied in [false, true] {
+                    let composed = compose_pixel(pixel, background, premultiplied);
+                    let expected = if background.is_some() { 1.0 } else { alpha };
+                    assert_eq!(composed.a, expected);
+
+                    let samples = |bit_depth| {
+                        let mut buf = Vec::new();
+                        crate::sample::push_pixel(
+                            &mut buf,
+                            composed,
+                            bit_depth,
+                            true,
+                            crate::sample::ChannelOrder::Rgba,
+                        );
+                        buf
+                    };
+                    let eight = samples(8)[3];
+                    let sixteen = samples(16);
+                    let sixteen = u16::from_be_bytes([sixteen[6], sixteen[7]]);
+                    assert_eq!(eight, (expected * 255.0).round() as u8);
+                    assert_eq!(sixteen, (expected * 65535.0).round() as u16);
+                    assert_eq!(sixteen >> 8, u16::from(eight));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compose_pixel_at_the_alpha_extremes() {
+        let pixel = Pixel {
+            r: 0.9,
+            g: 0.4,
+            b: 0.1,
+            a: 1.0,
+        };
+        let gray = Pixel {
+            r: 0.5,
+            g: 0.5,
+            b: 0.5,
+            a: 1.0,
+        };
+        let clear = Pixel { a: 0.0, ..pixel };
+
+        assert_eq!(compose_pixel(pixel, None, false), pixel);
+        assert_eq!(bytes(compose_pixel(pixel, None, true)), bytes(pixel));
+        assert_eq!(bytes(compose_pixel(pixel, Some(gray), false)), bytes(pixel));
+        assert_eq!(bytes(compose_pixel(clear, Some(gray), true)), bytes(gray));
+        assert_eq!(bytes(compose_pixel(clear, None, true)), [0, 0, 0, 0]);
+        assert_eq!(compose_pixel(clear, None, false), clear);
+    }
+}
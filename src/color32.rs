@@ -0,0 +1,112 @@
+//! `f32` versions of the OKLCH to encoded sample path in [`crate::color`], for jobs large
+//! enough that speed matters more than the last bits of precision. The coefficients are
+//! the `f64` ones rounded to `f32`; the results stay well under one 8-bit step from the
+//! `f64` path.
+
+use crate::color::{ColorSpace, check_oklab, oklch_to_oklab};
+use crate::{Error, Pixel};
+
+/// Oklab to cube-rooted LMS; [`crate::color`]'s `oklab_to_lms` before the cube.
+const OKLAB_TO_LMS: [[f32; 3]; 3] = to_f32([
+    [1.0, 0.3963377774, 0.2158037573],
+    [1.0, -0.1055613458, -0.0638541728],
+    [1.0, -0.0894841775, -1.2914855480],
+]);
+
+/// LMS to linear sRGB, as in [`crate::color::oklab_to_lin_srgb`].
+const LMS_TO_LIN_SRGB: [[f32; 3]; 3] = to_f32([
+    [4.0767416621, -3.3077115913, 0.2309699292],
+    [-1.2684380046, 2.6097574011, -0.3413193965],
+    [-0.0041960863, -0.7034186147, 1.7076147010],
+]);
+
+/// Linear sRGB to XYZ D65, as in [`crate::color::oklab_to_xyz`].
+const LIN_SRGB_TO_XYZ: [[f32; 3]; 3] = to_f32([
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041],
+]);
+
+/// XYZ D65 to linear Display P3, as in [`crate::color::xyz_to_lin_display_p3`].
+const XYZ_TO_LIN_DISPLAY_P3: [[f32; 3]; 3] = to_f32([
+    [2.493496911941425, -0.9313836179191239, -0.40271078445071684],
+    [
+        -0.8294889695615747,
+        1.7626640603183463,
+        0.023624685841943577,
+    ],
+    [
+        0.03584583024378447,
+        -0.07617238926804182,
+        0.9568845240076872,
+    ],
+]);
+
+const fn to_f32(m: [[f64; 3]; 3]) -> [[f32; 3]; 3] {
+    let mut out = [[0.0; 3]; 3];
+    let mut row = 0;
+    while row < 3 {
+        let mut column = 0;
+        while column < 3 {
+            out[row][column] = m[row][column] as f32;
+            column += 1;
+        }
+        row += 1;
+    }
+    out
+}
+
+fn apply(m: &[[f32; 3]; 3], (x, y, z): (f32, f32, f32)) -> (f32, f32, f32) {
+    let row = |r: [f32; 3]| r[0] * x + r[1] * y + r[2] * z;
+    (row(m[0]), row(m[1]), row(m[2]))
+}
+
+/// [`crate::color::encoded_pixel`] with the baked matrix, computed in `f32` from Oklab
+/// on. The polar to Oklab step and the overflow check stay in `f64`, so the errors are
+/// the same.
+pub fn encoded_pixel(
+    space: ColorSpace,
+    l: f64,
+    c: f64,
+    h: f64,
+    alpha: f64,
+) -> Result<(Pixel, bool), Error> {
+    let (l, a, b) = oklch_to_oklab(l, c, h);
+    check_oklab(l, a, b)?;
+
+    let (l_, m_, s_) = apply(&OKLAB_TO_LMS, (l as f32, a as f32, b as f32));
+    let lin_srgb = apply(&LMS_TO_LIN_SRGB, (l_ * l_ * l_, m_ * m_ * m_, s_ * s_ * s_));
+    let (r, g, b) = match space {
+        // As in crate::color::neutral_linear.
+        _ if a == 0.0 && b == 0.0 => (l_ * l_ * l_, l_ * l_ * l_, l_ * l_ * l_),
+        ColorSpace::DisplayP3 => apply(&XYZ_TO_LIN_DISPLAY_P3, apply(&LIN_SRGB_TO_XYZ, lin_srgb)),
+        ColorSpace::Srgb => lin_srgb,
+    };
+    if !r.is_finite() || !g.is_finite() || !b.is_finite() {
+        return Err(Error::Conversion);
+    }
+
+    let mut clipped = false;
+    let mut encode = |value: f32| {
+        if !(0.0..=1.0).contains(&value) {
+            clipped = true;
+        }
+        f64::from(srgb_encode(value.clamp(0.0, 1.0)))
+    };
+    let pixel = Pixel {
+        r: encode(r),
+        g: encode(g),
+        b: encode(b),
+        a: alpha,
+    };
+    Ok((pixel, clipped))
+}
+
+/// [`crate::color::srgb_encode`] in `f32`.
+pub fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
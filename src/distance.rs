@@ -0,0 +1,89 @@
+use crate::color::{oklab_to_xyz, oklch_to_oklab};
+
+/// The D65 reference white in XYZ, matching the sRGB matrix in [`oklab_to_xyz`].
+const D65_WHITE_XYZ: (f64, f64, f64) = (0.95047, 1.0, 1.08883);
+
+/// deltaEOK: the Euclidean distance between two OKLCH colors in Oklab.
+pub fn delta_e_ok(first: (f64, f64, f64), second: (f64, f64, f64)) -> f64 {
+    let (l1, a1, b1) = oklch_to_oklab(first.0, first.1, first.2);
+    let (l2, a2, b2) = oklch_to_oklab(second.0, second.1, second.2);
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+/// CIELAB (L* 0..100) relative to D65. No chromatic adaptation to D50 is applied, so
+/// the values differ slightly from tools that use a D50 Lab.
+pub fn oklch_to_cielab(l: f64, c: f64, h: f64) -> (f64, f64, f64) {
+    let (l, a, b) = oklch_to_oklab(l, c, h);
+    let (x, y, z) = oklab_to_xyz(l, a, b);
+    let (xn, yn, zn) = D65_WHITE_XYZ;
+
+    let f = |t: f64| {
+        let delta: f64 = 6.0 / 29.0;
+        if t > delta.powi(3) {
+            t.cbrt()
+        } else {
+            t / (3.0 * delta * delta) + 4.0 / 29.0
+        }
+    };
+    let (fx, fy, fz) = (f(x / xn), f(y / yn), f(z / zn));
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// CIEDE2000 between two OKLCH colors, computed on their D65 CIELAB values with
+/// kL = kC = kH = 1.
+pub fn ciede2000(first: (f64, f64, f64), second: (f64, f64, f64)) -> f64 {
+    let (l1, a1, b1) = oklch_to_cielab(first.0, first.1, first.2);
+    let (l2, a2, b2) = oklch_to_cielab(second.0, second.1, second.2);
+
+    let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + 25f64.powi(7))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |a: f64, b: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let (h1, h2) = (hue(a1, b1), hue(a2, b2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 <= h1 {
+        h2 - h1 + 360.0
+    } else {
+        h2 - h1 - 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    let l_bar = (l1 + l2) / 2.0;
+    let c_bar = (c1 + c2) / 2.0;
+    let h_bar = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_bar - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar).to_radians().cos()
+        + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+    let s_l = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar;
+    let s_h = 1.0 + 0.015 * c_bar * t;
+    let delta_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (c_bar.powi(7) / (c_bar.powi(7) + 25f64.powi(7))).sqrt();
+    let r_t = -r_c * (2.0 * delta_theta).to_radians().sin();
+
+    let (l_term, c_term, h_term) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l_term.powi(2) + c_term.powi(2) + h_term.powi(2) + r_t * c_term * h_term).sqrt()
+}
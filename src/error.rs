@@ -0,0 +1,72 @@
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The input could not be parsed as a number.
+    NotANumber { name: String },
+    /// The input looks like a number written with a decimal comma.
+    CommaDecimal { name: String },
+    /// The input parsed, but to an infinity or NaN.
+    NonFinite { name: String },
+    /// A hex color has a digit count other than 3, 4, 6, or 8.
+    HexLength { digits: usize },
+    /// A hex color has a non-hex character at `position` (1-based, after any `#`).
+    HexDigit { position: usize, found: char },
+    /// The value is outside `min..=max`; `max` is infinite for lower bounds only.
+    OutOfRange {
+        name: String,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+    /// An Oklab cone response (`L`, `M`, or `S`) overflowed, so chroma is too large to
+    /// convert.
+    Overflow { component: String },
+    /// A cICP byte does not decode to the color space, transfer, or range it was meant
+    /// to record.
+    CicpMismatch { field: String, code: u8 },
+    /// The color math produced a non-finite channel.
+    Conversion,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotANumber { name } => write!(f, "{name} must be a number"),
+            Error::CommaDecimal { name } => {
+                write!(f, "{name} must use `.` as the decimal separator, not `,`")
+            }
+            Error::HexLength { digits } => {
+                write!(f, "hex color must have 3, 4, 6, or 8 digits, not {digits}")
+            }
+            Error::HexDigit { position, found } => write!(
+                f,
+                "hex color has `{found}` at position {position}, which is not a hex digit"
+            ),
+            Error::NonFinite { name } => write!(f, "{name} must be finite"),
+            Error::OutOfRange { name, min, max, .. } if max.is_infinite() => {
+                write!(f, "{name} must be >= {min}")
+            }
+            Error::OutOfRange { name, min, max, .. } => {
+                write!(f, "{name} must be between {min} and {max}")?;
+                // Plain 0..1 lightness is the one range with an alternative spelling.
+                if name == "L" && *max == 1.0 {
+                    write!(f, " (or use %)")?;
+                }
+                Ok(())
+            }
+            Error::Overflow { component } => write!(
+                f,
+                "color conversion overflowed in the Oklab {component} cone response; \
+                 chroma is too large"
+            ),
+            Error::CicpMismatch { field, code } => write!(
+                f,
+                "cICP {field} code {code} does not match the image's {field}"
+            ),
+            Error::Conversion => write!(f, "color conversion produced a non-finite value"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,53 @@
+//! A 3x5 bitmap font covering the characters used in OKLCH labels.
+
+pub const GLYPH_WIDTH: u32 = 3;
+pub const GLYPH_HEIGHT: u32 = 5;
+
+/// Columns between adjacent glyphs, before scaling.
+const SPACING: u32 = 1;
+
+/// The rows of a glyph, top first, with the leftmost pixel in bit 2. Characters
+/// without a glyph draw as a filled box.
+fn glyph(ch: char) -> [u8; 5] {
+    match ch {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        ' ' => [0; 5],
+        _ => [0b111; 5],
+    }
+}
+
+/// The width in pixels of `text` drawn at `scale`.
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    let chars = text.chars().count() as u32;
+    (chars * (GLYPH_WIDTH + SPACING)).saturating_sub(SPACING) * scale
+}
+
+/// Whether the pixel at `(x, y)`, relative to the top left of `text` drawn at `scale`,
+/// is inked.
+pub fn text_pixel(text: &str, scale: u32, x: u32, y: u32) -> bool {
+    let (x, y) = (x / scale, y / scale);
+    if y >= GLYPH_HEIGHT {
+        return false;
+    }
+    let column = x % (GLYPH_WIDTH + SPACING);
+    if column >= GLYPH_WIDTH {
+        return false;
+    }
+    let index = (x / (GLYPH_WIDTH + SPACING)) as usize;
+    text.chars()
+        .nth(index)
+        .is_some_and(|ch| glyph(ch)[y as usize] & (1 << (GLYPH_WIDTH - 1 - column)) != 0)
+}
@@ -0,0 +1,217 @@
+use crate::color::{
+    ColorSpace, P3Matrix, linear_luminance, linear_to_oklab, oklch_to_linear, oklch_to_oklab,
+    srgb_encode,
+};
+use crate::{Error, Pixel};
+
+/// The just-noticeable difference, in deltaEOK, that CSS Color 4 gamut mapping uses.
+pub const DEFAULT_JND: f64 = 0.02;
+
+/// The chroma resolution at which the binary search stops.
+const CHROMA_EPSILON: f64 = 0.0001;
+
+/// The cap, in degrees, on the hue rotation [`tradeoff_gamut_map`] may apply.
+pub const DEFAULT_MAX_HUE_SHIFT: f64 = 5.0;
+
+/// The spacing, in degrees, of the hues [`tradeoff_gamut_map`] tries.
+const HUE_STEP: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GamutMapOptions {
+    /// Clipping is accepted once the clipped color is within this deltaEOK of the
+    /// reduced-chroma color.
+    pub jnd: f64,
+    /// Finish with the spec's "local MINDE" step, returning the clip of the final
+    /// reduced-chroma color, instead of the reduced-chroma color itself.
+    pub clip_final: bool,
+}
+
+impl Default for GamutMapOptions {
+    fn default() -> Self {
+        GamutMapOptions {
+            jnd: DEFAULT_JND,
+            clip_final: false,
+        }
+    }
+}
+
+/// How far [`css_gamut_map`] or [`tradeoff_gamut_map`] had to move a color's chroma
+/// and hue.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChromaReduction {
+    /// The chroma asked for.
+    pub requested: f64,
+    /// The chroma the mapped color was built from, before any final clip.
+    pub chroma: f64,
+    /// Degrees added to the hue; always 0 from [`css_gamut_map`].
+    pub hue_shift: f64,
+}
+
+impl ChromaReduction {
+    /// How much chroma was given up; 0 when clipping alone was close enough.
+    pub fn delta(&self) -> f64 {
+        self.requested - self.chroma
+    }
+}
+
+/// Brings an OKLCH color into `space` with the CSS Color 4 algorithm: binary-search for
+/// the chroma at which clipping is no longer noticeable, keeping lightness and hue. The
+/// reduction is `None` if the color was already in gamut.
+pub fn css_gamut_map(
+    space: ColorSpace,
+    matrix: P3Matrix,
+    (l, c, h): (f64, f64, f64),
+    alpha: f64,
+    options: GamutMapOptions,
+) -> Result<(Pixel, Option<ChromaReduction>), Error> {
+    let linear = |chroma: f64| oklch_to_linear(space, matrix, l, chroma, h);
+    let origin = linear(c)?;
+    if in_gamut(origin) {
+        return Ok((encode(origin, alpha), None));
+    }
+    let reduction = |chroma: f64| {
+        Some(ChromaReduction {
+            requested: c,
+            chroma,
+            hue_shift: 0.0,
+        })
+    };
+    if l >= 1.0 {
+        return Ok((encode((1.0, 1.0, 1.0), alpha), reduction(0.0)));
+    }
+    if l <= 0.0 {
+        return Ok((encode((0.0, 0.0, 0.0), alpha), reduction(0.0)));
+    }
+
+    // deltaEOK between the color at `chroma` and its clipped version.
+    let clip_distance = |rgb: (f64, f64, f64), chroma: f64| {
+        let (r, g, b) = clip(rgb);
+        let (l2, a2, b2) = linear_to_oklab(space, matrix, r, g, b);
+        let (l1, a1, b1) = oklch_to_oklab(l, chroma, h);
+        ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+    };
+
+    if clip_distance(origin, c) < options.jnd {
+        return Ok((encode(clip(origin), alpha), reduction(c)));
+    }
+
+    let (mut min, mut max) = (0.0, c);
+    let mut min_in_gamut = true;
+    let (mut current, mut current_chroma) = (origin, c);
+    while max - min > CHROMA_EPSILON {
+        let chroma = (min + max) / 2.0;
+        current = linear(chroma)?;
+        current_chroma = chroma;
+        if min_in_gamut && in_gamut(current) {
+            min = chroma;
+            continue;
+        }
+        let distance = clip_distance(current, chroma);
+        if distance < options.jnd {
+            if options.jnd - distance < CHROMA_EPSILON {
+                break;
+            }
+            min_in_gamut = false;
+            min = chroma;
+        } else {
+            max = chroma;
+        }
+    }
+
+    if !options.clip_final {
+        // `min` is the most chroma known to be in gamut or within the JND once clipped.
+        current = linear(min)?;
+        current_chroma = min;
+    }
+    Ok((encode(clip(current), alpha), reduction(current_chroma)))
+}
+
+/// Like [`css_gamut_map`], but first tries hues up to `max_hue_shift` degrees either
+/// side and keeps whichever loses the least chroma, preferring the smallest rotation on
+/// a tie. A color already in gamut is left alone.
+pub fn tradeoff_gamut_map(
+    space: ColorSpace,
+    matrix: P3Matrix,
+    (l, c, h): (f64, f64, f64),
+    alpha: f64,
+    options: GamutMapOptions,
+    max_hue_shift: f64,
+) -> Result<(Pixel, Option<ChromaReduction>), Error> {
+    let (mut best, mut best_reduction) = css_gamut_map(space, matrix, (l, c, h), alpha, options)?;
+    let Some(mut best_chroma) = best_reduction.map(|r| r.chroma) else {
+        return Ok((best, None));
+    };
+    let steps = (max_hue_shift / HUE_STEP).ceil() as u32;
+    for step in 1..=steps {
+        let magnitude = (f64::from(step) * HUE_STEP).min(max_hue_shift);
+        for shift in [magnitude, -magnitude] {
+            let hue = (h + shift).rem_euclid(360.0);
+            let (pixel, reduction) = css_gamut_map(space, matrix, (l, c, hue), alpha, options)?;
+            let chroma = reduction.map_or(c, |r| r.chroma);
+            if chroma > best_chroma {
+                best = pixel;
+                best_chroma = chroma;
+                best_reduction = Some(ChromaReduction {
+                    requested: c,
+                    chroma,
+                    hue_shift: shift,
+                });
+            }
+        }
+        if best_chroma >= c {
+            break;
+        }
+    }
+    Ok((best, best_reduction))
+}
+
+/// Brings an OKLCH color into `space` by moving it in linear light straight toward the
+/// gray of the same relative luminance, stopping at the first edge of the gamut. Unlike
+/// clipping each channel, this keeps luminance exactly (as long as it is in 0..1 at all)
+/// and the direction away from gray, so brightness doesn't jump; saturation is what
+/// gives. The flag is set if the color had to move.
+pub fn luminance_preserving_map(
+    space: ColorSpace,
+    matrix: P3Matrix,
+    (l, c, h): (f64, f64, f64),
+    alpha: f64,
+) -> Result<(Pixel, bool), Error> {
+    let rgb = oklch_to_linear(space, matrix, l, c, h)?;
+    if in_gamut(rgb) {
+        return Ok((encode(rgb, alpha), false));
+    }
+    let y = linear_luminance(space, rgb).clamp(0.0, 1.0);
+    // The largest share of the way from gray to the color that stays inside 0..1.
+    let scale = [rgb.0, rgb.1, rgb.2]
+        .iter()
+        .map(|&value| {
+            if value > 1.0 {
+                (1.0 - y) / (value - y)
+            } else if value < 0.0 {
+                y / (y - value)
+            } else {
+                1.0
+            }
+        })
+        .fold(1.0, f64::min);
+    let toward = |value: f64| y + (value - y) * scale;
+    let mapped = clip((toward(rgb.0), toward(rgb.1), toward(rgb.2)));
+    Ok((encode(mapped, alpha), true))
+}
+
+fn in_gamut((r, g, b): (f64, f64, f64)) -> bool {
+    [r, g, b].iter().all(|value| (0.0..=1.0).contains(value))
+}
+
+fn clip((r, g, b): (f64, f64, f64)) -> (f64, f64, f64) {
+    (r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0))
+}
+
+fn encode((r, g, b): (f64, f64, f64), alpha: f64) -> Pixel {
+    Pixel {
+        r: srgb_encode(r),
+        g: srgb_encode(g),
+        b: srgb_encode(b),
+        a: alpha,
+    }
+}
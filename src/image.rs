@@ -0,0 +1,64 @@
+use crate::Pixel;
+
+/// A `width` by `height` grid of pixels. Solid images store a single pixel, so large
+/// swatches cost no more memory than a 1x1 one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Pixels {
+    Solid(Pixel),
+    Grid(Vec<Pixel>),
+}
+
+impl Image {
+    pub fn solid(width: u32, height: u32, pixel: Pixel) -> Self {
+        Image {
+            width,
+            height,
+            pixels: Pixels::Solid(pixel),
+        }
+    }
+
+    /// Builds an image by calling `pixel_at(x, y)` for every pixel in row-major order.
+    pub fn from_fn(width: u32, height: u32, mut pixel_at: impl FnMut(u32, u32) -> Pixel) -> Self {
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(pixel_at(x, y));
+            }
+        }
+        Image {
+            width,
+            height,
+            pixels: Pixels::Grid(pixels),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The single color of a solid image, or `None` if pixels may differ.
+    pub fn as_solid(&self) -> Option<Pixel> {
+        match self.pixels {
+            Pixels::Solid(pixel) => Some(pixel),
+            Pixels::Grid(_) => None,
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Pixel {
+        match &self.pixels {
+            Pixels::Solid(pixel) => *pixel,
+            Pixels::Grid(pixels) => pixels[y as usize * self.width as usize + x as usize],
+        }
+    }
+}
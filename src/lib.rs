@@ -0,0 +1,79 @@
+//! OKLCH to Display P3 conversion and the image encoders behind `oklch-pixel`.
+
+use std::io;
+
+pub mod color;
+pub mod color32;
+pub mod distance;
+mod error;
+pub mod font;
+pub mod gamut;
+mod image;
+pub mod named;
+pub mod netpbm;
+pub mod palette;
+pub mod parse;
+pub mod png;
+pub mod qoi;
+pub mod raw;
+pub mod sample;
+pub mod spectral;
+pub mod svg;
+#[cfg(feature = "swatches")]
+pub mod swatches;
+
+pub use error::Error;
+pub use image::Image;
+
+/// One pixel of encoded (non-linear) samples in 0..1, plus straight alpha.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pixel {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+    pub a: f64,
+}
+
+/// Renders a 1x1 PNG of an OKLCH color exactly as the `oklch-pixel` binary writes it by
+/// default (clipped, encoded and tagged with `options`), for callers and tests that want the bytes
+/// without touching the filesystem. Alpha, if given, makes the image RGBA.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the color can't be converted or
+/// `bit_depth` is not 8 or 16.
+pub fn render_to_bytes(
+    (l, c, h): (f64, f64, f64),
+    alpha: Option<f64>,
+    bit_depth: u8,
+    options: &png::PngOptions,
+) -> io::Result<Vec<u8>> {
+    if bit_depth != 8 && bit_depth != 16 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("bit depth must be 8 or 16, not {bit_depth}"),
+        ));
+    }
+    let (mut pixel, _) = color::encoded_pixel(
+        options.color_space,
+        color::P3Matrix::Baked,
+        l,
+        c,
+        h,
+        alpha.unwrap_or(1.0),
+    )
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let encode = |value: f64| {
+        sample::apply_range(options.transfer.from_srgb(value), bit_depth, options.range)
+    };
+    pixel.r = encode(pixel.r);
+    pixel.g = encode(pixel.g);
+    pixel.b = encode(pixel.b);
+    let mut bytes = Vec::new();
+    png::write_png(
+        &mut bytes,
+        &Image::solid(1, 1, pixel),
+        bit_depth,
+        alpha.is_some(),
+        options,
+    )?;
+    Ok(bytes)
+}
@@ -1,29 +1,62 @@
-use std::fs::File;
-use std::io::{self, Write};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::hint;
+use std::io::{self, BufRead, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
 
-use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use clap_complete::{generate, Shell};
-use crc32fast::Hasher;
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
 
-const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+use oklch_pixel::color::{
+    self, ColorSpace, CustomTransfer, P3Matrix, Transfer, encoded_pixel, normalize_hue,
+    round_to_step, snap_alpha, snap_hue, snap_neutral_extremes, srgb_bytes, srgb_decode, srgb_hex,
+};
+use oklch_pixel::color32;
+use oklch_pixel::distance;
+use oklch_pixel::font;
+use oklch_pixel::gamut::{
+    self, ChromaReduction, GamutMapOptions, css_gamut_map, luminance_preserving_map,
+    tradeoff_gamut_map,
+};
+use oklch_pixel::named::nearest_named_color;
+use oklch_pixel::netpbm::{PamTupleType, write_netpbm, write_plain_ppm};
+use oklch_pixel::palette::{write_apple_clr, write_ms_pal};
+use oklch_pixel::parse::{
+    HUE_NAMES, HexAlphaPosition, InputScale, parse_color, parse_color_in, parse_f64, parse_hex,
+    parse_hue, parse_l, parse_non_negative,
+};
+use oklch_pixel::png::{self, DecodedPng, PngOptions, write_png};
+use oklch_pixel::qoi::write_qoi;
+use oklch_pixel::raw::write_raw;
+use oklch_pixel::sample;
+use oklch_pixel::spectral;
+use oklch_pixel::svg::{css_display_p3, format_css_number, write_svg};
+use oklch_pixel::{Image, Pixel};
 
-const CICP_PRIMARIES_DISPLAY_P3: u8 = 12;
-const CICP_TRANSFER_SRGB: u8 = 13;
-const CICP_MATRIX_IDENTITY: u8 = 0;
-const CICP_FULL_RANGE: u8 = 1;
+const CONFIG_FILE_NAME: &str = "oklch-pixel.toml";
 
-const AFTER_HELP: &str =
-    "Default output file: oklch(l c h).png or oklch(l c h \u{2215} a).png (L normalized to 0..1).";
+/// The default `--max-pixels`: 64 megapixels, about 256 MB of 8-bit RGBA.
+const DEFAULT_MAX_PIXELS: u64 = 64_000_000;
+
+const AFTER_HELP: &str = "Default output file: oklch(l c h).png or oklch(l c h \u{2215} a).png \
+     (L normalized to 0..1; the extension follows --output-format).
+
+Flag defaults can be set in oklch-pixel.toml, searched for in the current directory and then in \
+     $XDG_CONFIG_HOME/oklch-pixel/ (or ~/.config/oklch-pixel/). Only the first file found is used; \
+     flags given on the command line always win.";
 
 #[derive(Parser, Debug)]
 #[command(
     name = "oklch-pixel",
     version,
-    about = "Generate a 1x1 PNG in Display P3 from OKLCH.",
+    about = "Generate a solid-color image (a 1x1 PNG by default) in Display P3 from OKLCH.",
     after_help = AFTER_HELP,
     subcommand_negates_reqs = true,
     args_conflicts_with_subcommands = true,
@@ -33,370 +66,5555 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    #[arg(
-        long,
-        value_enum,
-        default_value_t = BitDepth::Eight,
-        help = "Output bit depth"
-    )]
-    bit_depth: BitDepth,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = BitDepth::Eight,
+        help = "Output bit depth"
+    )]
+    bit_depth: BitDepth,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Png,
+        help = "Output file format"
+    )]
+    output_format: OutputFormat,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = InputGamut::Unbounded,
+        help = "Treat out-of-gamut colors as clipped (unbounded) or as errors (p3)"
+    )]
+    assume_input_gamut: InputGamut,
+
+    #[arg(
+        long,
+        help = "Derive the XYZ to Display P3 matrix from the P3 primaries and D65 white \
+                instead of using the rounded built-in coefficients"
+    )]
+    exact_matrix: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Precision::F64,
+        help = "Floating-point precision of the color conversion; f32 is faster and stays \
+                within a fraction of an 8-bit step"
+    )]
+    precision: Precision,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = GamutMap::Clip,
+        help = "How to bring out-of-gamut colors in: clip each channel, reduce chroma \
+                with the CSS Color 4 algorithm, also allow a small hue rotation to keep \
+                more chroma, or move toward gray at constant luminance"
+    )]
+    gamut_map: GamutMap,
+
+    #[arg(
+        long,
+        value_name = "DEGREES",
+        value_parser = parse_max_hue_shift,
+        help = "Largest hue rotation --gamut-map chroma-hue-tradeoff may apply [default: 5]"
+    )]
+    max_hue_shift: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "deltaEOK",
+        value_parser = parse_gamut_map_jnd,
+        help = "Just-noticeable difference for --gamut-map css [default: 0.02]"
+    )]
+    gamut_map_jnd: Option<f64>,
+
+    #[arg(
+        long,
+        help = "Finish --gamut-map css by clipping the last reduced-chroma color (local MINDE)"
+    )]
+    gamut_map_clip_final: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = TransferArg::Srgb,
+        help = "Store samples with the sRGB curve, as linear light (both recorded in cICP), \
+                or with a custom curve from --gamma, --linear-threshold, and --linear-slope"
+    )]
+    transfer: TransferArg,
+
+    #[arg(
+        long,
+        value_name = "g",
+        value_parser = parse_gamma,
+        help = "Exponent of the --transfer custom power piece [default: 2.4]"
+    )]
+    gamma: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "t",
+        value_parser = parse_linear_threshold,
+        help = "Linear-light value where --transfer custom switches from the linear piece to \
+                the power piece [default: 0.0031308]"
+    )]
+    linear_threshold: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "s",
+        value_parser = parse_linear_slope,
+        help = "Slope of the --transfer custom linear piece [default: 12.92]"
+    )]
+    linear_slope: Option<f64>,
+
+    #[arg(
+        long,
+        help = "Allow --transfer linear with an sRGB chunk, which declares the sRGB curve"
+    )]
+    allow_transfer_mismatch: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = RangeArg::Full,
+        help = "Use the full sample range, or the video range (16..235 at 8 bits)"
+    )]
+    range: RangeArg,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "matrix",
+        help = "Write Y'CbCr samples with these coefficients instead of RGB (raw only)"
+    )]
+    output_matrix: Option<OutputMatrix>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "order",
+        help = "Sample order for raw output (png, ppm, and pam always use RGBA)"
+    )]
+    channel_order: Option<ChannelOrder>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "order",
+        help = "Byte order of 16-bit samples in raw output \
+                (png, ppm, and pam are always big-endian)"
+    )]
+    raw_endian: Option<RawEndian>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "type",
+        help = "PAM TUPLTYPE to write [default: rgb, or rgb-alpha when alpha is given]"
+    )]
+    pam_tuple_type: Option<PamTupleArg>,
+
+    #[arg(
+        long,
+        help = "Embed an eXIf chunk with orientation and color-space tags (PNG only)"
+    )]
+    exif: bool,
+
+    #[arg(
+        long,
+        help = "Write an sRGB-tagged PNG when the color fits in sRGB, Display P3 otherwise"
+    )]
+    auto_space: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "space",
+        conflicts_with = "auto_space",
+        help = "Gamut to clip or map into before encoding; srgb keeps Display P3 output \
+                within sRGB [default: the output space]"
+    )]
+    gamut_target: Option<GamutTarget>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "intent",
+        help = "Rendering intent for the sRGB chunk written when --auto-space picks sRGB \
+                and in the --sidecar-srgb copy [default: relative]"
+    )]
+    rendering_intent: Option<RenderingIntent>,
+
+    #[arg(
+        long,
+        help = "Check that the cICP bytes decode back to the color space, transfer, and range \
+                being written, and fail if not"
+    )]
+    validate_cicp: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["exif", "auto_space"],
+        help = "Write only the critical PNG chunks; the image is untagged and \
+                readers must assume Display P3"
+    )]
+    minimal: bool,
+
+    #[arg(
+        long,
+        help = "Write the output file directly instead of through a temp file that is \
+                renamed into place"
+    )]
+    no_atomic: bool,
+
+    #[arg(
+        long,
+        help = "Fail when a setting doesn't fit the output format, such as --bit-depth 16 \
+                with qoi, instead of adjusting it with a warning"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        help = "Format the default file name's numbers with a fixed six-decimal algorithm \
+                instead of Rust's shortest round-trip form"
+    )]
+    deterministic_filenames: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "output_file",
+        help = "End the default file name with the color's 8-bit sRGB hex code, as in \
+                oklch(0.7 0.1 30)-#d58679.png"
+    )]
+    name_with_hex: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "format",
+        help = "Also write the color (as 8-bit sRGB) to a palette file next to the output"
+    )]
+    emit_palette: Option<PaletteFormat>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "kind",
+        help = "Print the color instead of writing a file (all: every known color space; \
+                rust: a constant array of the samples; ppm-ascii: the image as a plain P3 PPM; \
+                terminal-truecolor-sample: sample text in the color against black and white; \
+                roundtrip: write the PNG as usual, then print the OKLCH read back from it; ts: a \
+                TypeScript/JavaScript constant with OKLCH, Display P3, and sRGB hex strings)"
+    )]
+    emit: Option<Emit>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["output_file", "output_dir", "emit_palette"],
+        help = "Print the image as a base64 data: URI instead of writing a file"
+    )]
+    emit_data_uri: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["auto_space", "minimal", "append_to", "emit_data_uri"],
+        help = "Also write an sRGB copy as <name>.srgb.png and a <name>.json manifest of both \
+                files and whether each was clipped"
+    )]
+    sidecar_srgb: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["sidecar_srgb", "emit_data_uri"],
+        help = "Also write a <name>.json manifest of the source color, output settings, \
+                clip status, size, and a CRC-32 of the file"
+    )]
+    manifest: bool,
+
+    #[arg(
+        long,
+        value_name = "path",
+        help = "Explicit output file path, or - for stdout; a known extension picks the \
+                format unless --output-format is given. {hex} and {hex8} become the color's \
+                8-bit sRGB hex code"
+    )]
+    output_file: Option<String>,
+
+    #[arg(
+        long,
+        help = "Write binary output to stdout even when it is a terminal"
+    )]
+    force_tty: bool,
+
+    #[arg(
+        long,
+        value_name = "path",
+        conflicts_with = "output_file",
+        help = "Write the default-named file into this directory, creating it if needed"
+    )]
+    output_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "sheet.png",
+        conflicts_with_all = ["output_file", "output_dir", "emit_data_uri"],
+        help = "Add the image as new columns on the right of this PNG, creating it if missing"
+    )]
+    append_to: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "px",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Image width in pixels"
+    )]
+    width: u32,
+
+    #[arg(
+        long,
+        value_name = "px",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Image height in pixels"
+    )]
+    height: u32,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_MAX_PIXELS,
+        help = "Refuse to write raster images with more than N pixels"
+    )]
+    max_pixels: u64,
+
+    #[arg(
+        long,
+        value_name = "n",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Snap the hue to the nearest of n evenly spaced hues"
+    )]
+    hue_steps: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "step",
+        value_parser = parse_round_step,
+        conflicts_with = "hue_steps",
+        help = "Round the hue to a multiple of step degrees"
+    )]
+    round_hue_to: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "step",
+        value_parser = parse_round_step,
+        help = "Round the chroma to a multiple of step"
+    )]
+    round_chroma_to: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "C",
+        value_parser = parse_chroma_clamp_max,
+        help = "Cap the chroma given (here and in --tile-color) at C, with a warning, to \
+                catch typos like 15 for 0.15"
+    )]
+    chroma_clamp_max: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "C",
+        default_value_t = color::DEFAULT_NEUTRAL_EPSILON,
+        value_parser = parse_neutral_epsilon,
+        help = "Treat chroma below this (here and in --tile-color) as exactly 0, so the color \
+                comes out an exact gray; 0 turns this off"
+    )]
+    neutral_epsilon: f64,
+
+    #[arg(
+        long,
+        help = "Snap achromatic colors (C <= 0.0001) within 0.0001 of L = 0 or 1 to exact \
+                black or white"
+    )]
+    snap_neutrals: bool,
+
+    #[arg(
+        long,
+        help = "Read L (here and in --tile-color) as toe-adjusted reference lightness Lr, as \
+                some color pickers show it, and convert it back to Oklab L"
+    )]
+    toe: bool,
+
+    #[arg(
+        long,
+        value_name = "eps",
+        value_parser = parse_alpha_threshold,
+        help = "Snap alpha below eps to 0 and above 1 - eps to 1"
+    )]
+    alpha_threshold: Option<f64>,
+
+    #[arg(
+        long,
+        help = "Write opaque RGB samples even when alpha is given; the alpha still appears in \
+                the default file name"
+    )]
+    no_alpha: bool,
+
+    #[arg(
+        long,
+        value_name = "MASK",
+        conflicts_with = "no_alpha",
+        help = "Take alpha from the luminance of this PNG, overriding A; a mask larger than \
+                1x1 must match the output size"
+    )]
+    alpha_from: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "px",
+        value_parser = parse_corner_radius,
+        conflicts_with = "no_alpha",
+        help = "Round the image's corners to this radius, with antialiased transparent \
+                corners (writes RGBA)"
+    )]
+    corner_radius: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "L C H",
+        help = "Composite the color over this opaque background (mixed in linear light) and \
+                write RGB"
+    )]
+    background: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "L C H",
+        conflicts_with = "minimal",
+        help = "Suggest this opaque backdrop to viewers in a PNG bKGD chunk; unlike \
+                --background, the image itself is unchanged"
+    )]
+    background_chunk: Option<String>,
+
+    #[arg(
+        long,
+        help = "Store color premultiplied by alpha (in linear light, after any --background)"
+    )]
+    premultiply: bool,
+
+    #[arg(
+        long,
+        value_name = "columns",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Lay the color and each --tile-color out in a grid this many cells wide; \
+                each cell is --width by --height, and colors repeat to fill the last row"
+    )]
+    tile: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "L C H [A]",
+        requires = "tile",
+        help = "Another color for --tile (repeatable)"
+    )]
+    tile_color: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "WxH",
+        value_parser = parse_preview_size,
+        help = "Print a WxH-character swatch of the color (sRGB) to the terminal"
+    )]
+    preview_size: Option<(u16, u16)>,
+
+    #[arg(
+        long,
+        conflicts_with = "emit_data_uri",
+        help = "Also print the nearest CSS named color and its deltaEOK distance"
+    )]
+    describe: bool,
+
+    #[arg(
+        long,
+        help = "Warn when a color with chroma comes out as pure black or pure white"
+    )]
+    warn_degenerate: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["emit", "emit_data_uri", "describe"],
+        help = "Print each step of the Display P3 conversion with its formula and values, and \
+                exit"
+    )]
+    explain: bool,
+
+    #[arg(
+        long,
+        value_name = "path",
+        help = "Append a CSV row with the per-channel Display P3 overshoot of each clipped color"
+    )]
+    clip_report_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        exclusive = true,
+        help = "Print the hue names accepted for H and exit"
+    )]
+    list_hue_names: bool,
+
+    #[arg(
+        long,
+        exclusive = true,
+        help = "Print the version, a fingerprint of the conversion constants and the PNG \
+                compression settings, and exit"
+    )]
+    version_full: bool,
+
+    #[arg(
+        long,
+        exclusive = true,
+        hide = true,
+        help = "Print a JSON Schema for the JSON this tool emits and exit"
+    )]
+    json_schema: bool,
+
+    #[cfg(feature = "swatches")]
+    #[arg(
+        long,
+        value_name = "path",
+        exclusive = true,
+        help = "Write a Display P3 PNG for each swatch in a Procreate .swatches file and exit"
+    )]
+    import_swatches: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "path",
+        conflicts_with = "l",
+        help = "Read the color as `L C H [A]` from the first non-comment line of a file"
+    )]
+    color_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "color_file",
+        help = "Use the first color line of --color-file and ignore any after it"
+    )]
+    first_line_only: bool,
+
+    #[arg(
+        long,
+        value_name = "VARNAME",
+        conflicts_with_all = ["l", "color_file"],
+        help = "Read the color as `L C H [A]` from an environment variable"
+    )]
+    color_env: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "HEX",
+        conflicts_with_all = ["l", "color_file", "color_env"],
+        help = "Take the color as an sRGB hex code (#rgb, #rgba, #rrggbb, or #rrggbbaa) \
+                instead of L C H"
+    )]
+    hex: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        requires = "hex",
+        default_value_t = HexAlphaArg::Last,
+        help = "Whether 4- and 8-digit --hex codes put alpha first (ARGB) or last (RGBA, as in CSS)"
+    )]
+    hex_alpha_position: HexAlphaArg,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = InputScaleArg::Css,
+        help = "Scale of L and C: css (L 0..1, C as in oklch()) or percent100 (both 0..100, \
+                C 100 = 0.4)"
+    )]
+    input_scale: InputScaleArg,
+
+    #[arg(
+        long,
+        value_name = "Y",
+        value_parser = parse_target_luminance,
+        conflicts_with_all = ["hex", "toe"],
+        help = "Replace L with the lightness at which C and H reach this relative luminance \
+                (0..1) in Display P3"
+    )]
+    target_luminance: Option<f64>,
+
+    #[arg(
+        long,
+        conflicts_with = "target_luminance",
+        help = "Snap each color to the nearest of the 216 web-safe sRGB colors (channels that \
+                are multiples of 0x33) and report the deltaEOK this moved it"
+    )]
+    snap_to_web_safe: bool,
+
+    #[arg(
+        value_name = "L",
+        required_unless_present_any = ["color_file", "color_env", "hex"],
+        help = "Lightness: 0..1 or percent (e.g. 62.5%)."
+    )]
+    l: Option<String>,
+
+    #[arg(
+        value_name = "C",
+        required_unless_present_any = ["color_file", "color_env", "hex"],
+        help = "Chroma (≥ 0)."
+    )]
+    c: Option<String>,
+
+    #[arg(
+        value_name = "H",
+        required_unless_present_any = ["color_file", "color_env", "hex"],
+        help = "Hue in degrees, or a name such as red or blue (see --list-hue-names)."
+    )]
+    h: Option<String>,
+
+    #[arg(value_name = "A", help = "Alpha 0..1 (optional). If provided, output is RGBA.")]
+    a: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    #[command(about = "Generate shell completions")]
+    GenerateCompletions {
+        #[arg(value_enum, value_name = "shell")]
+        shell: CompletionShell,
+    },
+    #[command(
+        about = "Read OKLCH colors from stdin and print their conversions",
+        long_about = "Read OKLCH colors (L C H [A]) from stdin, one per line, and print the sRGB hex, \
+                      Display P3 values, clip status, and an ANSI preview of each. \
+                      `save <path>` writes the last color as a PNG; `quit` exits."
+    )]
+    Repl {
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = BitDepth::Eight,
+            help = "Bit depth for PNGs written with `save`"
+        )]
+        bit_depth: BitDepth,
+
+        #[arg(
+            long,
+            value_name = "WxH",
+            value_parser = parse_preview_size,
+            default_value = "8x1",
+            help = "Size of each color's swatch, in characters"
+        )]
+        preview_size: (u16, u16),
+    },
+    #[command(
+        about = "Write a PNG for each OKLCH color read from stdin",
+        long_about = "Read OKLCH colors (L C H [A]) from stdin, one per line, and write a \
+                      Display P3 PNG for each under its default name. A line may end with a \
+                      comma and a name, which --emit css-vars uses for the property. Blank lines \
+                      and lines starting with # are skipped. A bad line is reported and skipped; the exit \
+                      status is nonzero if any line failed."
+    )]
+    Batch {
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = BitDepth::Eight,
+            help = "Output bit depth"
+        )]
+        bit_depth: BitDepth,
+
+        #[arg(
+            long,
+            value_enum,
+            value_name = "kind",
+            help = "Also print a result for each color to stdout (jsonl: one JSON object per \
+                    line; css-vars: a stylesheet of custom properties, one per color)"
+        )]
+        emit: Option<BatchEmit>,
+
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u16).range(1..),
+            help = "Convert and write this many colors in parallel; results stay in input order"
+        )]
+        jobs: u16,
+
+        #[arg(
+            long,
+            help = "Also write a <name>.json manifest next to each PNG, as the main command's \
+                    --manifest does"
+        )]
+        manifest: bool,
+
+        #[arg(
+            long,
+            value_name = "path",
+            help = "Append a CSV row with the per-channel Display P3 overshoot of each clipped \
+                    color, in input order, as the main command's --clip-report-file does"
+        )]
+        clip_report_file: Option<PathBuf>,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = OnCollision::Suffix,
+            help = "What to do when two different lines would get the same file name: fail the \
+                    later line, add a hash of its input to its name, or let it overwrite"
+        )]
+        on_collision: OnCollision,
+    },
+    #[command(
+        about = "Write a 1x1 PNG with literal sample values, skipping the color math",
+        long_about = "Write a 1x1 PNG whose samples are exactly the given values, with no OKLCH \
+                      conversion. Values are in 0..MAX (0..1 by default; --max 255 or 65535 for \
+                      integer samples). The PNG is untagged unless --cicp is given."
+    )]
+    RawPixel {
+        #[arg(long, value_name = "value", allow_negative_numbers = true)]
+        r: f64,
+
+        #[arg(long, value_name = "value", allow_negative_numbers = true)]
+        g: f64,
+
+        #[arg(long, value_name = "value", allow_negative_numbers = true)]
+        b: f64,
+
+        #[arg(
+            long,
+            value_name = "value",
+            allow_negative_numbers = true,
+            help = "Alpha; if given, output is RGBA"
+        )]
+        a: Option<f64>,
+
+        #[arg(
+            long,
+            value_name = "MAX",
+            default_value_t = 1.0,
+            help = "The value that means full intensity"
+        )]
+        max: f64,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = BitDepth::Eight,
+            help = "Output bit depth"
+        )]
+        bit_depth: BitDepth,
+
+        #[arg(long, help = "Tag the PNG as Display P3 with a cICP chunk")]
+        cicp: bool,
+
+        #[arg(
+            long,
+            value_name = "path",
+            default_value = "raw-pixel.png",
+            help = "Output file path"
+        )]
+        output_file: PathBuf,
+    },
+    #[command(
+        about = "Write a Display P3 PNG of an sRGB color",
+        long_about = "Write a 1x1 Display P3 PNG of an sRGB color given as three components and an \
+                      optional alpha. Components are 0..1 or 0..255; by default the range is \
+                      0..255 if any of R, G, or B is over 1, and 0..1 otherwise."
+    )]
+    FromRgb {
+        #[arg(value_name = "R")]
+        r: f64,
+
+        #[arg(value_name = "G")]
+        g: f64,
+
+        #[arg(value_name = "B")]
+        b: f64,
+
+        #[arg(
+            value_name = "A",
+            help = "Alpha, in the same range as R, G, and B; if given, output is RGBA"
+        )]
+        a: Option<f64>,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = RgbRange::Auto,
+            help = "How to read the components: 0..1 (unit), 0..255 (byte), or guess (auto)"
+        )]
+        rgb_range: RgbRange,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = BitDepth::Eight,
+            help = "Output bit depth"
+        )]
+        bit_depth: BitDepth,
+
+        #[arg(
+            long,
+            value_name = "path",
+            default_value = "from-rgb.png",
+            help = "Output file path"
+        )]
+        output_file: PathBuf,
+    },
+    #[command(
+        about = "Write a Display P3 PNG of an sRGB hsl color",
+        long_about = "Write a 1x1 Display P3 PNG of an sRGB hsl color: hue in degrees, then \
+                      saturation and lightness in 0..1, or 0..100 with --input-scale percent100, \
+                      and an optional alpha in the same range. The color is converted to sRGB \
+                      and re-encoded as Display P3, as from-rgb does."
+    )]
+    Hsl {
+        #[arg(value_name = "H", allow_negative_numbers = true)]
+        h: f64,
+
+        #[arg(value_name = "S")]
+        s: f64,
+
+        #[arg(value_name = "L")]
+        lightness: f64,
+
+        #[arg(
+            value_name = "A",
+            help = "Alpha, in the same range as S and L; if given, output is RGBA"
+        )]
+        a: Option<f64>,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = InputScaleArg::Css,
+            help = "How to read S, L, and A: 0..1 (css) or 0..100 (percent100)"
+        )]
+        input_scale: InputScaleArg,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = BitDepth::Eight,
+            help = "Output bit depth"
+        )]
+        bit_depth: BitDepth,
+
+        #[arg(
+            long,
+            value_name = "path",
+            default_value = "from-hsl.png",
+            help = "Output file path"
+        )]
+        output_file: PathBuf,
+    },
+    #[command(
+        about = "Write a Display P3 PNG of an sRGB hsv color",
+        long_about = "Write a 1x1 Display P3 PNG of an sRGB hsv color: hue in degrees, then \
+                      saturation and value in 0..1, or 0..100 with --input-scale percent100, \
+                      and an optional alpha in the same range. The color is converted to sRGB \
+                      and re-encoded as Display P3, as from-rgb does."
+    )]
+    Hsv {
+        #[arg(value_name = "H", allow_negative_numbers = true)]
+        h: f64,
+
+        #[arg(value_name = "S")]
+        s: f64,
+
+        #[arg(value_name = "V")]
+        value: f64,
+
+        #[arg(
+            value_name = "A",
+            help = "Alpha, in the same range as S and V; if given, output is RGBA"
+        )]
+        a: Option<f64>,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = InputScaleArg::Css,
+            help = "How to read S, V, and A: 0..1 (css) or 0..100 (percent100)"
+        )]
+        input_scale: InputScaleArg,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = BitDepth::Eight,
+            help = "Output bit depth"
+        )]
+        bit_depth: BitDepth,
+
+        #[arg(
+            long,
+            value_name = "path",
+            default_value = "from-hsv.png",
+            help = "Output file path"
+        )]
+        output_file: PathBuf,
+    },
+    #[command(
+        about = "Write a hue wheel at one lightness",
+        long_about = "Write a square RGBA Display P3 PNG of an OKLCH hue wheel: hue is the angle \
+                      counterclockwise from the right, and chroma grows from 0 at the center to \
+                      --max-chroma at the edge. Pixels outside the circle are transparent. \
+                      Prints the fraction of the wheel that is inside the gamut."
+    )]
+    Wheel {
+        #[arg(
+            long,
+            default_value_t = 256,
+            value_parser = clap::value_parser!(u32).range(1..=8192),
+            help = "Width and height in pixels"
+        )]
+        size: u32,
+
+        #[arg(
+            long,
+            value_name = "L",
+            default_value = "0.7",
+            help = "Lightness (0..1 or percent)"
+        )]
+        lightness: String,
+
+        #[arg(
+            long,
+            value_name = "C",
+            default_value = "0.37",
+            help = "Chroma at the edge of the wheel"
+        )]
+        max_chroma: String,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = WheelOutOfGamut::Clip,
+            help = "Clip out-of-gamut pixels, or mark them by drawing them gray"
+        )]
+        out_of_gamut: WheelOutOfGamut,
+
+        #[arg(
+            long,
+            value_name = "L C H [A]",
+            conflicts_with = "out_of_gamut",
+            help = "Draw out-of-gamut pixels in this color instead, to make the edge obvious"
+        )]
+        oog_marker: Option<String>,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = BitDepth::Eight,
+            help = "Output bit depth"
+        )]
+        bit_depth: BitDepth,
+
+        #[arg(
+            long,
+            value_name = "path",
+            default_value = "oklch-wheel.png",
+            help = "Output file path"
+        )]
+        output_file: PathBuf,
+    },
+    #[command(
+        about = "Show where Display P3 goes beyond sRGB at one hue",
+        long_about = "Write an RGBA Display P3 PNG of the lightness-chroma plane at one hue: \
+                      lightness runs from 1 at the top to 0 at the bottom, and chroma from 0 on \
+                      the left to --max-chroma on the right. Colors inside sRGB are drawn gray, \
+                      colors only Display P3 can show are drawn in their own color, and colors \
+                      outside both are transparent. Prints the share of each."
+    )]
+    GamutDiff {
+        /// Hue in degrees (or a hue name)
+        hue: String,
+
+        #[arg(
+            long,
+            default_value_t = 256,
+            value_parser = clap::value_parser!(u32).range(1..=8192),
+            help = "Width in pixels (the chroma axis)"
+        )]
+        width: u32,
+
+        #[arg(
+            long,
+            default_value_t = 256,
+            value_parser = clap::value_parser!(u32).range(1..=8192),
+            help = "Height in pixels (the lightness axis)"
+        )]
+        height: u32,
+
+        #[arg(
+            long,
+            value_name = "C",
+            default_value = "0.37",
+            help = "Chroma at the right edge"
+        )]
+        max_chroma: String,
+
+        #[arg(
+            long,
+            value_name = "L C H [A]",
+            help = "Draw colors outside both gamuts in this color instead of transparent"
+        )]
+        oog_marker: Option<String>,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = BitDepth::Eight,
+            help = "Output bit depth"
+        )]
+        bit_depth: BitDepth,
+
+        #[arg(
+            long,
+            value_name = "path",
+            default_value = "oklch-gamut-diff.png",
+            help = "Output file path"
+        )]
+        output_file: PathBuf,
+    },
+    #[command(
+        about = "Print a terminal map of the gamut at one hue",
+        long_about = "Print the lightness-chroma plane at one hue as a grid of characters: \
+                      lightness runs from 1 at the top to 0 at the bottom, and chroma from 0 on \
+                      the left to --max-chroma on the right. Cells inside the gamut are filled \
+                      with their color as sRGB (or # when NO_COLOR is set), cells outside are \
+                      dots, and \
+                      the color given by --lightness and --chroma is marked @. The grid fits \
+                      $COLUMNS and $LINES when the shell exports them."
+    )]
+    GamutExplorer {
+        /// Hue in degrees (or a hue name)
+        hue: String,
+
+        #[arg(
+            long,
+            value_name = "L",
+            requires = "chroma",
+            help = "Lightness of the color to mark: 0..1 or percent"
+        )]
+        lightness: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "C",
+            requires = "lightness",
+            help = "Chroma of the color to mark"
+        )]
+        chroma: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "C",
+            default_value = "0.37",
+            help = "Chroma at the right edge"
+        )]
+        max_chroma: String,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = GamutTarget::DisplayP3,
+            help = "Gamut to map"
+        )]
+        gamut: GamutTarget,
+    },
+    #[command(
+        about = "Write a PNG of the color of light at one wavelength",
+        long_about = "Write a Display P3 PNG of monochromatic light at a wavelength from 380 to \
+                      700 nm, found through the CIE 1931 color matching functions and shown at \
+                      --lightness. Almost every spectral color is outside Display P3, so its \
+                      chroma is reduced as --gamut-map css does, and how far it had to move is \
+                      printed."
+    )]
+    Wavelength {
+        /// Wavelength in nanometers
+        nm: String,
+
+        #[arg(
+            long,
+            value_name = "L",
+            default_value = "0.7",
+            help = "Lightness to show the light at: 0..1 or percent"
+        )]
+        lightness: String,
+
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u32).range(1..=8192),
+            help = "Width in pixels"
+        )]
+        width: u32,
+
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u32).range(1..=8192),
+            help = "Height in pixels"
+        )]
+        height: u32,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = BitDepth::Eight,
+            help = "Output bit depth"
+        )]
+        bit_depth: BitDepth,
+
+        #[arg(
+            long,
+            value_name = "path",
+            default_value = "oklch-wavelength.png",
+            help = "Output file path"
+        )]
+        output_file: PathBuf,
+    },
+    #[command(
+        about = "Write a labeled grid of swatches for several colors",
+        long_about = "Write a Display P3 PNG contact sheet: one square swatch per color, each with \
+                      its OKLCH coordinates printed beneath it, laid out left to right in rows of \
+                      --columns. Colors are `L C H [A]` arguments, lines of --color-file, or both."
+    )]
+    ContactSheet {
+        #[arg(value_name = "color", help = "A color as one `L C H [A]` argument")]
+        colors: Vec<String>,
+
+        #[arg(
+            long,
+            value_name = "path",
+            help = "Read more colors from a file, one `L C H [A]` per line"
+        )]
+        color_file: Option<PathBuf>,
+
+        #[arg(
+            long,
+            default_value_t = 4,
+            value_parser = clap::value_parser!(u32).range(1..=256),
+            help = "Swatches per row"
+        )]
+        columns: u32,
+
+        #[arg(
+            long,
+            default_value_t = 128,
+            value_parser = clap::value_parser!(u32).range(16..=2048),
+            help = "Width and height of each swatch in pixels"
+        )]
+        cell_size: u32,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = BitDepth::Eight,
+            help = "Output bit depth"
+        )]
+        bit_depth: BitDepth,
+
+        #[arg(
+            long,
+            value_name = "path",
+            default_value = "contact-sheet.png",
+            help = "Output file path"
+        )]
+        output_file: PathBuf,
+    },
+    #[command(
+        about = "Print the perceptual distance between two OKLCH colors",
+        long_about = "Print the perceptual distance between two OKLCH colors, each given as one \
+                      `L C H [A]` argument. Alpha is ignored. The default metric is deltaEOK, the \
+                      Euclidean distance in Oklab; ciede2000 works on D65 CIELAB."
+    )]
+    Distance {
+        #[arg(value_name = "color1")]
+        first: String,
+
+        #[arg(value_name = "color2")]
+        second: String,
+
+        #[arg(long, value_enum, default_value_t = Metric::DeltaEOk, help = "Distance metric")]
+        metric: Metric,
+    },
+    #[command(
+        about = "Write light and dark Display P3 PNGs of one color",
+        long_about = "Write two PNGs of one OKLCH color: the color as given, suffixed -light, and a \
+                      dark-mode variant with a different lightness, suffixed -dark. Give the dark \
+                      variant's lightness with --dark-lightness or as an offset with \
+                      --lightness-delta."
+    )]
+    ThemePair {
+        #[arg(value_name = "L")]
+        l: String,
+
+        #[arg(value_name = "C")]
+        c: String,
+
+        #[arg(value_name = "H")]
+        h: String,
+
+        #[arg(value_name = "A")]
+        a: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "L",
+            required_unless_present = "lightness_delta",
+            conflicts_with = "lightness_delta",
+            help = "Lightness of the dark variant (0..1 or percent)"
+        )]
+        dark_lightness: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "delta",
+            allow_negative_numbers = true,
+            help = "Subtract this from L for the dark variant"
+        )]
+        lightness_delta: Option<f64>,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value_t = BitDepth::Eight,
+            help = "Output bit depth"
+        )]
+        bit_depth: BitDepth,
+    },
+    #[command(
+        about = "List the supported color spaces, transfer functions, formats, and bit depths"
+    )]
+    Capabilities {
+        #[arg(long, help = "Print JSON instead of plain text")]
+        json: bool,
+    },
+    #[command(
+        hide = true,
+        about = "Time the core conversion over pseudo-random colors",
+        long_about = "Run oklch_to_display_p3_linear and srgb_encode over <iterations> \
+                      pseudo-random in-range OKLCH colors (a fixed seed, so runs are comparable) \
+                      and print the time per conversion and the conversions per second."
+    )]
+    Bench {
+        #[arg(
+            value_name = "iterations",
+            default_value_t = 1_000_000,
+            value_parser = clap::value_parser!(u64).range(1..)
+        )]
+        iterations: u64,
+
+        #[arg(long, value_enum, default_value_t = Precision::F64, help = "Precision to time")]
+        precision: Precision,
+    },
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "oklch-pixel")]
+struct SubcommandCli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum BitDepth {
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
+}
+
+impl BitDepth {
+    fn as_u8(self) -> u8 {
+        match self {
+            BitDepth::Eight => 8,
+            BitDepth::Sixteen => 16,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum OutputFormat {
+    #[value(name = "png")]
+    Png,
+    #[value(name = "svg")]
+    Svg,
+    #[value(name = "raw")]
+    Raw,
+    #[value(name = "ppm")]
+    Ppm,
+    #[value(name = "pam")]
+    Pam,
+    #[value(name = "qoi")]
+    Qoi,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Svg => "svg",
+            OutputFormat::Raw => "raw",
+            OutputFormat::Ppm => "ppm",
+            OutputFormat::Pam => "pam",
+            OutputFormat::Qoi => "qoi",
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Svg => "image/svg+xml",
+            OutputFormat::Raw => "application/octet-stream",
+            OutputFormat::Ppm => "image/x-portable-pixmap",
+            OutputFormat::Pam => "image/x-portable-arbitrarymap",
+            OutputFormat::Qoi => "image/qoi",
+        }
+    }
+
+    /// The bit depths this format can store, deepest first.
+    fn bit_depths(self) -> &'static [BitDepth] {
+        match self {
+            OutputFormat::Qoi => &[BitDepth::Eight],
+            _ => &[BitDepth::Sixteen, BitDepth::Eight],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum ChannelOrder {
+    #[value(name = "rgba")]
+    Rgba,
+    #[value(name = "bgra")]
+    Bgra,
+    #[value(name = "argb")]
+    Argb,
+}
+
+impl ChannelOrder {
+    fn as_order(self) -> sample::ChannelOrder {
+        match self {
+            ChannelOrder::Rgba => sample::ChannelOrder::Rgba,
+            ChannelOrder::Bgra => sample::ChannelOrder::Bgra,
+            ChannelOrder::Argb => sample::ChannelOrder::Argb,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum PamTupleArg {
+    Grayscale,
+    GrayscaleAlpha,
+    Rgb,
+    RgbAlpha,
+}
+
+impl PamTupleArg {
+    fn as_tuple_type(self) -> PamTupleType {
+        match self {
+            PamTupleArg::Grayscale => PamTupleType::Grayscale,
+            PamTupleArg::GrayscaleAlpha => PamTupleType::GrayscaleAlpha,
+            PamTupleArg::Rgb => PamTupleType::Rgb,
+            PamTupleArg::RgbAlpha => PamTupleType::RgbAlpha,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum RawEndian {
+    #[value(name = "little")]
+    Little,
+    #[value(name = "big")]
+    Big,
+}
+
+impl RawEndian {
+    fn as_endian(self) -> sample::Endian {
+        match self {
+            RawEndian::Little => sample::Endian::Little,
+            RawEndian::Big => sample::Endian::Big,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum RenderingIntent {
+    #[value(name = "perceptual")]
+    Perceptual,
+    #[value(name = "relative")]
+    Relative,
+    #[value(name = "saturation")]
+    Saturation,
+    #[value(name = "absolute")]
+    Absolute,
+}
+
+impl RenderingIntent {
+    fn as_intent(self) -> png::RenderingIntent {
+        match self {
+            RenderingIntent::Perceptual => png::RenderingIntent::Perceptual,
+            RenderingIntent::Relative => png::RenderingIntent::RelativeColorimetric,
+            RenderingIntent::Saturation => png::RenderingIntent::Saturation,
+            RenderingIntent::Absolute => png::RenderingIntent::AbsoluteColorimetric,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum Emit {
+    #[value(name = "all")]
+    All,
+    #[value(name = "rust")]
+    Rust,
+    #[value(name = "ppm-ascii")]
+    PpmAscii,
+    #[value(name = "terminal-truecolor-sample")]
+    TerminalTruecolorSample,
+    #[value(name = "roundtrip")]
+    Roundtrip,
+    #[value(name = "ts", alias = "color-js")]
+    Ts,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum PaletteFormat {
+    #[value(name = "apple-clr")]
+    AppleClr,
+    #[value(name = "ms-pal")]
+    MsPal,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum BatchEmit {
+    #[value(name = "jsonl")]
+    Jsonl,
+    #[value(name = "css-vars")]
+    CssVars,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum OnCollision {
+    #[value(name = "error")]
+    Error,
+    #[value(name = "suffix")]
+    Suffix,
+    #[value(name = "overwrite")]
+    Overwrite,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum Precision {
+    F32,
+    F64,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum RgbRange {
+    Auto,
+    Unit,
+    Byte,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum HexAlphaArg {
+    First,
+    Last,
+}
+
+impl HexAlphaArg {
+    fn as_position(self) -> HexAlphaPosition {
+        match self {
+            HexAlphaArg::First => HexAlphaPosition::First,
+            HexAlphaArg::Last => HexAlphaPosition::Last,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum InputScaleArg {
+    Css,
+    Percent100,
+}
+
+impl InputScaleArg {
+    fn as_scale(self) -> InputScale {
+        match self {
+            InputScaleArg::Css => InputScale::Css,
+            InputScaleArg::Percent100 => InputScale::Percent100,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum WheelOutOfGamut {
+    #[value(name = "clip")]
+    Clip,
+    #[value(name = "mark")]
+    Mark,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum Metric {
+    #[value(name = "deltaeok")]
+    DeltaEOk,
+    #[value(name = "ciede2000")]
+    Ciede2000,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum TransferArg {
+    #[value(name = "srgb")]
+    Srgb,
+    #[value(name = "linear")]
+    Linear,
+    #[value(name = "custom")]
+    Custom,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum RangeArg {
+    #[value(name = "full")]
+    Full,
+    #[value(name = "limited")]
+    Limited,
+}
+
+impl RangeArg {
+    fn as_range(self) -> sample::SampleRange {
+        match self {
+            RangeArg::Full => sample::SampleRange::Full,
+            RangeArg::Limited => sample::SampleRange::Limited,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum OutputMatrix {
+    #[value(name = "bt709")]
+    Bt709,
+    #[value(name = "bt2020")]
+    Bt2020,
+}
+
+impl OutputMatrix {
+    fn as_matrix(self) -> color::YcbcrMatrix {
+        match self {
+            OutputMatrix::Bt709 => color::YcbcrMatrix::Bt709,
+            OutputMatrix::Bt2020 => color::YcbcrMatrix::Bt2020,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum GamutMap {
+    #[value(name = "clip")]
+    Clip,
+    #[value(name = "css")]
+    Css,
+    #[value(name = "chroma-hue-tradeoff")]
+    ChromaHueTradeoff,
+    #[value(name = "luminance-preserve")]
+    LuminancePreserve,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum GamutTarget {
+    #[value(name = "display-p3")]
+    DisplayP3,
+    #[value(name = "srgb")]
+    Srgb,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum InputGamut {
+    #[value(name = "p3")]
+    P3,
+    #[value(name = "unbounded")]
+    Unbounded,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum CompletionShell {
+    #[value(name = "bash")]
+    Bash,
+    #[value(name = "elvish")]
+    Elvish,
+    #[value(name = "fish")]
+    Fish,
+    #[value(name = "powershell", alias = "power-shell")]
+    PowerShell,
+    #[value(name = "zsh")]
+    Zsh,
+}
+
+impl CompletionShell {
+    fn as_shell(self) -> Shell {
+        match self {
+            CompletionShell::Bash => Shell::Bash,
+            CompletionShell::Elvish => Shell::Elvish,
+            CompletionShell::Fish => Shell::Fish,
+            CompletionShell::PowerShell => Shell::PowerShell,
+            CompletionShell::Zsh => Shell::Zsh,
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let is_subcommand = args.get(1).is_some_and(|arg| {
+        Cli::command()
+            .get_subcommands()
+            .any(|subcommand| subcommand.get_name() == arg)
+    });
+    if is_subcommand {
+        run_subcommand(SubcommandCli::parse_from(&args).command);
+        return;
+    }
+
+    let mut cmd = Cli::command();
+    if let Some(path) = find_config_file() {
+        cmd = apply_config_defaults(cmd, &path).unwrap_or_else(|e| fail(&e));
+    }
+    let matches = cmd.get_matches_from(&args);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // Config settings are injected as defaults, which clap never checks for conflicts, so
+    // an explicit destination on the command line has to displace a configured directory.
+    if matches.value_source("output_dir") == Some(ValueSource::DefaultValue)
+        && (cli.output_file.is_some() || cli.append_to.is_some() || cli.emit_data_uri)
+    {
+        cli.output_dir = None;
+    }
+
+    if cli.list_hue_names {
+        for (name, degrees) in HUE_NAMES {
+            println!("{name:<8} {degrees}");
+        }
+        return;
+    }
+    if cli.version_full {
+        print_version_full();
+        return;
+    }
+    if cli.json_schema {
+        print_json_schema();
+        return;
+    }
+
+    #[cfg(feature = "swatches")]
+    if let Some(path) = &cli.import_swatches {
+        import_swatches(path).unwrap_or_else(|e| fail(&format!("{}: {e}", path.display())));
+        return;
+    }
+
+    let scale = cli.input_scale.as_scale();
+    let (mut l, mut c, mut h, a) = input_color(&cli, scale);
+    c = clamp_chroma(&cli, None, c);
+    if let Some(steps) = cli.hue_steps {
+        h = snap_hue(h, steps);
+        eprintln!("hue snapped to {}", format_component(h));
+    }
+    (c, h) = round_chroma_hue(&cli, c, h);
+    let mut name_l = l;
+    if cli.toe {
+        l = color::toe_inv(l);
+    }
+    let include_alpha = a.is_some();
+    let mut alpha = a.unwrap_or(1.0);
+
+    let p3_matrix = if cli.exact_matrix {
+        P3Matrix::Derived
+    } else {
+        P3Matrix::Baked
+    };
+    if let Some(target) = cli.target_luminance {
+        let solved = color::lightness_for_luminance(p3_matrix, c, h, target)
+            .unwrap_or_else(|e| fail(&e.to_string()));
+        l = round_to_step(solved, 0.00001);
+        name_l = l;
+        let (pixel, _) = encoded_pixel(ColorSpace::DisplayP3, p3_matrix, l, c, h, 1.0)
+            .unwrap_or_else(|e| fail(&e.to_string()));
+        let reached = color::display_p3_luminance(pixel);
+        eprintln!(
+            "solved L = {} (relative luminance {:.5})",
+            format_component(l),
+            reached
+        );
+        if (reached - target).abs() > 0.001 {
+            eprintln!(
+                "warning: no lightness reaches luminance {} at chroma {}",
+                format_component(target),
+                format_component(c)
+            );
+        }
+    }
+    if cli.snap_to_web_safe {
+        (l, c, h) = snap_to_web_safe(None, (l, c, h));
+        name_l = l;
+    }
+    if let Some(Emit::All) = cli.emit {
+        print_all_spaces(l, c, h, p3_matrix).unwrap_or_else(|e| fail(&e.to_string()));
+        if cli.describe {
+            print_nearest_named_color(l, c, h);
+        }
+        return;
+    }
+
+    if let Some(Emit::TerminalTruecolorSample) = cli.emit {
+        let srgb = srgb_bytes(l, c, h).unwrap_or_else(|e| fail(&e.to_string()));
+        match ansi_text_sample(srgb) {
+            Some(sample) => print!("{sample}"),
+            None => eprintln!("warning: NO_COLOR is set, so there is no sample to show"),
+        }
+        return;
+    }
+
+    if cli.explain {
+        print_explanation(
+            l,
+            c,
+            h,
+            p3_matrix,
+            cli_transfer(&cli),
+            cli.bit_depth.as_u8(),
+        )
+        .unwrap_or_else(|e| fail(&e.to_string()));
+        return;
+    }
+
+    let mut colors = vec![(l, c, h, alpha)];
+    let mut include_alpha = include_alpha;
+    for (color, has_alpha) in tile_colors(&cli, scale) {
+        include_alpha |= has_alpha;
+        colors.push(color);
+    }
+
+    let mut mask = None;
+    if let Some(path) = &cli.alpha_from {
+        let image = read_mask(path).unwrap_or_else(|e| fail(&e));
+        include_alpha = true;
+        if (image.width(), image.height()) == (1, 1) {
+            alpha = mask_alpha(image.pixel(0, 0));
+            for color in &mut colors {
+                color.3 = alpha;
+            }
+        } else {
+            mask = Some(image);
+        }
+    }
+    include_alpha |= cli.corner_radius.is_some();
+    if let Some(epsilon) = cli.alpha_threshold {
+        for (_, _, _, alpha) in &mut colors {
+            *alpha = snap_alpha(*alpha, epsilon);
+        }
+    }
+    let name_alpha = include_alpha.then_some(alpha);
+    let background = cli.background.as_deref().map(|spec| {
+        let (l, c, h, a) = parse_color_spec_in(scale, spec).unwrap_or_else(|e| fail(&e));
+        if a.is_some() {
+            fail("--background must be opaque; give it as `L C H`");
+        }
+        (l, c, h)
+    });
+    if background.is_some() {
+        include_alpha = false;
+    }
+    let background_chunk = cli.background_chunk.as_deref().map(|spec| {
+        let (l, c, h, a) = parse_color_spec_in(scale, spec).unwrap_or_else(|e| fail(&e));
+        if a.is_some() {
+            fail("bKGD has no alpha, so --background-chunk must be given as `L C H`");
+        }
+        (l, c, h, 1.0)
+    });
+    if cli.no_alpha {
+        include_alpha = false;
+        for (_, _, _, alpha) in &mut colors {
+            *alpha = 1.0;
+        }
+    }
+
+    let output_format = resolve_output_format(&cli, &matches);
+    let transfer = cli_transfer(&cli);
+    let range = cli.range.as_range();
+    check_output_flags(
+        &cli,
+        output_format,
+        transfer,
+        range,
+        include_alpha,
+        background.is_some(),
+        mask.is_some(),
+    );
+    let bit_depth = validate_output_config(output_format, cli.bit_depth, cli.strict);
+
+    let grid = cli.tile.map(|columns| {
+        let rows = (colors.len() as u32).div_ceil(columns);
+        if columns.checked_mul(cli.width).is_none() || rows.checked_mul(cli.height).is_none() {
+            fail("tile is too large");
+        }
+        (columns, rows)
+    });
+    let (width, height) = grid.map_or((cli.width, cli.height), |(columns, rows)| {
+        (columns * cli.width, rows * cli.height)
+    });
+    if !matches!(output_format, OutputFormat::Svg) {
+        check_pixel_count(width, height, cli.max_pixels).unwrap_or_else(|e| fail(&e));
+    }
+    if let Some(mask) = &mask
+        && (mask.width(), mask.height()) != (width, height)
+    {
+        fail(&format!(
+            "the mask is {}x{} but the image is {width}x{height}",
+            mask.width(),
+            mask.height()
+        ));
+    }
+    let to_stdout = cli.output_file.as_deref() == Some("-");
+    let hex = srgb_hex(l, c, h, None).unwrap_or_else(|e| fail(&e.to_string()));
+    let hex8 = srgb_hex(l, c, h, Some(alpha)).unwrap_or_else(|e| fail(&e.to_string()));
+    if cli.name_with_hex && grid.is_some() {
+        fail("--name-with-hex only applies to the default name of a single color");
+    }
+    let name = match &cli.output_file {
+        Some(name) if to_stdout => name.clone(),
+        Some(name) => name.replace("{hex8}", &hex8).replace("{hex}", &hex),
+        None => match grid {
+            Some((columns, rows)) => {
+                format!("oklch-tile-{columns}x{rows}.{}", output_format.extension())
+            }
+            None => {
+                let name = if cli.deterministic_filenames {
+                    pinned_output_name(name_l, c, h, name_alpha, output_format.extension())
+                } else {
+                    default_output_name(name_l, c, h, name_alpha, output_format.extension())
+                };
+                if cli.name_with_hex {
+                    let suffix = if include_alpha { &hex8 } else { &hex };
+                    with_name_suffix(&name, &format!("-{suffix}"))
+                } else {
+                    name
+                }
+            }
+        },
+    };
+    let output = match (&cli.output_dir, &cli.append_to) {
+        (_, Some(sheet)) => sheet.clone(),
+        (Some(dir), None) => dir.join(name),
+        (None, None) => PathBuf::from(name),
+    };
+    let bit_depth = bit_depth.as_u8();
+
+    let encoder = PixelEncoder::from_cli(&cli, p3_matrix, transfer, range, background, bit_depth);
+
+    let mut pixels = Vec::with_capacity(colors.len());
+    let mut p3_clipped = false;
+    for (index, color) in colors.iter().enumerate() {
+        let (composited, clipped, reduction) = encoder.composite(ColorSpace::DisplayP3, color);
+        let pixel = encoder.store(composited);
+        p3_clipped |= clipped;
+        if clipped {
+            if let Some(report) = &cli.clip_report_file {
+                append_clip_report(report, color, p3_matrix, &output).unwrap_or_else(|e| {
+                    fail(&format!("failed to write {}: {e}", report.display()))
+                });
+            }
+            let subject = if colors.len() == 1 {
+                "color".to_string()
+            } else {
+                format!("color {}", index + 1)
+            };
+            let prefix = if colors.len() == 1 {
+                String::new()
+            } else {
+                format!("{subject}: ")
+            };
+            warn_out_of_gamut(
+                &encoder,
+                cli.assume_input_gamut,
+                &subject,
+                &prefix,
+                reduction,
+            );
+        }
+        if cli.warn_degenerate
+            && color.1 > color::NEUTRAL_SNAP_EPSILON
+            && let Some(extreme) = pure_extreme(composited, bit_depth)
+        {
+            let prefix = if colors.len() == 1 {
+                String::new()
+            } else {
+                format!("color {}: ", index + 1)
+            };
+            eprintln!(
+                "warning: {prefix}chroma {} came out pure {extreme}; the chroma may be far too \
+                 high for this lightness, or L is at an extreme",
+                format_css_number(color.1)
+            );
+        }
+        pixels.push(pixel);
+    }
+
+    let mut color_space = ColorSpace::DisplayP3;
+    if cli.auto_space {
+        let srgb: Vec<(Pixel, bool)> = colors
+            .iter()
+            .map(|color| {
+                let (pixel, clipped, _) = encoder.encode(ColorSpace::Srgb, color);
+                (pixel, clipped)
+            })
+            .collect();
+        if srgb.iter().any(|&(_, clipped)| clipped) {
+            eprintln!("using Display P3: color is outside the sRGB gamut");
+        } else {
+            eprintln!("using sRGB: color is within the sRGB gamut");
+            pixels = srgb.into_iter().map(|(pixel, _)| pixel).collect();
+            color_space = ColorSpace::Srgb;
+        }
+    }
+
+    let tile_image = |pixels: &[Pixel]| {
+        let image = match grid {
+            Some((columns, rows)) => {
+                Image::from_fn(columns * cli.width, rows * cli.height, |x, y| {
+                    let cell = (y / cli.height) * columns + x / cli.width;
+                    pixels[cell as usize % pixels.len()]
+                })
+            }
+            None => Image::solid(cli.width, cli.height, pixels[0]),
+        };
+        if mask.is_none() && cli.corner_radius.is_none() {
+            return image;
+        }
+        Image::from_fn(image.width(), image.height(), |x, y| {
+            let mut pixel = image.pixel(x, y);
+            if let Some(mask) = &mask {
+                pixel.a = mask_alpha(mask.pixel(x, y));
+            }
+            if let Some(radius) = cli.corner_radius {
+                pixel.a *= rounded_rect_coverage(x, y, image.width(), image.height(), radius);
+            }
+            pixel
+        })
+    };
+    let image = tile_image(&pixels);
+    let image = match cli.append_to.as_deref().filter(|sheet| sheet.exists()) {
+        Some(sheet) => {
+            let sheet = read_sheet(sheet, bit_depth, color_space).unwrap_or_else(|e| fail(&e));
+            if sheet.image.height() != image.height() {
+                fail(&format!(
+                    "the sheet is {} pixels tall but the image is {}",
+                    sheet.image.height(),
+                    image.height()
+                ));
+            }
+            include_alpha |= sheet.has_alpha;
+            append_columns(&sheet.image, &image, cli.max_pixels)
+        }
+        None => image,
+    };
+
+    // Encoded like the image, so a viewer that fills with it matches an opaque pixel of it.
+    let suggested_background = background_chunk.map(|color| {
+        let (pixel, clipped, _) = encoder.encode(color_space, &color);
+        if clipped {
+            let space = match color_space {
+                ColorSpace::DisplayP3 => "Display P3",
+                ColorSpace::Srgb => "sRGB",
+            };
+            fail(&format!(
+                "the --background-chunk color is outside {space}, so it can't be recorded as given"
+            ));
+        }
+        pixel
+    });
+    let path = output.as_path();
+    let png_options = PngOptions {
+        color_space,
+        exif: cli.exif,
+        minimal: cli.minimal,
+        rendering_intent: cli
+            .rendering_intent
+            .map_or_else(Default::default, RenderingIntent::as_intent),
+        transfer,
+        range,
+        suggested_background,
+        ..PngOptions::default()
+    };
+    if cli.validate_cicp
+        && matches!(output_format, OutputFormat::Png)
+        && !cli.minimal
+        && color_space == ColorSpace::DisplayP3
+        && let Err(err) = png::validate_cicp(png::cicp_bytes(&png_options), &png_options)
+    {
+        fail(&err.to_string());
+    }
+    let output = ImageOutput {
+        format: output_format,
+        image: &image,
+        bit_depth,
+        include_alpha,
+        png_options,
+        svg_fallback: srgb_hex(l, c, h, include_alpha.then_some(pixels[0].a))
+            .unwrap_or_else(|e| fail(&e.to_string())),
+        channel_order: cli
+            .channel_order
+            .map_or_else(Default::default, ChannelOrder::as_order),
+        endian: cli
+            .raw_endian
+            .map_or_else(Default::default, RawEndian::as_endian),
+        pam_tuple_type: cli.pam_tuple_type.map_or_else(
+            || PamTupleType::for_alpha(include_alpha),
+            PamTupleArg::as_tuple_type,
+        ),
+    };
+    if let Some(Emit::Ts) = cli.emit {
+        if cli.emit_data_uri {
+            fail("--emit ts and --emit-data-uri both print the color; pick one");
+        }
+        if transfer != Transfer::Srgb || range != sample::SampleRange::Full {
+            fail("--emit ts prints CSS colors, which are always sRGB-encoded and full range");
+        }
+        let p3: Vec<Pixel> = colors
+            .iter()
+            .map(|color| encoder.encode(ColorSpace::DisplayP3, color).0)
+            .collect();
+        print_ts_consts(&colors, &p3, include_alpha);
+        return;
+    }
+    if let Some(Emit::Rust) = cli.emit {
+        if cli.emit_data_uri {
+            fail("--emit rust and --emit-data-uri both print the color; pick one");
+        }
+        print_rust_consts(&colors, &pixels, color_space, bit_depth, include_alpha);
+        if cli.describe {
+            print_nearest_named_color(l, c, h);
+        }
+        return;
+    }
+    if let Some(Emit::PpmAscii) = cli.emit {
+        if cli.emit_data_uri {
+            fail("--emit ppm-ascii and --emit-data-uri both print the image; pick one");
+        }
+        if include_alpha {
+            fail("ppm cannot store alpha, so --emit ppm-ascii can't show it");
+        }
+        let mut stdout = io::stdout().lock();
+        if let Err(err) = write_plain_ppm(&mut stdout, &image, bit_depth) {
+            fail(&format!("failed to print image: {err}"));
+        }
+        return;
+    }
+    let roundtrip = matches!(cli.emit, Some(Emit::Roundtrip));
+    if roundtrip {
+        if cli.emit_data_uri || to_stdout {
+            fail("--emit roundtrip reads back the file it writes, so it needs an output file");
+        }
+        if !matches!(output_format, OutputFormat::Png) {
+            fail("--emit roundtrip only applies to PNG output");
+        }
+        if cli.append_to.is_some() {
+            fail("--emit roundtrip can't be combined with --append-to");
+        }
+        // The file would hold the composited color, not the requested one.
+        if background.is_some() || cli.premultiply {
+            fail("--emit roundtrip can't be combined with --background or --premultiply");
+        }
+    }
+    if cli.emit_data_uri {
+        let mut buf = Vec::new();
+        if let Err(err) = output.write(&mut buf) {
+            fail(&format!("failed to encode image: {err}"));
+        }
+        println!("data:{};base64,{}", output_format.mime_type(), base64(&buf));
+        return;
+    }
+
+    if let Some(dir) = &cli.output_dir
+        && let Err(err) = fs::create_dir_all(dir)
+    {
+        fail(&format!("failed to create {}: {err}", dir.display()));
+    }
+    let result = if to_stdout {
+        if cli.describe
+            || cli.preview_size.is_some()
+            || cli.emit_palette.is_some()
+            || cli.sidecar_srgb
+            || cli.manifest
+        {
+            fail(
+                "--output-file - sends the image to stdout, so it can't be combined with \
+                 --describe, --preview-size, --emit-palette, --sidecar-srgb, or --manifest",
+            );
+        }
+        if io::stdout().is_terminal()
+            && !cli.force_tty
+            && !matches!(output_format, OutputFormat::Svg)
+        {
+            fail(&format!(
+                "refusing to write binary {} to a terminal; redirect or use --force-tty",
+                output_format.extension().to_uppercase()
+            ));
+        }
+        let mut writer = BufWriter::new(io::stdout().lock());
+        output.write(&mut writer).and_then(|()| writer.flush())
+    } else if cli.no_atomic {
+        write_file_in_place(path, |writer| output.write(writer))
+    } else {
+        write_file(path, |writer| output.write(writer))
+    };
+    if let Err(err) = result {
+        fail(&format!(
+            "failed to write {}: {err}",
+            output_format.extension().to_uppercase()
+        ));
+    }
+    if cli.output_dir.is_some() {
+        println!("wrote {}", path.display());
+    }
+
+    if cli.manifest {
+        let manifest = Manifest {
+            path,
+            colors: &colors,
+            color_space,
+            transfer: &value_name(cli.transfer),
+            bit_depth,
+            format: output_format.extension(),
+            size: (image.width(), image.height()),
+            gamut_map: &value_name(cli.gamut_map),
+            clipped: p3_clipped,
+        };
+        if let Err(err) = manifest.write() {
+            fail(&format!("failed to write manifest: {err}"));
+        }
+    }
+
+    if roundtrip {
+        // The middle of the first tile, clear of any rounded corner.
+        let center = (cli.width / 2, cli.height / 2);
+        let recovered = read_back_oklch(path, center, color_space, p3_matrix, transfer, range)
+            .unwrap_or_else(|e| fail(&format!("failed to read back {}: {e}", path.display())));
+        print_roundtrip((l, c, h), recovered);
+    }
+
+    if cli.sidecar_srgb {
+        let (srgb, srgb_clipped): (Vec<Pixel>, Vec<bool>) = colors
+            .iter()
+            .map(|color| {
+                let (pixel, clipped, _) = encoder.encode(ColorSpace::Srgb, color);
+                (pixel, clipped)
+            })
+            .unzip();
+        let sidecar = ImageOutput {
+            image: &tile_image(&srgb),
+            png_options: PngOptions {
+                color_space: ColorSpace::Srgb,
+                exif: cli.exif,
+                minimal: cli.minimal,
+                rendering_intent: output.png_options.rendering_intent,
+                transfer,
+                range,
+                ..PngOptions::default()
+            },
+            ..output
+        };
+        write_srgb_sidecar(path, &sidecar, p3_clipped, srgb_clipped.contains(&true));
+    }
+
+    if let Some(format) = cli.emit_palette {
+        let srgb: Vec<[u8; 3]> = colors
+            .iter()
+            .map(|&(l, c, h, _)| srgb_bytes(l, c, h).unwrap_or_else(|e| fail(&e.to_string())))
+            .collect();
+        let result = match format {
+            PaletteFormat::AppleClr => {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                let entries = palette_keys(&colors)
+                    .into_iter()
+                    .zip(srgb)
+                    .collect::<Vec<_>>();
+                write_file(&path.with_extension("clr"), |writer| {
+                    write_apple_clr(writer, &name, &entries)
+                })
+            }
+            PaletteFormat::MsPal => write_file(&path.with_extension("pal"), |writer| {
+                write_ms_pal(writer, &srgb)
+            }),
+        };
+        if let Err(err) = result {
+            fail(&format!("failed to write palette: {err}"));
+        }
+    }
+
+    if let Some(size) = cli.preview_size {
+        let srgb = srgb_bytes(l, c, h).unwrap_or_else(|e| fail(&e.to_string()));
+        if let Some(preview) = ansi_preview(srgb, size, "") {
+            print!("{preview}");
+        }
+    }
+
+    if cli.describe {
+        print_nearest_named_color(l, c, h);
+    }
+}
+
+/// A key for each color in a named palette: its OKLCH, with ` 2`, ` 3`, and so on after
+/// any that repeat an earlier color, since a color list keeps one color per key.
+fn palette_keys(colors: &[(f64, f64, f64, f64)]) -> Vec<String> {
+    let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+    colors
+        .iter()
+        .map(|&(l, c, h, _)| {
+            let key = format!("oklch({})", [l, c, h].map(format_css_number).join(" "));
+            let count = seen.entry(key.clone()).or_default();
+            *count += 1;
+            if *count == 1 {
+                key
+            } else {
+                format!("{key} {count}")
+            }
+        })
+        .collect()
+}
+
+/// Runs a subcommand; each one parses its own arguments and writes its own output.
+fn run_subcommand(command: Commands) {
+    match command {
+        Commands::GenerateCompletions { shell } => {
+            let mut cmd = Cli::command();
+            let bin_name = cmd.get_name().to_string();
+            generate(shell.as_shell(), &mut cmd, bin_name, &mut io::stdout());
+        }
+        Commands::Repl {
+            bit_depth,
+            preview_size,
+        } => {
+            if let Err(err) = run_repl(bit_depth.as_u8(), preview_size) {
+                fail(&format!("failed to read input: {err}"));
+            }
+        }
+        Commands::Batch {
+            bit_depth,
+            emit,
+            jobs,
+            manifest,
+            clip_report_file,
+            on_collision,
+        } => match run_batch(
+            bit_depth.as_u8(),
+            emit,
+            usize::from(jobs),
+            manifest,
+            clip_report_file.as_deref(),
+            on_collision,
+        ) {
+            Ok(true) => {}
+            Ok(false) => process::exit(1),
+            Err(err) => fail(&format!("failed to read input: {err}")),
+        },
+        Commands::RawPixel {
+            r,
+            g,
+            b,
+            a,
+            max,
+            bit_depth,
+            cicp,
+            output_file,
+        } => {
+            if !max.is_finite() || max <= 0.0 {
+                fail("--max must be greater than 0");
+            }
+            let sample = |name: &str, value: f64| {
+                if !(0.0..=max).contains(&value) {
+                    fail(&format!("--{name} must be between 0 and {max}"));
+                }
+                value / max
+            };
+            let pixel = Pixel {
+                r: sample("r", r),
+                g: sample("g", g),
+                b: sample("b", b),
+                a: a.map_or(1.0, |a| sample("a", a)),
+            };
+            let result = write_file(&output_file, |writer| {
+                write_png(
+                    writer,
+                    &Image::solid(1, 1, pixel),
+                    bit_depth.as_u8(),
+                    a.is_some(),
+                    &PngOptions {
+                        minimal: !cicp,
+                        ..PngOptions::default()
+                    },
+                )
+            });
+            if let Err(err) = result {
+                fail(&format!("failed to write {}: {err}", output_file.display()));
+            }
+        }
+        Commands::FromRgb {
+            r,
+            g,
+            b,
+            a,
+            rgb_range,
+            bit_depth,
+            output_file,
+        } => {
+            let max = match rgb_range {
+                RgbRange::Unit => 1.0,
+                RgbRange::Byte => 255.0,
+                RgbRange::Auto if [r, g, b].iter().any(|&v| v > 1.0) => 255.0,
+                RgbRange::Auto => 1.0,
+            };
+            let component = |name: &str, value: f64| {
+                if !(0.0..=max).contains(&value) {
+                    fail(&format!("{name} must be between 0 and {max}"));
+                }
+                value / max
+            };
+            write_srgb_pixel(
+                (component("R", r), component("G", g), component("B", b)),
+                a.map(|a| component("A", a)),
+                bit_depth,
+                &output_file,
+            );
+        }
+        Commands::Hsl {
+            h,
+            s,
+            lightness,
+            a,
+            input_scale,
+            bit_depth,
+            output_file,
+        } => {
+            let component = cylindrical_component(input_scale);
+            if !h.is_finite() {
+                fail("H must be a finite number of degrees");
+            }
+            let srgb = color::hsl_to_srgb(h, component("S", s), component("L", lightness));
+            let alpha = a.map(|a| component("A", a));
+            write_srgb_pixel(srgb, alpha, bit_depth, &output_file);
+        }
+        Commands::Hsv {
+            h,
+            s,
+            value,
+            a,
+            input_scale,
+            bit_depth,
+            output_file,
+        } => {
+            let component = cylindrical_component(input_scale);
+            if !h.is_finite() {
+                fail("H must be a finite number of degrees");
+            }
+            let srgb = color::hsv_to_srgb(h, component("S", s), component("V", value));
+            let alpha = a.map(|a| component("A", a));
+            write_srgb_pixel(srgb, alpha, bit_depth, &output_file);
+        }
+        Commands::Wheel {
+            size,
+            lightness,
+            max_chroma,
+            out_of_gamut,
+            oog_marker,
+            bit_depth,
+            output_file,
+        } => {
+            let l = parse_l(&lightness).unwrap_or_else(|e| fail(&e.to_string()));
+            let max_chroma = parse_non_negative(&max_chroma, "--max-chroma")
+                .unwrap_or_else(|e| fail(&e.to_string()));
+            let marker = oog_marker.as_deref().map(oog_marker_pixel);
+            let (image, in_gamut) = hue_wheel(size, l, max_chroma, out_of_gamut, marker)
+                .unwrap_or_else(|e| fail(&e.to_string()));
+            let result = write_file(&output_file, |writer| {
+                write_png(
+                    writer,
+                    &image,
+                    bit_depth.as_u8(),
+                    true,
+                    &PngOptions::default(),
+                )
+            });
+            if let Err(err) = result {
+                fail(&format!("failed to write {}: {err}", output_file.display()));
+            }
+            println!("{:.1}% of the wheel is in gamut", in_gamut * 100.0);
+        }
+        Commands::GamutDiff {
+            hue,
+            width,
+            height,
+            max_chroma,
+            oog_marker,
+            bit_depth,
+            output_file,
+        } => {
+            let h = parse_hue(&hue).unwrap_or_else(|e| fail(&e.to_string()));
+            let max_chroma = parse_non_negative(&max_chroma, "--max-chroma")
+                .unwrap_or_else(|e| fail(&e.to_string()));
+            let marker = oog_marker.as_deref().map(oog_marker_pixel);
+            let (image, [srgb, p3_only, outside]) =
+                gamut_diff(width, height, h, max_chroma, marker)
+                    .unwrap_or_else(|e| fail(&e.to_string()));
+            let result = write_file(&output_file, |writer| {
+                write_png(
+                    writer,
+                    &image,
+                    bit_depth.as_u8(),
+                    true,
+                    &PngOptions::default(),
+                )
+            });
+            if let Err(err) = result {
+                fail(&format!("failed to write {}: {err}", output_file.display()));
+            }
+            println!(
+                "sRGB: {:.1}%, Display P3 only: {:.1}%, outside both: {:.1}%",
+                srgb * 100.0,
+                p3_only * 100.0,
+                outside * 100.0
+            );
+        }
+        Commands::GamutExplorer {
+            hue,
+            lightness,
+            chroma,
+            max_chroma,
+            gamut,
+        } => {
+            let h = parse_hue(&hue).unwrap_or_else(|e| fail(&e.to_string()));
+            let max_chroma = parse_non_negative(&max_chroma, "--max-chroma")
+                .unwrap_or_else(|e| fail(&e.to_string()));
+            if max_chroma == 0.0 {
+                fail("--max-chroma must be greater than 0");
+            }
+            let mark = lightness.zip(chroma).map(|(l, c)| {
+                let l = parse_l(&l).unwrap_or_else(|e| fail(&e.to_string()));
+                let c = parse_non_negative(&c, "--chroma").unwrap_or_else(|e| fail(&e.to_string()));
+                (l, c)
+            });
+            let space = match gamut {
+                GamutTarget::DisplayP3 => ColorSpace::DisplayP3,
+                GamutTarget::Srgb => ColorSpace::Srgb,
+            };
+            let map =
+                gamut_explorer(space, h, max_chroma, mark).unwrap_or_else(|e| fail(&e.to_string()));
+            print!("{map}");
+        }
+        Commands::Wavelength {
+            nm,
+            lightness,
+            width,
+            height,
+            bit_depth,
+            output_file,
+        } => {
+            let nm = parse_f64(&nm, "wavelength").unwrap_or_else(|e| fail(&e.to_string()));
+            let l = parse_l(&lightness).unwrap_or_else(|e| fail(&e.to_string()));
+            let (l, c, h) =
+                spectral::wavelength_to_oklch(nm, l).unwrap_or_else(|e| fail(&e.to_string()));
+            let (pixel, reduction) = css_gamut_map(
+                ColorSpace::DisplayP3,
+                P3Matrix::Baked,
+                (l, c, h),
+                1.0,
+                GamutMapOptions::default(),
+            )
+            .unwrap_or_else(|e| fail(&e.to_string()));
+            let result = write_file(&output_file, |writer| {
+                write_png(
+                    writer,
+                    &Image::solid(width, height, pixel),
+                    bit_depth.as_u8(),
+                    false,
+                    &PngOptions::default(),
+                )
+            });
+            if let Err(err) = result {
+                fail(&format!("failed to write {}: {err}", output_file.display()));
+            }
+            // How far the drawn color is from the light itself, in Oklab.
+            let decoded = [pixel.r, pixel.g, pixel.b].map(srgb_decode);
+            let drawn = color::linear_to_oklab(
+                ColorSpace::DisplayP3,
+                P3Matrix::Baked,
+                decoded[0],
+                decoded[1],
+                decoded[2],
+            );
+            let (_, a, b) = color::oklch_to_oklab(l, c, h);
+            let moved =
+                ((drawn.0 - l).powi(2) + (drawn.1 - a).powi(2) + (drawn.2 - b).powi(2)).sqrt();
+            let oklch = [l, c, h].map(format_css_number).join(" ");
+            match reduction {
+                Some(reduction) if reduction.delta() > 0.0 => println!(
+                    "{} nm is oklch({oklch}); reduced chroma to {} to fit Display P3, \
+                     deltaEOK {:.4} away",
+                    format_component(nm),
+                    format_css_number(reduction.chroma),
+                    moved
+                ),
+                Some(_) => println!(
+                    "{} nm is oklch({oklch}); clipped to fit Display P3, deltaEOK {:.4} away",
+                    format_component(nm),
+                    moved
+                ),
+                None => println!(
+                    "{} nm is oklch({oklch}), inside Display P3",
+                    format_component(nm)
+                ),
+            }
+        }
+        Commands::ContactSheet {
+            colors,
+            color_file,
+            columns,
+            cell_size,
+            bit_depth,
+            output_file,
+        } => {
+            let mut specs = colors;
+            if let Some(path) = &color_file {
+                let contents = fs::read_to_string(path)
+                    .unwrap_or_else(|e| fail(&format!("failed to read {}: {e}", path.display())));
+                specs.extend(
+                    contents
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                        .map(str::to_string),
+                );
+            }
+            if specs.is_empty() {
+                fail("contact-sheet needs at least one color");
+            }
+            let colors: Vec<_> = specs
+                .iter()
+                .map(|spec| parse_color_spec(spec).unwrap_or_else(|e| fail(&e)))
+                .collect();
+            let image =
+                contact_sheet(&colors, columns, cell_size).unwrap_or_else(|e| fail(&e.to_string()));
+            let include_alpha = colors.iter().any(|&(_, _, _, a)| a.is_some());
+            let result = write_file(&output_file, |writer| {
+                write_png(
+                    writer,
+                    &image,
+                    bit_depth.as_u8(),
+                    include_alpha,
+                    &PngOptions::default(),
+                )
+            });
+            if let Err(err) = result {
+                fail(&format!("failed to write {}: {err}", output_file.display()));
+            }
+        }
+        Commands::Distance {
+            first,
+            second,
+            metric,
+        } => {
+            let oklch = |spec: &str| {
+                let (l, c, h, _) = parse_color_spec(spec).unwrap_or_else(|e| fail(&e));
+                (l, c, h)
+            };
+            let (first, second) = (oklch(&first), oklch(&second));
+            let distance = match metric {
+                Metric::DeltaEOk => distance::delta_e_ok(first, second),
+                Metric::Ciede2000 => distance::ciede2000(first, second),
+            };
+            println!("{distance:.6}");
+        }
+        Commands::Capabilities { json } => print_capabilities(json),
+        Commands::Bench {
+            iterations,
+            precision,
+        } => run_bench(iterations, precision),
+        Commands::ThemePair {
+            l,
+            c,
+            h,
+            a,
+            dark_lightness,
+            lightness_delta,
+            bit_depth,
+        } => {
+            let (l, c, h, a) =
+                parse_color(&l, &c, &h, a.as_deref()).unwrap_or_else(|e| fail(&e.to_string()));
+            let dark_l = match dark_lightness {
+                Some(value) => parse_l(&value).unwrap_or_else(|e| fail(&e.to_string())),
+                None => l - lightness_delta.unwrap_or_default(),
+            };
+            if !(0.0..=1.0).contains(&dark_l) {
+                fail(&format!("dark variant lightness {dark_l} is outside 0..1"));
+            }
+            let stem = default_output_name(l, c, h, a, "png");
+            let stem = stem.strip_suffix(".png").unwrap_or(&stem);
+            for (variant, lightness) in [("light", l), ("dark", dark_l)] {
+                let path = format!("{stem}-{variant}.png");
+                write_theme_variant(&path, (lightness, c, h, a), variant, bit_depth.as_u8())
+                    .unwrap_or_else(|e| fail(&format!("failed to write {path}: {e}")));
+                println!("wrote {path}");
+            }
+        }
+    }
+}
+
+/// The main color, from `--color-file`, `--color-env`, `--hex`, or the positional
+/// arguments, in that order of precedence.
+fn input_color(cli: &Cli, scale: InputScale) -> (f64, f64, f64, Option<f64>) {
+    match (&cli.color_file, &cli.color_env, &cli.hex) {
+        (Some(path), _, _) => read_color_file(path, cli.first_line_only)
+            .and_then(|spec| parse_color_spec_in(scale, &spec))
+            .unwrap_or_else(|e| fail(&e)),
+        (None, Some(name), _) => read_color_env(name)
+            .and_then(|spec| parse_color_spec_in(scale, &spec))
+            .unwrap_or_else(|e| fail(&e)),
+        (None, None, Some(hex)) => {
+            let (srgb, a) = parse_hex(hex, cli.hex_alpha_position.as_position())
+                .unwrap_or_else(|e| fail(&e.to_string()));
+            let (l, c, h) = color::srgb_to_oklch(srgb);
+            (l, c, h, a)
+        }
+        (None, None, None) => {
+            // clap requires all three positionals when --color-file, --color-env, and --hex
+            // are absent.
+            let positional = |value: &Option<String>| value.clone().unwrap_or_default();
+            parse_color_in(
+                scale,
+                &positional(&cli.l),
+                &positional(&cli.c),
+                &positional(&cli.h),
+                cli.a.as_deref(),
+            )
+            .unwrap_or_else(|e| fail(&e.to_string()))
+        }
+    }
+}
+
+/// The `--tile-color` colors, adjusted like the main color, each with whether it gave an
+/// alpha.
+fn tile_colors(cli: &Cli, scale: InputScale) -> Vec<((f64, f64, f64, f64), bool)> {
+    cli.tile_color
+        .iter()
+        .map(|spec| {
+            let (mut l, c, mut h, a) =
+                parse_color_spec_in(scale, spec).unwrap_or_else(|e| fail(&e));
+            let c = clamp_chroma(cli, Some(spec), c);
+            if let Some(steps) = cli.hue_steps {
+                h = snap_hue(h, steps);
+            }
+            let (mut c, mut h) = round_chroma_hue(cli, c, h);
+            if cli.toe {
+                l = color::toe_inv(l);
+            }
+            if cli.snap_to_web_safe {
+                (l, c, h) = snap_to_web_safe(Some(spec), (l, c, h));
+            }
+            ((l, c, h, a.unwrap_or(1.0)), a.is_some())
+        })
+        .collect()
+}
+
+/// The format the `--output-file` extension implies, unless `--output-format` was given
+/// on the command line; then the flag wins, with a warning if the two disagree.
+fn resolve_output_format(cli: &Cli, matches: &ArgMatches) -> OutputFormat {
+    match cli.output_file.as_deref().and_then(format_from_extension) {
+        Some(inferred)
+            if matches.value_source("output_format") == Some(ValueSource::CommandLine) =>
+        {
+            if inferred.extension() != cli.output_format.extension() {
+                eprintln!(
+                    "warning: --output-file ends in .{} but --output-format is {}; writing {}",
+                    inferred.extension(),
+                    cli.output_format.extension(),
+                    cli.output_format.extension()
+                );
+            }
+            cli.output_format
+        }
+        Some(inferred) => inferred,
+        None => cli.output_format,
+    }
+}
+
+/// Rejects flags that don't apply to `output_format`, or to each other, before any
+/// pixels are computed.
+fn check_output_flags(
+    cli: &Cli,
+    output_format: OutputFormat,
+    transfer: Transfer,
+    range: sample::SampleRange,
+    include_alpha: bool,
+    has_background: bool,
+    has_mask: bool,
+) {
+    if cli.channel_order.is_some() && !matches!(output_format, OutputFormat::Raw) {
+        fail(&format!(
+            "--channel-order only applies to raw output; {} mandates RGBA order",
+            output_format.extension()
+        ));
+    }
+    if cli.pam_tuple_type.is_some() && !matches!(output_format, OutputFormat::Pam) {
+        fail("--pam-tuple-type only applies to pam output");
+    }
+    if cli.raw_endian.is_some() && !matches!(output_format, OutputFormat::Raw) {
+        fail(&format!(
+            "--raw-endian only applies to raw output; {} has a fixed byte order",
+            output_format.extension()
+        ));
+    }
+    if cli.exif && !matches!(output_format, OutputFormat::Png) {
+        fail("--exif only applies to PNG output");
+    }
+    if cli.auto_space && !matches!(output_format, OutputFormat::Png) {
+        fail("--auto-space only applies to PNG output");
+    }
+    if cli.rendering_intent.is_some() && !cli.auto_space && !cli.sidecar_srgb {
+        fail("--rendering-intent only applies to --auto-space or --sidecar-srgb");
+    }
+    if (cli.gamut_map_jnd.is_some() || cli.gamut_map_clip_final)
+        && matches!(cli.gamut_map, GamutMap::Clip | GamutMap::LuminancePreserve)
+    {
+        fail(
+            "--gamut-map-jnd and --gamut-map-clip-final only apply to --gamut-map css or \
+             chroma-hue-tradeoff",
+        );
+    }
+    if cli.max_hue_shift.is_some() && !matches!(cli.gamut_map, GamutMap::ChromaHueTradeoff) {
+        fail("--max-hue-shift only applies to --gamut-map chroma-hue-tradeoff");
+    }
+    if cli.precision == Precision::F32
+        && (cli.exact_matrix || !matches!(cli.gamut_map, GamutMap::Clip))
+    {
+        fail("--precision f32 only supports the built-in matrix and --gamut-map clip");
+    }
+    // Both --auto-space and the --sidecar-srgb file tag their samples with an sRGB chunk,
+    // unless --minimal leaves the chunks out.
+    let srgb_chunk_flag = if cli.minimal {
+        None
+    } else if cli.auto_space {
+        Some("--auto-space")
+    } else if cli.sidecar_srgb {
+        Some("--sidecar-srgb")
+    } else {
+        None
+    };
+    if transfer != Transfer::Srgb {
+        let name = match transfer {
+            Transfer::Custom(_) => "custom",
+            _ => "linear",
+        };
+        if matches!(output_format, OutputFormat::Svg) {
+            fail(&format!(
+                "--transfer {name} does not apply to svg output; CSS color() is always encoded"
+            ));
+        }
+        // cICP always records the real transfer, but an sRGB chunk can only say sRGB.
+        if let Some(flag) = srgb_chunk_flag
+            && !cli.allow_transfer_mismatch
+        {
+            fail(&format!(
+                "--transfer {name} with {flag} would tag {name} samples as sRGB; \
+                 pass --allow-transfer-mismatch to write it anyway"
+            ));
+        }
+    }
+    if matches!(transfer, Transfer::Custom(_)) && matches!(output_format, OutputFormat::Qoi) {
+        fail("qoi can only record the sRGB curve or linear light, not --transfer custom");
+    }
+    if cli.output_matrix.is_some() && !matches!(output_format, OutputFormat::Raw) {
+        fail(&format!(
+            "--output-matrix only applies to raw output; {} can only hold RGB",
+            output_format.extension()
+        ));
+    }
+    if range == sample::SampleRange::Limited {
+        if matches!(output_format, OutputFormat::Svg) {
+            fail("--range limited does not apply to svg output");
+        }
+        if let Some(flag) = srgb_chunk_flag {
+            fail(&format!(
+                "--range limited can't be used with {flag}; the sRGB chunk implies full range"
+            ));
+        }
+    }
+    if cli.minimal && !matches!(output_format, OutputFormat::Png) {
+        fail("--minimal only applies to PNG output");
+    }
+    if cli.background_chunk.is_some() && !matches!(output_format, OutputFormat::Png) {
+        fail("--background-chunk only applies to PNG output");
+    }
+    if cli.tile.is_some() && matches!(output_format, OutputFormat::Svg) {
+        fail("--tile does not support svg output");
+    }
+    if (has_background || cli.premultiply) && matches!(output_format, OutputFormat::Svg) {
+        fail("--background and --premultiply do not apply to svg output");
+    }
+    if cli.sidecar_srgb && !matches!(output_format, OutputFormat::Png) {
+        fail("--sidecar-srgb only applies to PNG output");
+    }
+    if cli.append_to.is_some() && !matches!(output_format, OutputFormat::Png) {
+        fail("--append-to only applies to PNG output");
+    }
+    if include_alpha && matches!(output_format, OutputFormat::Ppm) {
+        fail("ppm cannot store alpha; use --output-format pam");
+    }
+    if cli.corner_radius.is_some() {
+        if matches!(output_format, OutputFormat::Svg) {
+            fail("--corner-radius does not apply to svg output");
+        }
+        if has_background || cli.premultiply {
+            fail("--corner-radius cannot be used with --background or --premultiply");
+        }
+    }
+    if has_mask {
+        if matches!(output_format, OutputFormat::Svg) {
+            fail("a --alpha-from mask larger than 1x1 does not apply to svg output");
+        }
+        if has_background || cli.premultiply {
+            fail(
+                "a --alpha-from mask larger than 1x1 cannot be used with --background or --premultiply",
+            );
+        }
+    }
+}
+
+/// Fails for a color outside the gamut under `--assume-input-gamut p3`, and otherwise
+/// warns about it, saying what the gamut mapping did. `subject` names the color and
+/// `prefix` starts a chroma warning with it when there are several colors.
+fn warn_out_of_gamut(
+    encoder: &PixelEncoder,
+    assume_input_gamut: InputGamut,
+    subject: &str,
+    prefix: &str,
+    reduction: Option<ChromaReduction>,
+) {
+    let gamut_name = encoder.gamut_name();
+    match assume_input_gamut {
+        InputGamut::P3 => fail(&format!("{subject} out of {gamut_name} gamut")),
+        InputGamut::Unbounded => {
+            match reduction.filter(|r| r.delta() > 0.0 || r.hue_shift != 0.0) {
+                Some(reduction) => {
+                    let mut changes = Vec::new();
+                    if reduction.delta() > 0.0 {
+                        changes.push(format!(
+                            "reduced chroma {}\u{2192}{}",
+                            format_css_number(reduction.requested),
+                            format_css_number(reduction.chroma)
+                        ));
+                    }
+                    if reduction.hue_shift != 0.0 {
+                        changes.push(format!(
+                            "rotated hue by {}{}\u{b0}",
+                            if reduction.hue_shift > 0.0 { "+" } else { "" },
+                            format_css_number(reduction.hue_shift)
+                        ));
+                    }
+                    eprintln!(
+                        "warning: {prefix}{} to fit {gamut_name}",
+                        changes.join(" and ")
+                    )
+                }
+                None => {
+                    let action = match encoder.gamut_map {
+                        GamutMap::Clip => "clipped",
+                        GamutMap::Css | GamutMap::ChromaHueTradeoff => "gamut-mapped",
+                        GamutMap::LuminancePreserve => "moved toward gray at constant luminance",
+                    };
+                    eprintln!("warning: {subject} out of {gamut_name} gamut; {action}")
+                }
+            }
+        }
+    }
+}
+
+/// `sheet` with `image`, which must be as tall, added as new columns on the right.
+fn append_columns(sheet: &Image, image: &Image, max_pixels: u64) -> Image {
+    let sheet_width = sheet.width();
+    let width = sheet_width
+        .checked_add(image.width())
+        .unwrap_or_else(|| fail("sheet is too large"));
+    check_pixel_count(width, image.height(), max_pixels).unwrap_or_else(|e| fail(&e));
+    Image::from_fn(width, image.height(), |x, y| {
+        if x < sheet_width {
+            sheet.pixel(x, y)
+        } else {
+            image.pixel(x - sheet_width, y)
+        }
+    })
+}
+
+/// Prints what `--emit roundtrip` shows: the requested and recovered colors, and how far
+/// apart they are.
+fn print_roundtrip(requested: (f64, f64, f64), recovered: (f64, f64, f64)) {
+    let show = |(l, c, h): (f64, f64, f64)| {
+        let [l, c, h] = [l, c, h].map(|value| format_css_number((value * 1e5).round() / 1e5));
+        format!("oklch({l} {c} {h})")
+    };
+    println!("requested {}", show(requested));
+    println!("recovered {}", show(recovered));
+    println!("deltaEOK {:.5}", distance::delta_e_ok(requested, recovered));
+}
+
+/// Writes `sidecar` as `<name>.srgb.png` next to `path`, and a `<name>.json` manifest
+/// saying whether each of the two files was clipped.
+fn write_srgb_sidecar(path: &Path, sidecar: &ImageOutput, p3_clipped: bool, srgb_clipped: bool) {
+    let sidecar_path = path.with_extension("srgb.png");
+    if let Err(err) = write_file(&sidecar_path, |writer| sidecar.write(writer)) {
+        fail(&format!(
+            "failed to write {}: {err}",
+            sidecar_path.display()
+        ));
+    }
+    let manifest_path = path.with_extension("json");
+    let entry = |file: &Path, clipped: bool| {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        format!(
+            "{{\"path\": {}, \"clipped\": {clipped}}}",
+            json_string(&name)
+        )
+    };
+    let manifest = format!(
+        "{{\n  \"display-p3\": {},\n  \"srgb\": {}\n}}\n",
+        entry(path, p3_clipped),
+        entry(&sidecar_path, srgb_clipped)
+    );
+    if let Err(err) = write_file(&manifest_path, |writer| {
+        writer.write_all(manifest.as_bytes())
+    }) {
+        fail(&format!(
+            "failed to write {}: {err}",
+            manifest_path.display()
+        ));
+    }
+}
+
+/// The finished image and everything needed to write it in one output format.
+struct ImageOutput<'a> {
+    format: OutputFormat,
+    image: &'a Image,
+    bit_depth: u8,
+    include_alpha: bool,
+    png_options: PngOptions,
+    /// The sRGB hex color an SVG falls back to where `color(display-p3 ...)` is unsupported.
+    svg_fallback: String,
+    channel_order: sample::ChannelOrder,
+    endian: sample::Endian,
+    pam_tuple_type: PamTupleType,
+}
+
+impl ImageOutput<'_> {
+    fn write(&self, mut writer: &mut dyn Write) -> io::Result<()> {
+        let image = self.image;
+        match self.format {
+            OutputFormat::Png => write_png(
+                &mut writer,
+                image,
+                self.bit_depth,
+                self.include_alpha,
+                &self.png_options,
+            ),
+            // SVG rules out tiles, masks, and rounded corners, so the image is one solid color.
+            OutputFormat::Svg => write_svg(
+                &mut writer,
+                image.width(),
+                image.height(),
+                self.include_alpha,
+                image.pixel(0, 0),
+                &self.svg_fallback,
+            ),
+            OutputFormat::Raw => write_raw(
+                &mut writer,
+                image,
+                self.bit_depth,
+                self.include_alpha,
+                self.channel_order,
+                self.endian,
+            ),
+            OutputFormat::Ppm => write_netpbm(&mut writer, image, self.bit_depth, None),
+            OutputFormat::Pam => write_netpbm(
+                &mut writer,
+                image,
+                self.bit_depth,
+                Some(self.pam_tuple_type),
+            ),
+            OutputFormat::Qoi => write_qoi(
+                &mut writer,
+                image,
+                self.include_alpha,
+                self.png_options.transfer,
+            ),
+        }
+    }
+}
+
+/// How each color becomes a stored pixel: gamut mapping into the target, the neutral
+/// snap, compositing and premultiplying, then the output transfer, matrix, and range.
+struct PixelEncoder {
+    gamut_map: GamutMap,
+    precision: Precision,
+    matrix: P3Matrix,
+    map_options: GamutMapOptions,
+    max_hue_shift: f64,
+    /// Map into sRGB even when the output space is Display P3.
+    map_into_srgb: bool,
+    snap_neutrals: bool,
+    background: Option<(f64, f64, f64)>,
+    premultiply: bool,
+    transfer: Transfer,
+    range: sample::SampleRange,
+    output_matrix: Option<OutputMatrix>,
+    bit_depth: u8,
+}
+
+impl PixelEncoder {
+    fn from_cli(
+        cli: &Cli,
+        matrix: P3Matrix,
+        transfer: Transfer,
+        range: sample::SampleRange,
+        background: Option<(f64, f64, f64)>,
+        bit_depth: u8,
+    ) -> Self {
+        PixelEncoder {
+            gamut_map: cli.gamut_map,
+            precision: cli.precision,
+            matrix,
+            map_options: GamutMapOptions {
+                jnd: cli.gamut_map_jnd.unwrap_or(gamut::DEFAULT_JND),
+                clip_final: cli.gamut_map_clip_final,
+            },
+            max_hue_shift: cli.max_hue_shift.unwrap_or(gamut::DEFAULT_MAX_HUE_SHIFT),
+            // sRGB is the only gamut smaller than an output space, so it is the only real
+            // target.
+            map_into_srgb: cli.gamut_target == Some(GamutTarget::Srgb),
+            snap_neutrals: cli.snap_neutrals,
+            background,
+            premultiply: cli.premultiply,
+            transfer,
+            range,
+            output_matrix: cli.output_matrix,
+            bit_depth,
+        }
+    }
+
+    /// The gamut colors are mapped into, as warnings name it.
+    fn gamut_name(&self) -> &'static str {
+        if self.map_into_srgb {
+            "sRGB"
+        } else {
+            "Display P3"
+        }
+    }
+
+    /// Encodes one color for output in `space`, with whether it had to be brought into
+    /// gamut and, for the chroma-reducing maps, by how much.
+    fn encode(
+        &self,
+        space: ColorSpace,
+        color: &(f64, f64, f64, f64),
+    ) -> (Pixel, bool, Option<ChromaReduction>) {
+        let (pixel, clipped, reduction) = self.composite(space, color);
+        (self.store(pixel), clipped, reduction)
+    }
+
+    /// The first half of [`encode`](Self::encode): the color brought into gamut and
+    /// composited, still in sRGB-curve samples with the full 0..1 range.
+    fn composite(
+        &self,
+        space: ColorSpace,
+        &(l, c, h, alpha): &(f64, f64, f64, f64),
+    ) -> (Pixel, bool, Option<ChromaReduction>) {
+        let map_space = if self.map_into_srgb {
+            ColorSpace::Srgb
+        } else {
+            space
+        };
+        let (mut pixel, clipped, reduction) = match self.gamut_map {
+            GamutMap::Clip if self.precision == Precision::F32 => {
+                color32::encoded_pixel(map_space, l, c, h, alpha)
+                    .map(|(pixel, clipped)| (pixel, clipped, None))
+            }
+            GamutMap::Clip => encoded_pixel(map_space, self.matrix, l, c, h, alpha)
+                .map(|(pixel, clipped)| (pixel, clipped, None)),
+            GamutMap::Css => {
+                css_gamut_map(map_space, self.matrix, (l, c, h), alpha, self.map_options)
+                    .map(|(pixel, reduction)| (pixel, reduction.is_some(), reduction))
+            }
+            GamutMap::ChromaHueTradeoff => tradeoff_gamut_map(
+                map_space,
+                self.matrix,
+                (l, c, h),
+                alpha,
+                self.map_options,
+                self.max_hue_shift,
+            )
+            .map(|(pixel, reduction)| (pixel, reduction.is_some(), reduction)),
+            GamutMap::LuminancePreserve => {
+                luminance_preserving_map(map_space, self.matrix, (l, c, h), alpha)
+                    .map(|(pixel, moved)| (pixel, moved, None))
+            }
+        }
+        .unwrap_or_else(|e| fail(&e.to_string()));
+        if map_space != space {
+            // Re-encode the sRGB-safe color in the output space.
+            let (r, g, b) = color::srgb_to_display_p3(pixel.r, pixel.g, pixel.b);
+            pixel = Pixel { r, g, b, ..pixel };
+        }
+        // An overshoot on an exact black or white is matrix rounding, not a real clip.
+        let snapped = snap_neutral_extremes(l, c, pixel);
+        let (pixel, clipped) = if self.snap_neutrals && snapped != pixel {
+            (snapped, false)
+        } else {
+            (pixel, clipped)
+        };
+        // Composite, then premultiply; `store` applies the transfer and range.
+        let background = self.background.map(|(l, c, h)| {
+            let (background, _) = encoded_pixel(space, self.matrix, l, c, h, 1.0)
+                .unwrap_or_else(|e| fail(&e.to_string()));
+            background
+        });
+        let pixel = color::compose_pixel(pixel, background, self.premultiply);
+        (pixel, clipped, reduction)
+    }
+
+    /// The second half of [`encode`](Self::encode): applies the transfer, the sample
+    /// range, and any `--output-matrix` to a [`composite`](Self::composite) pixel.
+    fn store(&self, pixel: Pixel) -> Pixel {
+        let transferred = |value: f64| self.transfer.from_srgb(value);
+        let (r, g, b) = (
+            transferred(pixel.r),
+            transferred(pixel.g),
+            transferred(pixel.b),
+        );
+        let luma = |value: f64| sample::apply_range(value, self.bit_depth, self.range);
+        let chroma = |value: f64| sample::apply_chroma_range(value, self.bit_depth, self.range);
+        match self.output_matrix {
+            None => Pixel {
+                r: luma(r),
+                g: luma(g),
+                b: luma(b),
+                a: pixel.a,
+            },
+            Some(matrix) => {
+                let (y, cb, cr) = color::rgb_to_ycbcr(matrix.as_matrix(), r, g, b);
+                Pixel {
+                    r: luma(y),
+                    g: chroma(cb),
+                    b: chroma(cr),
+                    a: pixel.a,
+                }
+            }
+        }
+    }
+}
+
+fn print_nearest_named_color(l: f64, c: f64, h: f64) {
+    let (name, distance) = nearest_named_color(l, c, h);
+    println!("closest named color: {name} (\u{394}Eok {distance:.2})");
+}
+
+/// Parses a whitespace-separated `L C H [A]` color.
+fn parse_color_spec(spec: &str) -> Result<(f64, f64, f64, Option<f64>), String> {
+    parse_color_spec_in(InputScale::Css, spec)
+}
+
+/// [`parse_color_spec`] with L and C read in `scale`.
+fn parse_color_spec_in(
+    scale: InputScale,
+    spec: &str,
+) -> Result<(f64, f64, f64, Option<f64>), String> {
+    let parts: Vec<&str> = spec.split_whitespace().collect();
+    match parts.as_slice() {
+        [l, c, h] => parse_color_in(scale, l, c, h, None),
+        [l, c, h, a] => parse_color_in(scale, l, c, h, Some(a)),
+        _ => return Err(format!("expected `L C H [A]`, got `{spec}`")),
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// Writes one 1x1 Display P3 PNG of a theme pair, warning if the color was clipped.
+fn write_theme_variant(
+    path: &str,
+    (l, c, h, a): (f64, f64, f64, Option<f64>),
+    variant: &str,
+    bit_depth: u8,
+) -> io::Result<()> {
+    let (pixel, clipped) = encoded_pixel(
+        ColorSpace::DisplayP3,
+        P3Matrix::Baked,
+        l,
+        c,
+        h,
+        a.unwrap_or(1.0),
+    )
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if clipped {
+        eprintln!("warning: {variant} color out of Display P3 gamut; clipped");
+    }
+    write_file(Path::new(path), |writer| {
+        write_png(
+            writer,
+            &Image::solid(1, 1, pixel),
+            bit_depth,
+            a.is_some(),
+            &PngOptions::default(),
+        )
+    })
+}
+
+/// Renders the wheel and returns it with the fraction of in-circle pixels that didn't
+/// need clipping.
+fn hue_wheel(
+    size: u32,
+    l: f64,
+    max_chroma: f64,
+    out_of_gamut: WheelOutOfGamut,
+    marker: Option<Pixel>,
+) -> Result<(Image, f64), oklch_pixel::Error> {
+    let transparent = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 0.0,
+    };
+    let (gray, _) = encoded_pixel(ColorSpace::DisplayP3, P3Matrix::Baked, l, 0.0, 0.0, 1.0)?;
+    let radius = f64::from(size) / 2.0;
+    let (mut inside, mut in_gamut) = (0u64, 0u64);
+    let mut error = None;
+
+    let image = Image::from_fn(size, size, |x, y| {
+        let dx = f64::from(x) + 0.5 - radius;
+        let dy = radius - (f64::from(y) + 0.5);
+        let distance = dx.hypot(dy) / radius;
+        if distance > 1.0 || error.is_some() {
+            return transparent;
+        }
+        inside += 1;
+        let hue = dy.atan2(dx).to_degrees();
+        match encoded_pixel(
+            ColorSpace::DisplayP3,
+            P3Matrix::Baked,
+            l,
+            distance * max_chroma,
+            hue,
+            1.0,
+        ) {
+            Ok((pixel, false)) => {
+                in_gamut += 1;
+                pixel
+            }
+            Ok((pixel, true)) => match (marker, out_of_gamut) {
+                (Some(marker), _) => marker,
+                (None, WheelOutOfGamut::Clip) => pixel,
+                (None, WheelOutOfGamut::Mark) => gray,
+            },
+            Err(err) => {
+                error = Some(err);
+                transparent
+            }
+        }
+    });
+
+    match error {
+        Some(err) => Err(err),
+        None => Ok((image, in_gamut as f64 / inside.max(1) as f64)),
+    }
+}
+
+/// Reads the PNG at `path` back and undoes the encoding of the pixel at `(x, y)`: the
+/// range, then the transfer, then the primaries, to OKLCH. Hue is 0 for a neutral.
+fn read_back_oklch(
+    path: &Path,
+    (x, y): (u32, u32),
+    color_space: ColorSpace,
+    matrix: P3Matrix,
+    transfer: Transfer,
+    range: sample::SampleRange,
+) -> io::Result<(f64, f64, f64)> {
+    let decoded = png::read_png(&mut io::BufReader::new(File::open(path)?))?;
+    let image = &decoded.image;
+    let pixel = image.pixel(x.min(image.width() - 1), y.min(image.height() - 1));
+    let linear = [pixel.r, pixel.g, pixel.b]
+        .map(|value| transfer.to_linear(sample::remove_range(value, decoded.bit_depth, range)));
+    let (l, a, b) = color::linear_to_oklab(color_space, matrix, linear[0], linear[1], linear[2]);
+    let c = a.hypot(b);
+    let h = if c < color::NEUTRAL_SNAP_EPSILON {
+        0.0
+    } else {
+        b.atan2(a).to_degrees().rem_euclid(360.0)
+    };
+    Ok((l, c, h))
+}
+
+/// The Display P3 pixel for an `--oog-marker` color, failing if it doesn't parse.
+fn oog_marker_pixel(spec: &str) -> Pixel {
+    let (l, c, h, a) = parse_color_spec(spec).unwrap_or_else(|e| fail(&e));
+    let (pixel, clipped) = encoded_pixel(
+        ColorSpace::DisplayP3,
+        P3Matrix::Baked,
+        l,
+        c,
+        h,
+        a.unwrap_or(1.0),
+    )
+    .unwrap_or_else(|e| fail(&e.to_string()));
+    if clipped {
+        eprintln!("warning: the --oog-marker color is out of Display P3 gamut; clipped");
+    }
+    pixel
+}
+
+/// Writes the 1x1 Display P3 PNG for `from-rgb`, `hsl`, and `hsv` from sRGB samples in
+/// 0..1.
+fn write_srgb_pixel(
+    (r, g, b): (f64, f64, f64),
+    alpha: Option<f64>,
+    bit_depth: BitDepth,
+    output_file: &Path,
+) {
+    let (r, g, b) = color::srgb_to_display_p3(r, g, b);
+    let pixel = Pixel {
+        r,
+        g,
+        b,
+        a: alpha.unwrap_or(1.0),
+    };
+    let result = write_file(output_file, |writer| {
+        write_png(
+            writer,
+            &Image::solid(1, 1, pixel),
+            bit_depth.as_u8(),
+            alpha.is_some(),
+            &PngOptions::default(),
+        )
+    });
+    if let Err(err) = result {
+        fail(&format!("failed to write {}: {err}", output_file.display()));
+    }
+}
+
+/// Reads an HSL or HSV saturation, lightness, value, or alpha in the `--input-scale`
+/// range, failing if it is outside it, and returns it in 0..1.
+fn cylindrical_component(input_scale: InputScaleArg) -> impl Fn(&str, f64) -> f64 {
+    let max = match input_scale {
+        InputScaleArg::Css => 1.0,
+        InputScaleArg::Percent100 => 100.0,
+    };
+    move |name, value| {
+        if !(0.0..=max).contains(&value) {
+            fail(&format!("{name} must be between 0 and {max}"));
+        }
+        value / max
+    }
+}
+
+/// Renders the L-C plane at hue `h` for `gamut-explorer`, sized to `$COLUMNS` by `$LINES`
+/// (80 by 24 when they aren't exported), with `mark` shown as `@` and a closing line on
+/// how far its lightness reaches into the gamut.
+fn gamut_explorer(
+    space: ColorSpace,
+    h: f64,
+    max_chroma: f64,
+    mark: Option<(f64, f64)>,
+) -> Result<String, oklch_pixel::Error> {
+    const LABEL: &str = "0.00 |";
+    let terminal = |name: &str, default: usize| {
+        env::var(name)
+            .ok()
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .unwrap_or(default)
+    };
+    let columns = terminal("COLUMNS", 80).saturating_sub(LABEL.len()).max(1);
+    // A title, the axis, its labels, and the closing line.
+    let rows = terminal("LINES", 24).saturating_sub(4).max(1);
+    let color = env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+    let in_gamut = |l: f64, c: f64| -> Result<bool, oklch_pixel::Error> {
+        let (_, clipped) = encoded_pixel(space, P3Matrix::Baked, l, c, h, 1.0)?;
+        Ok(!clipped)
+    };
+    let gamut_name = match space {
+        ColorSpace::DisplayP3 => "Display P3",
+        ColorSpace::Srgb => "sRGB",
+    };
+    // The cell each coordinate falls in, with the same centers as the rows and columns.
+    let cell = |value: f64, count: usize| ((value * count as f64) as usize).min(count - 1);
+    let mark_cell = mark.map(|(l, c)| (cell(1.0 - l, rows), cell(c / max_chroma, columns)));
+
+    let mut map = format!(
+        "hue {} in {gamut_name}, chroma 0 to {}\n",
+        format_css_number(h),
+        format_css_number(max_chroma)
+    );
+    for row in 0..rows {
+        let l = 1.0 - (row as f64 + 0.5) / rows as f64;
+        map.push_str(&format!("{l:.2} |"));
+        for column in 0..columns {
+            let c = (column as f64 + 0.5) / columns as f64 * max_chroma;
+            let inside = in_gamut(l, c)?;
+            let marked = mark_cell == Some((row, column));
+            match (color, inside, marked) {
+                (true, true, _) => {
+                    let [r, g, b] = srgb_bytes(l, c, h)?;
+                    let glyph = if marked { '@' } else { ' ' };
+                    let ink = if l > 0.6 { "0;0;0" } else { "255;255;255" };
+                    map.push_str(&format!(
+                        "\x1b[38;2;{ink}m\x1b[48;2;{r};{g};{b}m{glyph}\x1b[0m"
+                    ));
+                }
+                (_, _, true) => map.push('@'),
+                (false, true, false) => map.push('#'),
+                (_, false, false) => map.push('.'),
+            }
+        }
+        map.push('\n');
+    }
+    map.push_str(&format!("     +{}\n", "-".repeat(columns)));
+    let right = format_css_number(max_chroma);
+    map.push_str(&format!(
+        "      0{right:>width$}\n",
+        width = columns.saturating_sub(1).max(right.len())
+    ));
+
+    if let Some((l, c)) = mark {
+        // The edge of the gamut along this lightness, to within 0.0001.
+        let (mut inside, mut outside) = (0.0, 0.5);
+        while outside - inside > 0.0001 {
+            let middle = (inside + outside) / 2.0;
+            if in_gamut(l, middle)? {
+                inside = middle;
+            } else {
+                outside = middle;
+            }
+        }
+        let at = format!(
+            "oklch({} {} {})",
+            format_css_number(l),
+            format_css_number(c),
+            format_css_number(h)
+        );
+        let status = if in_gamut(l, c)? { "inside" } else { "outside" };
+        map.push_str(&format!(
+            "@ {at} is {status} {gamut_name}, whose chroma at L {} reaches {:.4}\n",
+            format_css_number(l),
+            inside
+        ));
+    }
+    Ok(map)
+}
+
+/// Draws the L-C plane at hue `h` for `gamut-diff`, returning the image and the shares
+/// of the plane inside sRGB, inside only Display P3, and outside both.
+fn gamut_diff(
+    width: u32,
+    height: u32,
+    h: f64,
+    max_chroma: f64,
+    marker: Option<Pixel>,
+) -> Result<(Image, [f64; 3]), oklch_pixel::Error> {
+    let transparent = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 0.0,
+    };
+    let mut counts = [0u64; 3];
+    let mut error = None;
+
+    let image = Image::from_fn(width, height, |x, y| {
+        if error.is_some() {
+            return transparent;
+        }
+        let l = 1.0 - (f64::from(y) + 0.5) / f64::from(height);
+        let c = (f64::from(x) + 0.5) / f64::from(width) * max_chroma;
+        let result = encoded_pixel(ColorSpace::Srgb, P3Matrix::Baked, l, c, h, 1.0).and_then(
+            |(_, srgb_clipped)| {
+                let (p3, p3_clipped) =
+                    encoded_pixel(ColorSpace::DisplayP3, P3Matrix::Baked, l, c, h, 1.0)?;
+                Ok((p3, srgb_clipped, p3_clipped))
+            },
+        );
+        match result {
+            Ok((_, false, _)) => {
+                counts[0] += 1;
+                let (gray, _) =
+                    encoded_pixel(ColorSpace::DisplayP3, P3Matrix::Baked, l, 0.0, 0.0, 1.0)
+                        .unwrap_or((transparent, false));
+                gray
+            }
+            Ok((pixel, true, false)) => {
+                counts[1] += 1;
+                pixel
+            }
+            Ok((_, true, true)) => {
+                counts[2] += 1;
+                marker.unwrap_or(transparent)
+            }
+            Err(err) => {
+                error = Some(err);
+                transparent
+            }
+        }
+    });
+
+    match error {
+        Some(err) => Err(err),
+        None => {
+            let total = (u64::from(width) * u64::from(height)) as f64;
+            Ok((image, counts.map(|count| count as f64 / total)))
+        }
+    }
+}
+
+/// Lays out one swatch per color on a white sheet, with the color's `L C H [/ A]` drawn in
+/// black below it. Labels are drawn at double size when they fit the cell.
+fn contact_sheet(
+    colors: &[(f64, f64, f64, Option<f64>)],
+    columns: u32,
+    cell_size: u32,
+) -> Result<Image, oklch_pixel::Error> {
+    const MARGIN: u32 = 8;
+    const LABEL_GAP: u32 = 4;
+
+    let mut cells = Vec::with_capacity(colors.len());
+    for (index, &(l, c, h, a)) in colors.iter().enumerate() {
+        let (pixel, clipped) = encoded_pixel(
+            ColorSpace::DisplayP3,
+            P3Matrix::Baked,
+            l,
+            c,
+            h,
+            a.unwrap_or(1.0),
+        )?;
+        if clipped {
+            eprintln!(
+                "warning: color {} out of Display P3 gamut; clipped",
+                index + 1
+            );
+        }
+        let mut label = [l, c, h].map(format_css_number).join(" ");
+        if let Some(alpha) = a {
+            label = format!("{label} / {}", format_css_number(alpha));
+        }
+        let scale = if font::text_width(&label, 2) <= cell_size {
+            2
+        } else {
+            1
+        };
+        cells.push((pixel, label, scale));
+    }
+
+    let label_height = 2 * font::GLYPH_HEIGHT;
+    let columns = columns.min(colors.len() as u32);
+    let rows = (colors.len() as u32).div_ceil(columns);
+    let pitch_x = cell_size + MARGIN;
+    let pitch_y = cell_size + LABEL_GAP + label_height + MARGIN;
+    let white = Pixel {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+    let black = Pixel {
+        r: 0.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+
+    Ok(Image::from_fn(
+        MARGIN + columns * pitch_x,
+        MARGIN + rows * pitch_y,
+        |x, y| {
+            let (Some(x), Some(y)) = (x.checked_sub(MARGIN), y.checked_sub(MARGIN)) else {
+                return white;
+            };
+            let index = ((y / pitch_y) * columns + x / pitch_x) as usize;
+            let (x, y) = (x % pitch_x, y % pitch_y);
+            let Some((pixel, label, scale)) = cells.get(index) else {
+                return white;
+            };
+            if x >= cell_size {
+                white
+            } else if y < cell_size {
+                *pixel
+            } else if let Some(label_y) = y.checked_sub(cell_size + LABEL_GAP)
+                && x < font::text_width(label, *scale)
+                && font::text_pixel(label, *scale, x, label_y)
+            {
+                black
+            } else {
+                white
+            }
+        },
+    ))
+}
+
+/// Times `iterations` OKLCH to encoded Display P3 conversions. Inputs come from a
+/// xorshift generator with a fixed seed and are built before the clock starts. For f32,
+/// also reports the largest difference from the f64 path in 8-bit steps.
+fn run_bench(iterations: u64, precision: Precision) {
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let inputs: Vec<(f64, f64, f64)> = (0..iterations)
+        .map(|_| (next(), next() * 0.4, next() * 360.0))
+        .collect();
+
+    let start = Instant::now();
+    match precision {
+        Precision::F64 => {
+            for &(l, c, h) in &inputs {
+                if let Ok((r, g, b, clipped)) = color::oklch_to_display_p3_linear(l, c, h) {
+                    hint::black_box((
+                        color::srgb_encode(r),
+                        color::srgb_encode(g),
+                        color::srgb_encode(b),
+                        clipped,
+                    ));
+                }
+            }
+        }
+        Precision::F32 => {
+            for &(l, c, h) in &inputs {
+                hint::black_box(color32::encoded_pixel(ColorSpace::DisplayP3, l, c, h, 1.0).ok());
+            }
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!("{iterations} conversions in {elapsed:.3} s");
+    println!("{:.1} ns/conversion", elapsed * 1e9 / iterations as f64);
+    println!("{:.0} conversions/sec", iterations as f64 / elapsed);
+
+    if let Precision::F32 = precision {
+        let mut worst: f64 = 0.0;
+        for &(l, c, h) in &inputs {
+            let f64_pixel = encoded_pixel(ColorSpace::DisplayP3, P3Matrix::Baked, l, c, h, 1.0);
+            let f32_pixel = color32::encoded_pixel(ColorSpace::DisplayP3, l, c, h, 1.0);
+            if let (Ok((wide, _)), Ok((narrow, _))) = (f64_pixel, f32_pixel) {
+                for (a, b) in [(wide.r, narrow.r), (wide.g, narrow.g), (wide.b, narrow.b)] {
+                    worst = worst.max((a - b).abs() * 255.0);
+                }
+            }
+        }
+        println!("max difference from f64: {worst:.5} of an 8-bit step");
+    }
+}
+
+/// Prints what `--json-schema` shows: one JSON Schema (draft 2020-12) with a definition
+/// for each kind of JSON written, namely `batch --emit jsonl` lines, `capabilities --json`,
+/// and the `--sidecar-srgb` and `--manifest` manifests. The enumerations come from the
+/// same lists those commands print, so they can't drift apart.
+fn print_json_schema() {
+    let quoted = |names: Vec<String>| {
+        names
+            .iter()
+            .map(|name| json_string(name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let formats = quoted(value_names::<OutputFormat>());
+    let transfers = quoted(value_names::<TransferArg>());
+    let bit_depths = value_names::<BitDepth>().join(", ");
+    let gamut_maps = quoted(value_names::<GamutMap>());
+    let sidecar_entry = r#"{
+          "type": "object",
+          "required": ["path", "clipped"],
+          "properties": {
+            "path": { "type": "string", "description": "File name, relative to the manifest" },
+            "clipped": { "type": "boolean" }
+          },
+          "additionalProperties": false
+        }"#;
+    println!(
+        r#"{{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "oklch-pixel JSON output",
+  "$defs": {{
+    "batch-result": {{
+      "description": "One line of `batch --emit jsonl`",
+      "oneOf": [
+        {{
+          "type": "object",
+          "required": ["input", "output", "samples", "clipped"],
+          "properties": {{
+            "input": {{ "type": "string" }},
+            "output": {{ "type": "string", "description": "Path of the PNG written" }},
+            "samples": {{
+              "type": "array",
+              "description": "Stored R, G, B, and (with alpha) A samples",
+              "items": {{ "type": "integer", "minimum": 0, "maximum": 65535 }},
+              "minItems": 3,
+              "maxItems": 4
+            }},
+            "clipped": {{ "type": "boolean" }}
+          }},
+          "additionalProperties": false
+        }},
+        {{
+          "type": "object",
+          "required": ["input", "error"],
+          "properties": {{
+            "input": {{ "type": "string" }},
+            "error": {{ "type": "string" }}
+          }},
+          "additionalProperties": false
+        }}
+      ]
+    }},
+    "capabilities": {{
+      "description": "The output of `capabilities --json`",
+      "type": "object",
+      "required": ["color_spaces", "transfer_functions", "output_formats", "bit_depths"],
+      "properties": {{
+        "color_spaces": {{
+          "type": "array",
+          "items": {{
+            "type": "object",
+            "required": ["name", "cicp_primaries"],
+            "properties": {{
+              "name": {{ "type": "string" }},
+              "cicp_primaries": {{ "type": "integer", "minimum": 0, "maximum": 255 }}
+            }}
+          }}
+        }},
+        "transfer_functions": {{
+          "type": "array",
+          "items": {{
+            "type": "object",
+            "required": ["name", "cicp_transfer"],
+            "properties": {{
+              "name": {{ "enum": [{transfers}] }},
+              "cicp_transfer": {{ "type": "integer", "minimum": 0, "maximum": 255 }}
+            }}
+          }}
+        }},
+        "output_formats": {{ "type": "array", "items": {{ "enum": [{formats}] }} }},
+        "bit_depths": {{ "type": "array", "items": {{ "enum": [{bit_depths}] }} }}
+      }}
+    }},
+    "sidecar-manifest": {{
+      "description": "The <name>.json written by --sidecar-srgb",
+      "type": "object",
+      "required": ["display-p3", "srgb"],
+      "properties": {{
+        "display-p3": {sidecar_entry},
+        "srgb": {sidecar_entry}
+      }},
+      "additionalProperties": false
+    }},
+    "manifest": {{
+      "description": "The <name>.json written by --manifest and `batch --manifest`",
+      "type": "object",
+      "required": [
+        "path", "colors", "color_space", "transfer", "bit_depth", "format", "width", "height",
+        "gamut_map", "clipped", "crc32"
+      ],
+      "properties": {{
+        "path": {{ "type": "string", "description": "File name, relative to the manifest" }},
+        "colors": {{
+          "type": "array",
+          "description": "The OKLCH colors converted, one per tile",
+          "items": {{
+            "type": "object",
+            "required": ["l", "c", "h", "alpha"],
+            "properties": {{
+              "l": {{ "type": "number" }},
+              "c": {{ "type": "number" }},
+              "h": {{ "type": "number" }},
+              "alpha": {{ "type": "number" }}
+            }},
+            "additionalProperties": false
+          }},
+          "minItems": 1
+        }},
+        "color_space": {{ "enum": ["display-p3", "srgb"] }},
+        "transfer": {{ "enum": [{transfers}] }},
+        "bit_depth": {{ "enum": [{bit_depths}] }},
+        "format": {{ "enum": [{formats}] }},
+        "width": {{ "type": "integer", "minimum": 1 }},
+        "height": {{ "type": "integer", "minimum": 1 }},
+        "gamut_map": {{ "enum": [{gamut_maps}] }},
+        "clipped": {{ "type": "boolean" }},
+        "crc32": {{ "type": "string", "pattern": "^[0-9a-f]{{8}}$" }}
+      }},
+      "additionalProperties": false
+    }}
+  }}
+}}"#
+    );
+}
+
+/// Prints what this build supports, as plain text or as a single JSON object.
+fn print_capabilities(json: bool) {
+    let color_spaces = [
+        ("display-p3", ColorSpace::DisplayP3),
+        ("srgb", ColorSpace::Srgb),
+    ];
+    let transfers = [
+        ("srgb", Transfer::Srgb),
+        ("linear", Transfer::Linear),
+        ("custom", Transfer::Custom(CustomTransfer::default())),
+    ];
+    let formats = value_names::<OutputFormat>();
+    let bit_depths = value_names::<BitDepth>();
+
+    if json {
+        let spaces: Vec<String> = color_spaces
+            .iter()
+            .map(|&(name, space)| {
+                let primaries = png::cicp_primaries(space);
+                format!("{{\"name\":\"{name}\",\"cicp_primaries\":{primaries}}}")
+            })
+            .collect();
+        let transfers: Vec<String> = transfers
+            .iter()
+            .map(|&(name, transfer)| {
+                let code = png::cicp_transfer(transfer);
+                format!("{{\"name\":\"{name}\",\"cicp_transfer\":{code}}}")
+            })
+            .collect();
+        let formats: Vec<String> = formats.iter().map(|name| format!("\"{name}\"")).collect();
+        println!(
+            "{{\"color_spaces\":[{}],\"transfer_functions\":[{}],\
+             \"output_formats\":[{}],\"bit_depths\":[{}]}}",
+            spaces.join(","),
+            transfers.join(","),
+            formats.join(","),
+            bit_depths.join(",")
+        );
+        return;
+    }
+
+    println!("color spaces:");
+    for (name, space) in color_spaces {
+        println!("  {name:<12} cICP primaries {}", png::cicp_primaries(space));
+    }
+    println!("transfer functions:");
+    for (name, transfer) in transfers {
+        println!(
+            "  {name:<12} cICP transfer {}",
+            png::cicp_transfer(transfer)
+        );
+    }
+    println!("output formats: {}", formats.join(", "));
+    println!("bit depths: {}", bit_depths.join(", "));
+}
+
+fn value_names<T: ValueEnum>() -> Vec<String> {
+    T::value_variants()
+        .iter()
+        .filter_map(ValueEnum::to_possible_value)
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
+
+/// The name `value` goes by on the command line.
+fn value_name<T: ValueEnum>(value: T) -> String {
+    value
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string())
+}
+
+/// What `--manifest` records about one written file.
+struct Manifest<'a> {
+    path: &'a Path,
+    colors: &'a [(f64, f64, f64, f64)],
+    color_space: ColorSpace,
+    transfer: &'a str,
+    bit_depth: u8,
+    format: &'a str,
+    size: (u32, u32),
+    gamut_map: &'a str,
+    clipped: bool,
+}
+
+impl Manifest<'_> {
+    /// Writes `<name>.json` next to the file, with a CRC-32 of the file as it now is on
+    /// disk.
+    fn write(&self) -> io::Result<()> {
+        let crc = crc32fast::hash(&fs::read(self.path)?);
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let colors: Vec<String> = self
+            .colors
+            .iter()
+            .map(|&(l, c, h, alpha)| {
+                format!("{{\"l\": {l}, \"c\": {c}, \"h\": {h}, \"alpha\": {alpha}}}")
+            })
+            .collect();
+        let color_space = match self.color_space {
+            ColorSpace::DisplayP3 => "display-p3",
+            ColorSpace::Srgb => "srgb",
+        };
+        let manifest = format!(
+            "{{\n  \"path\": {},\n  \"colors\": [{}],\n  \"color_space\": \"{color_space}\",\n  \
+             \"transfer\": {},\n  \"bit_depth\": {},\n  \"format\": {},\n  \
+             \"width\": {},\n  \"height\": {},\n  \"gamut_map\": {},\n  \
+             \"clipped\": {},\n  \"crc32\": \"{crc:08x}\"\n}}\n",
+            json_string(&name),
+            colors.join(", "),
+            json_string(self.transfer),
+            self.bit_depth,
+            json_string(self.format),
+            self.size.0,
+            self.size.1,
+            json_string(self.gamut_map),
+            self.clipped
+        );
+        write_file(&self.path.with_extension("json"), |writer| {
+            writer.write_all(manifest.as_bytes())
+        })
+    }
+}
+
+/// Returns the `--color-env` variable's value, which must be set and not blank.
+fn read_color_env(name: &str) -> Result<String, String> {
+    match env::var(name) {
+        Ok(value) if value.trim().is_empty() => {
+            Err(format!("environment variable {name} is empty"))
+        }
+        Ok(value) => Ok(value),
+        Err(env::VarError::NotPresent) => Err(format!("environment variable {name} is not set")),
+        Err(env::VarError::NotUnicode(_)) => {
+            Err(format!("environment variable {name} is not valid UTF-8"))
+        }
+    }
+}
+
+/// Returns the single color line of a `--color-file`. Blank lines and `#` comments are
+/// skipped; a second color line is an error unless `first_line_only` is set.
+fn read_color_file(path: &Path, first_line_only: bool) -> Result<String, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let mut lines = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    let first = lines
+        .next()
+        .ok_or_else(|| format!("{} contains no color", path.display()))?;
+    if !first_line_only && lines.next().is_some() {
+        return Err(format!(
+            "{} contains more than one color (use --first-line-only to take the first)",
+            path.display()
+        ));
+    }
+    Ok(first.to_string())
+}
+
+/// Prints what `--version-full` reports: everything that decides an image's bytes
+/// besides the flags.
+fn print_version_full() {
+    let features: &[&str] = &[
+        #[cfg(feature = "swatches")]
+        "swatches",
+    ];
+    println!("oklch-pixel {}", env!("CARGO_PKG_VERSION"));
+    println!(
+        "color math: built-in matrix {:08x}, --exact-matrix {:08x}",
+        color::conversion_fingerprint(P3Matrix::Baked),
+        color::conversion_fingerprint(P3Matrix::Derived)
+    );
+    println!(
+        "png: zlib level {}, no row filtering",
+        png::COMPRESSION_LEVEL
+    );
+    println!(
+        "features: {}",
+        if features.is_empty() {
+            "none".to_string()
+        } else {
+            features.join(", ")
+        }
+    );
+}
+
+/// Prints the color in every space the conversion passes through. Linear values are
+/// shown before clipping; encoded values and the hex code are after.
+fn print_all_spaces(l: f64, c: f64, h: f64, p3_matrix: P3Matrix) -> Result<(), oklch_pixel::Error> {
+    let (_, a, b) = color::oklch_to_oklab(l, c, h);
+    color::check_oklab(l, a, b)?;
+    let (x, y, z) = color::oklab_to_xyz(l, a, b);
+    let srgb_linear = color::oklab_to_lin_srgb(l, a, b);
+    let p3_linear = color::xyz_to_lin_display_p3_with(p3_matrix, x, y, z);
+    let (p3, p3_clipped) = encoded_pixel(ColorSpace::DisplayP3, p3_matrix, l, c, h, 1.0)?;
+    let (_, srgb_clipped) = encoded_pixel(ColorSpace::Srgb, p3_matrix, l, c, h, 1.0)?;
+    let clip_note = |clipped: bool| if clipped { " (clipped)" } else { "" };
+
+    let row = |name: &str, (first, second, third): (f64, f64, f64)| {
+        println!("{name:<12} {first:.6} {second:.6} {third:.6}");
+    };
+    row("oklch", (l, c, h));
+    row("oklab", (l, a, b));
+    row("xyz-d65", (x, y, z));
+    row("srgb-linear", srgb_linear);
+    println!(
+        "{:<12} {}{}",
+        "srgb",
+        srgb_hex(l, c, h, None)?,
+        clip_note(srgb_clipped)
+    );
+    row("p3-linear", p3_linear);
+    println!(
+        "{:<12} {:.6} {:.6} {:.6}{}",
+        "p3",
+        p3.r,
+        p3.g,
+        p3.b,
+        clip_note(p3_clipped)
+    );
+    Ok(())
+}
+
+/// Prints what `--emit ts` shows: one exported object per color with its CSS `oklch()`
+/// and `color(display-p3 ...)` strings and an 8-bit sRGB hex fallback, valid as both
+/// TypeScript and JavaScript. `p3` holds the Display P3 pixels, as `--output-format svg`
+/// would write them.
+fn print_ts_consts(colors: &[(f64, f64, f64, f64)], p3: &[Pixel], include_alpha: bool) {
+    for (index, (&(l, c, h, alpha), &pixel)) in colors.iter().zip(p3).enumerate() {
+        let name = if colors.len() == 1 {
+            "COLOR".to_string()
+        } else {
+            format!("COLOR_{}", index + 1)
+        };
+        let oklch = [l, c, h].map(format_css_number).join(" ");
+        let oklch = if include_alpha {
+            format!("{oklch} / {}", format_css_number(alpha))
+        } else {
+            oklch
+        };
+        let hex = srgb_hex(l, c, h, include_alpha.then_some(alpha))
+            .unwrap_or_else(|e| fail(&e.to_string()));
+        if index > 0 {
+            println!();
+        }
+        println!("export const {name} = {{");
+        println!("  oklch: {},", json_string(&format!("oklch({oklch})")));
+        println!(
+            "  displayP3: {},",
+            json_string(&css_display_p3(pixel, include_alpha))
+        );
+        println!("  srgb: {},", json_string(&hex));
+        println!("}};");
+    }
+}
+
+/// Prints what `--emit rust` shows: one `const` array of stored samples per color, each
+/// under a comment naming the OKLCH color it came from.
+fn print_rust_consts(
+    colors: &[(f64, f64, f64, f64)],
+    pixels: &[Pixel],
+    color_space: ColorSpace,
+    bit_depth: u8,
+    include_alpha: bool,
+) {
+    let space = match color_space {
+        ColorSpace::DisplayP3 => "Display P3",
+        ColorSpace::Srgb => "sRGB",
+    };
+    for (index, (&(l, c, h, alpha), &pixel)) in colors.iter().zip(pixels).enumerate() {
+        let mut bytes = Vec::new();
+        sample::push_pixel(
+            &mut bytes,
+            pixel,
+            bit_depth,
+            include_alpha,
+            sample::ChannelOrder::Rgba,
+        );
+        let samples: Vec<String> = if bit_depth == 16 {
+            bytes
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]).to_string())
+                .collect()
+        } else {
+            bytes.iter().map(u8::to_string).collect()
+        };
+        let name = if colors.len() == 1 {
+            "COLOR".to_string()
+        } else {
+            format!("COLOR_{}", index + 1)
+        };
+        let oklch = [l, c, h].map(format_css_number).join(" ");
+        let oklch = if include_alpha {
+            format!("{oklch} / {}", format_css_number(alpha))
+        } else {
+            oklch
+        };
+        if index > 0 {
+            println!();
+        }
+        println!(
+            "// oklch({oklch}) in {space}, {bit_depth}-bit {}",
+            if include_alpha { "RGBA" } else { "RGB" }
+        );
+        println!(
+            "const {name}: [u{bit_depth}; {}] = [{}];",
+            samples.len(),
+            samples.join(", ")
+        );
+    }
+}
+
+/// Prints what `--explain` shows: every stage from OKLCH to stored Display P3 samples,
+/// each with the formula applied and the values it gives.
+fn print_explanation(
+    l: f64,
+    c: f64,
+    h: f64,
+    p3_matrix: P3Matrix,
+    transfer: Transfer,
+    bit_depth: u8,
+) -> Result<(), oklch_pixel::Error> {
+    let step = |name: &str, formula: &str, (first, second, third): (f64, f64, f64)| {
+        println!("{name}\n    {formula}\n    = {first:.6} {second:.6} {third:.6}");
+    };
+    let h_norm = normalize_hue(h);
+    println!("OKLCH\n    = {l:.6} {c:.6} {h_norm:.6}");
+    let (_, a, b) = color::oklch_to_oklab(l, c, h);
+    color::check_oklab(l, a, b)?;
+    step("OKLCH -> Oklab", "a = C cos(h), b = C sin(h)", (l, a, b));
+    step(
+        "Oklab -> LMS",
+        "l = (L + 0.3963377774a + 0.2158037573b)^3\n    m = (L - 0.1055613458a - 0.0638541728b)^3\n    \
+         s = (L - 0.0894841775a - 1.2914855480b)^3",
+        color::oklab_to_lms(l, a, b),
+    );
+    step(
+        "LMS -> linear sRGB",
+        "R = 4.0767416621l - 3.3077115913m + 0.2309699292s\n    \
+         G = -1.2684380046l + 2.6097574011m - 0.3413193965s\n    \
+         B = -0.0041960863l - 0.7034186147m + 1.7076147010s",
+        color::oklab_to_lin_srgb(l, a, b),
+    );
+    let (x, y, z) = color::oklab_to_xyz(l, a, b);
+    step(
+        "linear sRGB -> XYZ D65",
+        "X = 0.4124564R + 0.3575761G + 0.1804375B\n    \
+         Y = 0.2126729R + 0.7151522G + 0.0721750B\n    Z = 0.0193339R + 0.1191920G + 0.9503041B",
+        (x, y, z),
+    );
+    // The matrix's columns are its images of the unit vectors.
+    let columns = [(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)]
+        .map(|(x, y, z)| color::xyz_to_lin_display_p3_with(p3_matrix, x, y, z));
+    let signed = |value: f64| {
+        if value < 0.0 {
+            format!("- {}", -value)
+        } else {
+            format!("+ {value}")
+        }
+    };
+    let row = |name: &str, pick: fn((f64, f64, f64)) -> f64| {
+        format!(
+            "{name} = {}X {}Y {}Z",
+            pick(columns[0]),
+            signed(pick(columns[1])),
+            signed(pick(columns[2]))
+        )
+    };
+    let (r, g, b) = color::xyz_to_lin_display_p3_with(p3_matrix, x, y, z);
+    step(
+        match p3_matrix {
+            P3Matrix::Baked => "XYZ D65 -> linear Display P3 (built-in matrix)",
+            P3Matrix::Derived => "XYZ D65 -> linear Display P3 (--exact-matrix)",
+        },
+        &[
+            row("R", |(r, _, _)| r),
+            row("G", |(_, g, _)| g),
+            row("B", |(_, _, b)| b),
+        ]
+        .join("\n    "),
+        (r, g, b),
+    );
+
+    let out_of_range: Vec<String> = [("R", r), ("G", g), ("B", b)]
+        .iter()
+        .filter(|(_, value)| !(0.0..=1.0).contains(value))
+        .map(|(name, value)| format!("{name} {}", if *value < 0.0 { "< 0" } else { "> 1" }))
+        .collect();
+    let clamp = |value: f64| value.clamp(0.0, 1.0);
+    let clamped = (clamp(r), clamp(g), clamp(b));
+    step(
+        "clamp",
+        &if out_of_range.is_empty() {
+            "each channel to 0..1 (already in range)".to_string()
+        } else {
+            format!(
+                "each channel to 0..1 (clipped: {})",
+                out_of_range.join(", ")
+            )
+        },
+        clamped,
+    );
+    let encoded = match transfer {
+        Transfer::Srgb => {
+            let (r, g, b) = clamped;
+            let encoded = (
+                color::srgb_encode(r),
+                color::srgb_encode(g),
+                color::srgb_encode(b),
+            );
+            step(
+                "sRGB transfer",
+                "V' = 12.92V if V <= 0.0031308, else 1.055V^(1/2.4) - 0.055",
+                encoded,
+            );
+            encoded
+        }
+        Transfer::Linear => {
+            println!("transfer\n    none (--transfer linear)");
+            clamped
+        }
+        Transfer::Custom(curve) => {
+            let (r, g, b) = clamped;
+            let encoded = (curve.encode(r), curve.encode(g), curve.encode(b));
+            step(
+                "custom transfer",
+                &format!(
+                    "V' = {}V if V <= {}, else {}V^(1/{}) - {}",
+                    curve.slope,
+                    curve.threshold,
+                    format_css_number(1.0 + curve.offset()),
+                    curve.gamma,
+                    format_css_number(curve.offset())
+                ),
+                encoded,
+            );
+            encoded
+        }
+    };
+    let max = if bit_depth == 16 { 65535.0 } else { 255.0 };
+    let (r, g, b) = encoded;
+    println!(
+        "quantize to {bit_depth} bits\n    round(V' * {max})\n    = {} {} {}",
+        (r * max).round(),
+        (g * max).round(),
+        (b * max).round()
+    );
+    Ok(())
+}
+
+/// Counts the temp files [`write_file`] creates, so two writes of one path in the same
+/// process (parallel batch jobs, say) never share a temp file.
+static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Writes `path` through a sibling temp file that is renamed into place on success, so
+/// readers never see a partly written file. Paths that exist but are not regular files
+/// (`/dev/stdout`, a FIFO) are written in place.
+fn write_file<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    if fs::metadata(path).is_ok_and(|meta| !meta.is_file()) {
+        return write_file_in_place(path, write);
+    }
+    let Some(name) = path.file_name() else {
+        return write_file_in_place(path, write);
+    };
+    // `create_new` never opens a file someone else made; a leftover or another process's
+    // temp file with the same name just moves on to the next number.
+    let (temp_path, file) = loop {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        let count = TEMP_FILES.fetch_add(1, Ordering::Relaxed);
+        temp_name.push(format!(".{}.{count}.tmp", process::id()));
+        let temp_path = path.with_file_name(temp_name);
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => break (temp_path, file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    };
+
+    let mut writer = BufWriter::new(file);
+    let result = write(&mut writer)
+        .and_then(|()| writer.flush())
+        .and_then(|()| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn write_file_in_place<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer)?;
+    writer.flush()
+}
+
+/// Fails if a `width` by `height` image would have more than `max_pixels` pixels.
+fn check_pixel_count(width: u32, height: u32, max_pixels: u64) -> Result<(), String> {
+    let pixels = u64::from(width) * u64::from(height);
+    if pixels > max_pixels {
+        return Err(format!(
+            "a {width}x{height} image has {pixels} pixels, more than --max-pixels {max_pixels}"
+        ));
+    }
+    Ok(())
+}
+
+/// Writes `<stem>-<slot>.png` for each filled slot of a `.swatches` file, numbering slots
+/// from 1 in Procreate's order.
+#[cfg(feature = "swatches")]
+fn import_swatches(path: &Path) -> io::Result<()> {
+    let swatches = oklch_pixel::swatches::read_swatches(&mut File::open(path)?)?;
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    for (index, swatch) in swatches.iter().enumerate() {
+        let Some(swatch) = swatch else {
+            continue;
+        };
+        let output = path.with_file_name(format!("{stem}-{}.png", index + 1));
+        write_file(&output, |writer| {
+            write_png(
+                writer,
+                &Image::solid(1, 1, swatch.display_p3_pixel()),
+                8,
+                swatch.alpha < 1.0,
+                &PngOptions::default(),
+            )
+        })?;
+        println!("wrote {}", output.display());
+    }
+    Ok(())
+}
+
+/// Reads an `--append-to` sheet, checking that it can take more columns at `bit_depth` in
+/// `color_space`.
+fn read_sheet(path: &Path, bit_depth: u8, color_space: ColorSpace) -> Result<DecodedPng, String> {
+    let describe = |e: io::Error| format!("failed to read {}: {e}", path.display());
+    let mut reader = io::BufReader::new(File::open(path).map_err(describe)?);
+    let sheet = png::read_png(&mut reader).map_err(describe)?;
+    if sheet.bit_depth != bit_depth {
+        return Err(format!(
+            "{} is {}-bit; pass --bit-depth {}",
+            path.display(),
+            sheet.bit_depth,
+            sheet.bit_depth
+        ));
+    }
+    if sheet.color_space.is_some_and(|space| space != color_space) {
+        return Err(format!(
+            "{} is tagged with a different color space than this color",
+            path.display()
+        ));
+    }
+    Ok(sheet)
+}
+
+/// `"black"` or `"white"` if every channel of `pixel` rounds to 0, or every one to the
+/// largest code, at `bit_depth`. `pixel` comes before the transfer and range, so the
+/// result is the same whatever `--transfer` and `--range` store it as.
+fn pure_extreme(pixel: Pixel, bit_depth: u8) -> Option<&'static str> {
+    let max = if bit_depth == 16 { 65535.0 } else { 255.0 };
+    let stored = [pixel.r, pixel.g, pixel.b].map(|value| (value.clamp(0.0, 1.0) * max).round());
+    if stored.iter().all(|&value| value == 0.0) {
+        Some("black")
+    } else if stored.iter().all(|&value| value == max) {
+        Some("white")
+    } else {
+        None
+    }
+}
 
-    #[arg(long, value_name = "path", help = "Explicit output file path")]
-    output_file: Option<String>,
+/// The `--transfer` to store samples with. A custom curve takes sRGB's constant for any of
+/// `--gamma`, `--linear-threshold`, and `--linear-slope` that isn't given, and a warning
+/// names a sharp bend where its two pieces meet.
+fn cli_transfer(cli: &Cli) -> Transfer {
+    let custom =
+        cli.gamma.is_some() || cli.linear_threshold.is_some() || cli.linear_slope.is_some();
+    match cli.transfer {
+        _ if custom && !matches!(cli.transfer, TransferArg::Custom) => {
+            fail("--gamma, --linear-threshold, and --linear-slope only apply to --transfer custom")
+        }
+        TransferArg::Srgb => Transfer::Srgb,
+        TransferArg::Linear => Transfer::Linear,
+        TransferArg::Custom => {
+            let curve = CustomTransfer::new(
+                cli.gamma.unwrap_or(CustomTransfer::SRGB_GAMMA),
+                cli.linear_threshold
+                    .unwrap_or(CustomTransfer::SRGB_THRESHOLD),
+                cli.linear_slope.unwrap_or(CustomTransfer::SRGB_SLOPE),
+            )
+            .unwrap_or_else(|| {
+                fail(
+                    "no continuous, rising curve has that gamma, threshold, and slope; the \
+                     threshold must be below 1 and the linear piece must stay below 1 there",
+                )
+            });
+            if let Some(power_slope) = curve.power_slope_at_threshold()
+                && (power_slope - curve.slope).abs() > 0.1 * curve.slope.max(power_slope)
+            {
+                eprintln!(
+                    "warning: the custom transfer bends sharply at its threshold: the linear \
+                     piece has slope {} and the power piece {}",
+                    format_css_number(curve.slope),
+                    format_css_number(power_slope)
+                );
+            }
+            Transfer::Custom(curve)
+        }
+    }
+}
 
-    #[arg(
-        value_name = "L",
-        help = "Lightness: 0..1 or percent (e.g. 62.5%)."
-    )]
-    l: String,
+/// Applies `--neutral-epsilon`, then `--round-chroma-to` and `--round-hue-to`.
+fn round_chroma_hue(cli: &Cli, c: f64, h: f64) -> (f64, f64) {
+    let c = if c < cli.neutral_epsilon { 0.0 } else { c };
+    let c = cli.round_chroma_to.map_or(c, |step| round_to_step(c, step));
+    let h = cli
+        .round_hue_to
+        .map_or(h, |step| normalize_hue(round_to_step(h, step)));
+    (c, h)
+}
 
-    #[arg(
-        value_name = "C",
-        help = "Chroma (≥ 0)."
-    )]
-    c: String,
+/// Reconciles the requested settings with what `format` can store, returning the bit
+/// depth to write: one the format lacks is replaced by the deepest one it has, with a
+/// warning, or fails under `--strict`.
+fn validate_output_config(format: OutputFormat, bit_depth: BitDepth, strict: bool) -> BitDepth {
+    let supported = format.bit_depths();
+    if supported.contains(&bit_depth) {
+        return bit_depth;
+    }
+    let depths: Vec<String> = supported
+        .iter()
+        .map(|depth| depth.as_u8().to_string())
+        .collect();
+    let message = format!(
+        "{} output is {}-bit only",
+        format.extension(),
+        depths.join("- or ")
+    );
+    if strict {
+        fail(&message);
+    }
+    eprintln!("warning: {message}; writing {}-bit", supported[0].as_u8());
+    supported[0]
+}
 
-    #[arg(
-        value_name = "H",
-        help = "Hue in degrees."
-    )]
-    h: String,
+/// Applies `--chroma-clamp-max` to the chroma given for `subject` (the main color when
+/// `None`), warning when it cuts it down.
+fn clamp_chroma(cli: &Cli, subject: Option<&str>, c: f64) -> f64 {
+    match cli.chroma_clamp_max {
+        Some(max) if c > max => {
+            let subject = subject.map_or_else(String::new, |spec| format!("{spec}: "));
+            eprintln!(
+                "warning: {subject}chroma {} is over --chroma-clamp-max; using {}",
+                format_css_number(c),
+                format_css_number(max)
+            );
+            max
+        }
+        _ => c,
+    }
+}
 
-    #[arg(value_name = "A", help = "Alpha 0..1 (optional). If provided, output is RGBA.")]
-    a: Option<String>,
+/// Applies `--snap-to-web-safe`, printing the web-safe color chosen for `subject` (the
+/// main color when `None`) and how far in deltaEOK that is from the requested one. The
+/// snapped color is exactly what `--hex` with that color would give.
+fn snap_to_web_safe(subject: Option<&str>, (l, c, h): (f64, f64, f64)) -> (f64, f64, f64) {
+    let bytes = color::web_safe_bytes(l, c, h).unwrap_or_else(|e| fail(&e.to_string()));
+    let snapped = color::srgb_to_oklch(bytes);
+    let [r, g, b] = bytes;
+    let subject = subject.map_or_else(String::new, |spec| format!("{spec}: "));
+    eprintln!(
+        "{subject}snapped to web-safe #{r:02x}{g:02x}{b:02x} (deltaEOK {:.4})",
+        distance::delta_e_ok((l, c, h), snapped)
+    );
+    snapped
 }
 
-#[derive(Subcommand, Debug)]
-enum Commands {
-    #[command(about = "Generate shell completions")]
-    GenerateCompletions {
-        #[arg(value_enum, value_name = "shell")]
-        shell: CompletionShell,
-    },
+/// Reads an `--alpha-from` mask.
+fn read_mask(path: &Path) -> Result<Image, String> {
+    let describe = |e: io::Error| format!("failed to read {}: {e}", path.display());
+    let mut reader = io::BufReader::new(File::open(path).map_err(describe)?);
+    Ok(png::read_png(&mut reader).map_err(describe)?.image)
 }
 
-#[derive(Parser, Debug)]
-#[command(name = "oklch-pixel")]
-struct CompletionCli {
-    #[command(subcommand)]
-    command: Commands,
+/// The alpha a mask pixel stands for: its Rec. 709 luma, which for a grayscale mask is
+/// simply the gray level. The mask's own alpha channel is ignored.
+fn mask_alpha(pixel: Pixel) -> f64 {
+    (0.2126 * pixel.r + 0.7152 * pixel.g + 0.0722 * pixel.b).clamp(0.0, 1.0)
 }
 
-#[derive(Copy, Clone, Debug, ValueEnum)]
-enum BitDepth {
-    #[value(name = "8")]
-    Eight,
-    #[value(name = "16")]
-    Sixteen,
+/// How much of pixel (`x`, `y`) lies inside a `width` by `height` rectangle with corners
+/// of `radius`, from the signed distance of the pixel's center to the edge. Coverage is
+/// an area, so it scales alpha directly, with no transfer curve involved. The radius is
+/// capped at half the shorter side.
+fn rounded_rect_coverage(x: u32, y: u32, width: u32, height: u32, radius: f64) -> f64 {
+    let (half_width, half_height) = (f64::from(width) / 2.0, f64::from(height) / 2.0);
+    let radius = radius.min(half_width).min(half_height);
+    // Distance from the center, folded into one quadrant, relative to the corner circle.
+    let dx = (f64::from(x) + 0.5 - half_width).abs() - (half_width - radius);
+    let dy = (f64::from(y) + 0.5 - half_height).abs() - (half_height - radius);
+    let outside = dx.max(0.0).hypot(dy.max(0.0));
+    let distance = outside + dx.max(dy).min(0.0) - radius;
+    (0.5 - distance).clamp(0.0, 1.0)
 }
 
-impl BitDepth {
-    fn as_u8(self) -> u8 {
-        match self {
-            BitDepth::Eight => 8,
-            BitDepth::Sixteen => 16,
+/// Appends one CSV row for a clipped color, writing the header first if the file is new
+/// or empty.
+fn append_clip_report(
+    path: &Path,
+    &(l, c, h, alpha): &(f64, f64, f64, f64),
+    p3_matrix: P3Matrix,
+    output: &Path,
+) -> io::Result<()> {
+    let (r, g, b) = color::display_p3_overshoot(p3_matrix, l, c, h)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    if file.metadata()?.len() == 0 {
+        writeln!(
+            file,
+            "l,c,h,alpha,r_overshoot,g_overshoot,b_overshoot,output"
+        )?;
+    }
+    let output = output.display().to_string();
+    let output = if output.contains([',', '"', '\n']) {
+        format!("\"{}\"", output.replace('"', "\"\""))
+    } else {
+        output
+    };
+    writeln!(file, "{l},{c},{h},{alpha},{r:.6},{g:.6},{b:.6},{output}")
+}
+
+/// The outcome of one batch line.
+struct BatchResult {
+    input: String,
+    output: Result<BatchOutput, String>,
+}
+
+struct BatchOutput {
+    name: Option<String>,
+    color: (f64, f64, f64, Option<f64>),
+    path: String,
+    pixel: Pixel,
+    include_alpha: bool,
+    clipped: bool,
+}
+
+/// Converts and writes every color on stdin, `jobs` at a time, reporting results in input
+/// order as soon as each one and everything before it is done. Clipped colors go to
+/// `clip_report` once every job has finished. Returns whether every line succeeded.
+fn run_batch(
+    bit_depth: u8,
+    emit: Option<BatchEmit>,
+    jobs: usize,
+    manifest: bool,
+    clip_report: Option<&Path>,
+    on_collision: OnCollision,
+) -> io::Result<bool> {
+    let lines: Vec<String> = io::stdin()
+        .lock()
+        .lines()
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    let planned = plan_batch(&lines, on_collision);
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut ok = true;
+    thread::scope(|scope| -> io::Result<()> {
+        for _ in 0..jobs.min(lines.len()) {
+            let sender = sender.clone();
+            let (next, lines, planned) = (&next, &lines, &planned);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(line) = lines.get(index) else { break };
+                    let result = batch_line(line, &planned[index], bit_depth, manifest);
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut stdout = io::stdout().lock();
+        let mut pending = BTreeMap::new();
+        let mut reported = 0;
+        let mut css_vars = Vec::new();
+        let mut clipped = Vec::new();
+        for (index, result) in receiver {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&reported) {
+                reported += 1;
+                ok &= report_batch_result(&mut stdout, &result, emit, bit_depth)?;
+                if let Ok(output) = result.output {
+                    if output.clipped {
+                        let (l, c, h, a) = output.color;
+                        clipped.push(((l, c, h, a.unwrap_or(1.0)), PathBuf::from(&output.path)));
+                    }
+                    css_vars.push((css_property_name(output.name.as_deref(), reported), output));
+                }
+            }
+        }
+        if let Some(BatchEmit::CssVars) = emit {
+            write_css_vars(&mut stdout, &css_vars)?;
+        }
+        if let Some(report) = clip_report {
+            for (color, path) in &clipped {
+                if let Err(err) = append_clip_report(report, color, P3Matrix::Baked, path) {
+                    eprintln!("error: failed to write {}: {err}", report.display());
+                    ok = false;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    })?;
+    Ok(ok)
+}
+
+/// A batch line's color, its optional name, and the file it is written to.
+#[derive(Clone)]
+struct BatchJob {
+    name: Option<String>,
+    color: (f64, f64, f64, Option<f64>),
+    path: String,
+    /// Another line writes the file: the earlier line this one repeats or, under
+    /// `--on-collision overwrite`, the last line to claim the name.
+    shared: bool,
+}
+
+/// Parses every batch line and picks its file name before anything is written, so two
+/// lines whose colors round to the same default name are caught whatever order the jobs
+/// finish in. A later line that would take an earlier one's name is failed, renamed with
+/// a CRC-32 of its input, or left to overwrite, as `policy` says; each case is reported.
+/// Only the last line to overwrite a file writes it, so its contents don't depend on
+/// which job finishes last. A line that repeats an earlier one exactly isn't a collision;
+/// it shares the earlier line's file and doesn't write it again.
+fn plan_batch(lines: &[String], policy: OnCollision) -> Vec<Result<BatchJob, String>> {
+    let mut owners: BTreeMap<String, &str> = BTreeMap::new();
+    let mut written: BTreeMap<&str, String> = BTreeMap::new();
+    let mut jobs: Vec<Result<BatchJob, String>> = lines
+        .iter()
+        .map(|line| {
+            let (spec, name) = match line.split_once(',') {
+                Some((spec, name)) => (spec.trim(), Some(name.trim().to_string())),
+                None => (line.as_str(), None),
+            };
+            let (l, c, h, a) = parse_color_spec(spec)?;
+            if let Some(path) = written.get(line.as_str()) {
+                eprintln!("warning: {line}: repeats an earlier line, already written as {path}");
+                return Ok(BatchJob {
+                    name,
+                    color: (l, c, h, a),
+                    path: path.clone(),
+                    shared: true,
+                });
+            }
+            let mut path = default_output_name(l, c, h, a, "png");
+            if let Some(&owner) = owners.get(&path) {
+                match policy {
+                    OnCollision::Error => {
+                        return Err(format!(
+                            "{path} is already the file for {owner}; pass --on-collision \
+                             suffix or overwrite to write it anyway"
+                        ));
+                    }
+                    OnCollision::Suffix => {
+                        let hash = crc32fast::hash(line.as_bytes());
+                        let renamed = with_name_suffix(&path, &format!("-{hash:08x}"));
+                        eprintln!(
+                            "warning: {line}: {path} is already the file for {owner}; \
+                             writing {renamed} instead"
+                        );
+                        path = renamed;
+                    }
+                    OnCollision::Overwrite => {
+                        eprintln!("warning: {line}: overwriting {path}, the file for {owner}");
+                    }
+                }
+            }
+            owners.entry(path.clone()).or_insert(line);
+            written.insert(line, path.clone());
+            Ok(BatchJob {
+                name,
+                color: (l, c, h, a),
+                path,
+                shared: false,
+            })
+        })
+        .collect();
+
+    if let OnCollision::Overwrite = policy {
+        // Collecting keeps the last index for each path.
+        let last_writer: BTreeMap<String, usize> = jobs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, job)| match job {
+                Ok(job) if !job.shared => Some((job.path.clone(), index)),
+                _ => None,
+            })
+            .collect();
+        for (index, job) in jobs.iter_mut().enumerate() {
+            if let Ok(job) = job
+                && !job.shared
+                && last_writer[&job.path] != index
+            {
+                job.shared = true;
+            }
         }
     }
+    jobs
 }
 
-#[derive(Copy, Clone, Debug, ValueEnum)]
-enum CompletionShell {
-    #[value(name = "bash")]
-    Bash,
-    #[value(name = "elvish")]
-    Elvish,
-    #[value(name = "fish")]
-    Fish,
-    #[value(name = "powershell", alias = "power-shell")]
-    PowerShell,
-    #[value(name = "zsh")]
-    Zsh,
+fn batch_line(
+    line: &str,
+    job: &Result<BatchJob, String>,
+    bit_depth: u8,
+    manifest: bool,
+) -> BatchResult {
+    let output = job.clone().and_then(|job| {
+        let BatchJob {
+            name,
+            color,
+            path,
+            shared,
+        } = job;
+        let (l, c, h, a) = color;
+        let (pixel, clipped) = encoded_pixel(
+            ColorSpace::DisplayP3,
+            P3Matrix::Baked,
+            l,
+            c,
+            h,
+            a.unwrap_or(1.0),
+        )
+        .map_err(|e| e.to_string())?;
+        // A shared file is written once, by the line that owns it.
+        if !shared {
+            write_file(Path::new(&path), |writer| {
+                write_png(
+                    writer,
+                    &Image::solid(1, 1, pixel),
+                    bit_depth,
+                    a.is_some(),
+                    &PngOptions::default(),
+                )
+            })
+            .map_err(|e| format!("failed to write {path}: {e}"))?;
+            if manifest {
+                Manifest {
+                    path: Path::new(&path),
+                    colors: &[(l, c, h, a.unwrap_or(1.0))],
+                    color_space: ColorSpace::DisplayP3,
+                    transfer: &value_name(TransferArg::Srgb),
+                    bit_depth,
+                    format: OutputFormat::Png.extension(),
+                    size: (1, 1),
+                    gamut_map: &value_name(GamutMap::Clip),
+                    clipped,
+                }
+                .write()
+                .map_err(|e| format!("failed to write the manifest for {path}: {e}"))?;
+            }
+        }
+        Ok(BatchOutput {
+            name,
+            color: (l, c, h, a),
+            path,
+            pixel,
+            include_alpha: a.is_some(),
+            clipped,
+        })
+    });
+    BatchResult {
+        input: line.to_string(),
+        output,
+    }
 }
 
-impl CompletionShell {
-    fn as_shell(self) -> Shell {
-        match self {
-            CompletionShell::Bash => Shell::Bash,
-            CompletionShell::Elvish => Shell::Elvish,
-            CompletionShell::Fish => Shell::Fish,
-            CompletionShell::PowerShell => Shell::PowerShell,
-            CompletionShell::Zsh => Shell::Zsh,
+/// Prints warnings and errors to stderr and, with `--emit jsonl`, one JSON object to
+/// `stdout`. Returns whether the line succeeded.
+fn report_batch_result(
+    stdout: &mut impl Write,
+    result: &BatchResult,
+    emit: Option<BatchEmit>,
+    bit_depth: u8,
+) -> io::Result<bool> {
+    let input = json_string(&result.input);
+    match &result.output {
+        Ok(output) => {
+            if output.clipped {
+                eprintln!("warning: {} out of Display P3 gamut; clipped", result.input);
+            }
+            if let Some(BatchEmit::Jsonl) = emit {
+                let max = if bit_depth == 16 { 65535.0 } else { 255.0 };
+                let pixel = output.pixel;
+                let mut samples = vec![pixel.r, pixel.g, pixel.b];
+                if output.include_alpha {
+                    samples.push(pixel.a);
+                }
+                let samples: Vec<String> = samples
+                    .iter()
+                    .map(|value| format!("{}", (value.clamp(0.0, 1.0) * max).round()))
+                    .collect();
+                writeln!(
+                    stdout,
+                    "{{\"input\":{input},\"output\":{},\"samples\":[{}],\"clipped\":{}}}",
+                    json_string(&output.path),
+                    samples.join(","),
+                    output.clipped
+                )?;
+            }
+            Ok(true)
+        }
+        Err(err) => {
+            eprintln!("error: {}: {err}", result.input);
+            if let Some(BatchEmit::Jsonl) = emit {
+                writeln!(
+                    stdout,
+                    "{{\"input\":{input},\"error\":{}}}",
+                    json_string(err)
+                )?;
+            }
+            Ok(false)
         }
     }
 }
 
-#[derive(Clone, Copy)]
-struct Pixel {
-    r: f64,
-    g: f64,
-    b: f64,
-    a: f64,
+/// The custom property for the `index`th batch color (counting from 1): its name as
+/// lowercase ASCII letters, digits, `-`, and `_`, with any run of other characters
+/// turned into one `-`, or `color-<index>` when there is no usable name.
+fn css_property_name(name: Option<&str>, index: usize) -> String {
+    let mut property = String::new();
+    for ch in name.unwrap_or_default().chars() {
+        if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+            property.push(ch.to_ascii_lowercase());
+        } else if !property.is_empty() && !property.ends_with('-') {
+            property.push('-');
+        }
+    }
+    let property = property.trim_matches('-');
+    if property.is_empty() {
+        format!("--color-{index}")
+    } else {
+        format!("--{property}")
+    }
 }
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.get(1).map(String::as_str) == Some("generate-completions") {
-        let completion_cli = CompletionCli::parse_from(&args);
-        let Commands::GenerateCompletions { shell } = completion_cli.command;
-        let mut cmd = Cli::command();
-        let bin_name = cmd.get_name().to_string();
-        generate(shell.as_shell(), &mut cmd, bin_name, &mut io::stdout());
-        return;
+/// Prints what `--emit css-vars` shows: a `:root` rule giving each color as Display P3,
+/// then the same properties as OKLCH for browsers that support it. A custom property
+/// takes whatever value is declared last, so the OKLCH ones sit behind `@supports`.
+fn write_css_vars(stdout: &mut impl Write, colors: &[(String, BatchOutput)]) -> io::Result<()> {
+    writeln!(stdout, ":root {{")?;
+    for (property, output) in colors {
+        writeln!(
+            stdout,
+            "  {property}: {};",
+            css_display_p3(output.pixel, output.include_alpha)
+        )?;
+    }
+    writeln!(stdout, "}}")?;
+    writeln!(stdout)?;
+    writeln!(stdout, "@supports (color: oklch(0 0 0)) {{")?;
+    writeln!(stdout, "  :root {{")?;
+    for (property, output) in colors {
+        let (l, c, h, a) = output.color;
+        let oklch = [l, c, h].map(format_css_number).join(" ");
+        let oklch = match a {
+            Some(a) => format!("{oklch} / {}", format_css_number(a)),
+            None => oklch,
+        };
+        writeln!(stdout, "    {property}: oklch({oklch});")?;
     }
+    writeln!(stdout, "  }}")?;
+    writeln!(stdout, "}}")
+}
 
-    let cli = Cli::parse_from(&args);
+/// A JSON string literal for `value`.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            ch if u32::from(ch) < 0x20 => quoted.push_str(&format!("\\u{:04x}", u32::from(ch))),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
 
-    let l_str = cli.l;
-    let c_str = cli.c;
-    let h_str = cli.h;
-    let a_str = cli.a;
+fn run_repl(bit_depth: u8, preview_size: (u16, u16)) -> io::Result<()> {
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut stdout = io::stdout();
+    let mut last: Option<(Pixel, bool)> = None;
 
-    let include_alpha = a_str.is_some();
-    let l = parse_l(&l_str).unwrap_or_else(|e| fail(&e));
-    let c = parse_non_negative(&c_str, "C").unwrap_or_else(|e| fail(&e));
-    let h = parse_f64(&h_str, "H").unwrap_or_else(|e| fail(&e));
-    let alpha = match a_str {
-        Some(value) => parse_unit_range(&value, "A").unwrap_or_else(|e| fail(&e)),
-        None => 1.0,
-    };
+    loop {
+        if interactive {
+            write!(stdout, "> ")?;
+            stdout.flush()?;
+        }
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "quit" || line == "exit" {
+            break;
+        }
 
-    let output = cli
-        .output_file
-        .unwrap_or_else(|| default_output_name(l, c, h, include_alpha.then_some(alpha)));
-    let bit_depth = cli.bit_depth.as_u8();
+        if let Some(path) = line.strip_prefix("save ") {
+            let path = path.trim();
+            match last {
+                Some((pixel, include_alpha)) => {
+                    let result = write_file(Path::new(path), |writer| {
+                        write_png(
+                            writer,
+                            &Image::solid(1, 1, pixel),
+                            bit_depth,
+                            include_alpha,
+                            &PngOptions::default(),
+                        )
+                    });
+                    match result {
+                        Ok(()) => writeln!(stdout, "wrote {path}")?,
+                        Err(err) => eprintln!("error: failed to write PNG: {err}"),
+                    }
+                }
+                None => eprintln!("error: no color to save yet"),
+            }
+            continue;
+        }
 
-    let (r_lin, g_lin, b_lin, clipped) = oklch_to_display_p3_linear(l, c, h)
-        .unwrap_or_else(|e| fail(&e));
-    if clipped {
-        eprintln!("warning: color out of Display P3 gamut; clipped");
+        match describe_repl_line(line, preview_size) {
+            Ok((description, pixel, include_alpha)) => {
+                write!(stdout, "{description}")?;
+                last = Some((pixel, include_alpha));
+            }
+            Err(err) => eprintln!("error: {err}"),
+        }
     }
+    Ok(())
+}
 
-    let pixel = Pixel {
-        r: srgb_encode(r_lin),
-        g: srgb_encode(g_lin),
-        b: srgb_encode(b_lin),
-        a: alpha,
+fn describe_repl_line(
+    line: &str,
+    preview_size: (u16, u16),
+) -> Result<(String, Pixel, bool), String> {
+    if !(3..=4).contains(&line.split_whitespace().count()) {
+        return Err("expected `L C H [A]`, `save <path>`, or `quit`".to_string());
+    }
+    let (l, c, h, a) = parse_color_spec(line)?;
+    let (pixel, clipped) = encoded_pixel(
+        ColorSpace::DisplayP3,
+        P3Matrix::Baked,
+        l,
+        c,
+        h,
+        a.unwrap_or(1.0),
+    )
+    .map_err(|e| e.to_string())?;
+    let srgb = srgb_bytes(l, c, h).map_err(|e| e.to_string())?;
+
+    let mut p3 = format!(
+        "{} {} {}",
+        format_css_number(pixel.r),
+        format_css_number(pixel.g),
+        format_css_number(pixel.b)
+    );
+    if a.is_some() {
+        p3.push_str(&format!(" / {}", format_css_number(pixel.a)));
+    }
+    let clip = if clipped {
+        "clipped to Display P3"
+    } else {
+        "in gamut"
     };
 
-    if let Err(err) = write_png(Path::new(&output), bit_depth, include_alpha, pixel) {
-        fail(&format!("failed to write PNG: {err}"));
+    let mut description = format!(
+        "srgb  #{:02x}{:02x}{:02x}\np3    {p3}\nclip  {clip}\n",
+        srgb[0], srgb[1], srgb[2]
+    );
+    if let Some(preview) = ansi_preview(srgb, preview_size, "      ") {
+        description.push_str(&preview);
     }
+    Ok((description, pixel, a.is_some()))
 }
 
-fn fail(message: &str) -> ! {
-    eprintln!("error: {message}");
-    eprintln!("Run with --help for usage.");
-    process::exit(1);
+/// Renders `rows` lines of `columns` truecolor spaces, each prefixed with `indent`.
+/// Returns `None` when `NO_COLOR` is set. The width is capped to `$COLUMNS` when the
+/// shell exports it.
+fn ansi_preview(srgb: [u8; 3], (columns, rows): (u16, u16), indent: &str) -> Option<String> {
+    if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return None;
+    }
+    let terminal_width = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(usize::MAX);
+    let columns = usize::from(columns).min(terminal_width.saturating_sub(indent.len()).max(1));
+
+    let [r, g, b] = srgb;
+    let line = format!(
+        "{indent}\x1b[48;2;{r};{g};{b}m{}\x1b[0m\n",
+        " ".repeat(columns)
+    );
+    Some(line.repeat(usize::from(rows)))
 }
 
-fn default_output_name(l: f64, c: f64, h: f64, a: Option<f64>) -> String {
-    let l_str = format_component(l);
-    let c_str = format_component(c);
-    let h_str = format_component(h);
-    if let Some(alpha) = a {
-        let a_str = format_component(alpha);
-        format!(
-            "oklch({} {} {} \u{2215} {}).png",
-            l_str, c_str, h_str, a_str
-        )
-    } else {
-        format!("oklch({} {} {}).png", l_str, c_str, h_str)
+/// Renders a line of sample text in the color on black and on white, then black and
+/// white text on the color, each followed by what it shows. Returns `None` when
+/// `NO_COLOR` is set, as [`ansi_preview`] does.
+fn ansi_text_sample(srgb: [u8; 3]) -> Option<String> {
+    if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return None;
+    }
+    const TEXT: &str = " The quick brown fox jumps over the lazy dog ";
+    let [r, g, b] = srgb;
+    let color = format!("{r};{g};{b}");
+    let mut sample = String::new();
+    for (foreground, background, label) in [
+        (color.as_str(), "0;0;0", "color on black"),
+        (color.as_str(), "255;255;255", "color on white"),
+        ("0;0;0", color.as_str(), "black on color"),
+        ("255;255;255", color.as_str(), "white on color"),
+    ] {
+        sample.push_str(&format!(
+            "\x1b[38;2;{foreground}m\x1b[48;2;{background}m{TEXT}\x1b[0m {label}\n"
+        ));
     }
+    Some(sample)
 }
 
-fn format_component(value: f64) -> String {
-    let mut s = format!("{value}");
-    if s == "-0" || s == "-0.0" {
-        s = "0".to_string();
+fn parse_gamut_map_jnd(input: &str) -> Result<f64, String> {
+    let value = parse_f64(input, "JND").map_err(|e| e.to_string())?;
+    if value <= 0.0 {
+        return Err("JND must be greater than 0".to_string());
     }
-    s
+    Ok(value)
 }
 
-fn parse_l(input: &str) -> Result<f64, String> {
-    if let Some(value) = input.strip_suffix('%') {
-        let parsed = parse_f64(value, "L%")?;
-        if !(0.0..=100.0).contains(&parsed) {
-            return Err("L% must be between 0 and 100".to_string());
-        }
-        Ok(parsed / 100.0)
-    } else {
-        let parsed = parse_f64(input, "L")?;
-        if !(0.0..=1.0).contains(&parsed) {
-            return Err("L must be between 0 and 1 (or use %)".to_string());
-        }
-        Ok(parsed)
+fn parse_target_luminance(input: &str) -> Result<f64, String> {
+    let value = parse_f64(input, "luminance").map_err(|e| e.to_string())?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err("luminance must be between 0 and 1".to_string());
     }
+    Ok(value)
 }
 
-fn parse_non_negative(input: &str, name: &str) -> Result<f64, String> {
-    let value = parse_f64(input, name)?;
-    if value < 0.0 {
-        return Err(format!("{name} must be >= 0"));
+fn parse_corner_radius(input: &str) -> Result<f64, String> {
+    let value = parse_f64(input, "radius").map_err(|e| e.to_string())?;
+    if value <= 0.0 {
+        return Err("radius must be greater than 0".to_string());
     }
     Ok(value)
 }
 
-fn parse_unit_range(input: &str, name: &str) -> Result<f64, String> {
-    let value = parse_f64(input, name)?;
-    if !(0.0..=1.0).contains(&value) {
-        return Err(format!("{name} must be between 0 and 1"));
+fn parse_round_step(input: &str) -> Result<f64, String> {
+    let value = parse_f64(input, "step").map_err(|e| e.to_string())?;
+    if value <= 0.0 {
+        return Err("step must be greater than 0".to_string());
     }
     Ok(value)
 }
 
-fn parse_f64(input: &str, name: &str) -> Result<f64, String> {
-    let value = input
-        .parse::<f64>()
-        .map_err(|_| format!("{name} must be a number"))?;
-    if !value.is_finite() {
-        return Err(format!("{name} must be finite"));
+fn parse_max_hue_shift(input: &str) -> Result<f64, String> {
+    let value = parse_f64(input, "hue shift").map_err(|e| e.to_string())?;
+    if !(0.0..=180.0).contains(&value) {
+        return Err("hue shift must be between 0 and 180 degrees".to_string());
     }
     Ok(value)
 }
 
-fn oklch_to_display_p3_linear(l: f64, c: f64, h_deg: f64) -> Result<(f64, f64, f64, bool), String> {
-    let h = h_deg.rem_euclid(360.0).to_radians();
-    let a = c * h.cos();
-    let b = c * h.sin();
+fn parse_chroma_clamp_max(input: &str) -> Result<f64, String> {
+    parse_non_negative(input, "chroma cap").map_err(|e| e.to_string())
+}
+
+fn parse_neutral_epsilon(input: &str) -> Result<f64, String> {
+    parse_non_negative(input, "neutral epsilon").map_err(|e| e.to_string())
+}
 
-    let (x, y, z) = oklab_to_xyz(l, a, b);
-    let (r, g, b) = xyz_to_lin_display_p3(x, y, z);
+fn parse_gamma(input: &str) -> Result<f64, String> {
+    let value = parse_f64(input, "gamma").map_err(|e| e.to_string())?;
+    if value <= 0.0 {
+        return Err("gamma must be greater than 0".to_string());
+    }
+    Ok(value)
+}
 
-    if !r.is_finite() || !g.is_finite() || !b.is_finite() {
-        return Err("color conversion produced a non-finite value".to_string());
+fn parse_linear_threshold(input: &str) -> Result<f64, String> {
+    let value = parse_f64(input, "threshold").map_err(|e| e.to_string())?;
+    if !(0.0..1.0).contains(&value) {
+        return Err("threshold must be at least 0 and below 1".to_string());
     }
+    Ok(value)
+}
+
+fn parse_linear_slope(input: &str) -> Result<f64, String> {
+    parse_non_negative(input, "slope").map_err(|e| e.to_string())
+}
 
-    let mut clipped = false;
-    let r = clamp01(r, &mut clipped);
-    let g = clamp01(g, &mut clipped);
-    let b = clamp01(b, &mut clipped);
+fn parse_alpha_threshold(input: &str) -> Result<f64, String> {
+    let value = parse_f64(input, "threshold").map_err(|e| e.to_string())?;
+    if !(0.0..=0.5).contains(&value) {
+        return Err("threshold must be between 0 and 0.5".to_string());
+    }
+    Ok(value)
+}
 
-    Ok((r, g, b, clipped))
+fn parse_preview_size(input: &str) -> Result<(u16, u16), String> {
+    let (columns, rows) = input
+        .split_once(['x', 'X'])
+        .ok_or_else(|| "expected WxH, e.g. 8x4".to_string())?;
+    let parse = |value: &str| match value.trim().parse::<u16>() {
+        Ok(0) | Err(_) => Err("width and height must be positive integers".to_string()),
+        Ok(value) => Ok(value),
+    };
+    Ok((parse(columns)?, parse(rows)?))
 }
 
-fn oklab_to_xyz(l: f64, a: f64, b: f64) -> (f64, f64, f64) {
-    let l_ = l + 0.3963377774 * a + 0.2158037573 * b;
-    let m_ = l - 0.1055613458 * a - 0.0638541728 * b;
-    let s_ = l - 0.0894841775 * a - 1.2914855480 * b;
+fn find_config_file() -> Option<PathBuf> {
+    let local = PathBuf::from(CONFIG_FILE_NAME);
+    if local.is_file() {
+        return Some(local);
+    }
 
-    let l = l_ * l_ * l_;
-    let m = m_ * m_ * m_;
-    let s = s_ * s_ * s_;
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    let global = config_dir.join("oklch-pixel").join(CONFIG_FILE_NAME);
+    global.is_file().then_some(global)
+}
 
-    // Oklab is defined over linear sRGB; convert LMS to linear sRGB, then to XYZ D65.
-    let r_lin = 4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s;
-    let g_lin = -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s;
-    let b_lin = -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s;
+/// Flags that pick the input color or replace the output with a printout. As config
+/// defaults they'd silently win over the L C H given on the command line, so the config
+/// file can't set them.
+const PER_RUN_FLAGS: [&str; 7] = [
+    "output-file",
+    "hex",
+    "color-file",
+    "color-env",
+    "import-swatches",
+    "emit",
+    "explain",
+];
 
-    let x = 0.4124564 * r_lin + 0.3575761 * g_lin + 0.1804375 * b_lin;
-    let y = 0.2126729 * r_lin + 0.7151522 * g_lin + 0.0721750 * b_lin;
-    let z = 0.0193339 * r_lin + 0.1191920 * g_lin + 0.9503041 * b_lin;
+fn apply_config_defaults(mut cmd: Command, path: &Path) -> Result<Command, String> {
+    let display = path.display();
+    let text = fs::read_to_string(path).map_err(|e| format!("failed to read {display}: {e}"))?;
 
-    (x, y, z)
+    for (line_number, key, value) in parse_config(&text).map_err(|e| format!("{display}: {e}"))? {
+        if PER_RUN_FLAGS.contains(&key.as_str()) {
+            return Err(format!(
+                "{display}:{line_number}: `{key}` can't be set in a config file; \
+                 pass --{key} on the command line"
+            ));
+        }
+        let id = cmd
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key.as_str()))
+            .map(|arg| arg.get_id().clone())
+            .ok_or_else(|| format!("{display}:{line_number}: unknown setting `{key}`"))?;
+        cmd = cmd.mut_arg(id, |arg| arg.default_value(value));
+    }
+    Ok(cmd)
 }
 
-fn xyz_to_lin_display_p3(x: f64, y: f64, z: f64) -> (f64, f64, f64) {
-    let r = 2.493496911941425 * x - 0.9313836179191239 * y - 0.40271078445071684 * z;
-    let g = -0.8294889695615747 * x + 1.7626640603183463 * y + 0.023624685841943577 * z;
-    let b = 0.03584583024378447 * x - 0.07617238926804182 * y + 0.9568845240076872 * z;
-    (r, g, b)
+/// Parses the flat `key = value` subset of TOML that the config file needs.
+///
+/// Keys are long flag names (underscores are accepted in place of dashes); values may be
+/// quoted strings, numbers, or booleans. Tables and arrays are rejected.
+fn parse_config(text: &str) -> Result<Vec<(usize, String, String)>, String> {
+    let mut entries = Vec::new();
+    for (index, raw_line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = strip_config_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {line_number}: expected `key = value`"))?;
+        let key = key.trim().replace('_', "-");
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+        {
+            return Err(format!("line {line_number}: invalid key `{}`", key.trim()));
+        }
+
+        let value = value.trim();
+        let value = if let Some(quoted) = value
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .or_else(|| {
+                value
+                    .strip_prefix('\'')
+                    .and_then(|rest| rest.strip_suffix('\''))
+            }) {
+            quoted.to_string()
+        } else if value == "true"
+            || value == "false"
+            || (!value.is_empty() && value.parse::<f64>().is_ok())
+        {
+            value.to_string()
+        } else {
+            return Err(format!("line {line_number}: unsupported value for `{key}`"));
+        };
+
+        entries.push((line_number, key, value));
+    }
+    Ok(entries)
 }
 
-fn clamp01(value: f64, clipped: &mut bool) -> f64 {
-    if value < 0.0 {
-        *clipped = true;
-        0.0
-    } else if value > 1.0 {
-        *clipped = true;
-        1.0
-    } else {
-        value
+fn strip_config_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (index, ch) in line.char_indices() {
+        match (quote, ch) {
+            (None, '#') => return &line[..index],
+            (None, '"' | '\'') => quote = Some(ch),
+            (Some(open), _) if ch == open => quote = None,
+            _ => {}
+        }
     }
+    line
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {message}");
+    eprintln!("Run with --help for usage.");
+    process::exit(1);
 }
 
-fn srgb_encode(linear: f64) -> f64 {
-    if linear <= 0.0031308 {
-        12.92 * linear
+/// The longest file name, in bytes, that common file systems (NTFS, APFS, ext4) accept.
+const MAX_FILE_NAME_LEN: usize = 255;
+
+/// Base names Windows reserves for devices, with any extension.
+const RESERVED_WINDOWS_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn default_output_name(l: f64, c: f64, h: f64, a: Option<f64>, extension: &str) -> String {
+    let name = oklch_file_name(format_component, (l, c, h), a, extension);
+    // Plain formatting spells out tiny or huge values in full; round them instead.
+    let name = if name.len() > MAX_FILE_NAME_LEN {
+        oklch_file_name(format_css_number, (l, c, h), a, extension)
     } else {
-        1.055 * linear.powf(1.0 / 2.4) - 0.055
-    }
+        name
+    };
+    portable_file_name(name)
 }
 
-fn write_png(
-    path: &Path,
-    bit_depth: u8,
-    include_alpha: bool,
-    pixel: Pixel,
-) -> io::Result<()> {
-    let mut file = File::create(path)?;
-
-    file.write_all(&PNG_SIGNATURE)?;
-
-    let color_type = if include_alpha { 6 } else { 2 };
-    let mut ihdr = Vec::with_capacity(13);
-    ihdr.extend_from_slice(&1u32.to_be_bytes());
-    ihdr.extend_from_slice(&1u32.to_be_bytes());
-    ihdr.push(bit_depth);
-    ihdr.push(color_type);
-    ihdr.push(0);
-    ihdr.push(0);
-    ihdr.push(0);
-    write_chunk(&mut file, b"IHDR", &ihdr)?;
-
-    let cicp = [
-        CICP_PRIMARIES_DISPLAY_P3,
-        CICP_TRANSFER_SRGB,
-        CICP_MATRIX_IDENTITY,
-        CICP_FULL_RANGE,
-    ];
-    write_chunk(&mut file, b"cICP", &cicp)?;
+/// [`default_output_name`] with every number formatted by [`format_component_pinned`].
+fn pinned_output_name(l: f64, c: f64, h: f64, a: Option<f64>, extension: &str) -> String {
+    portable_file_name(oklch_file_name(
+        format_component_pinned,
+        (l, c, h),
+        a,
+        extension,
+    ))
+}
 
-    let mut raw = Vec::new();
-    raw.push(0);
-    push_sample(&mut raw, pixel.r, bit_depth);
-    push_sample(&mut raw, pixel.g, bit_depth);
-    push_sample(&mut raw, pixel.b, bit_depth);
-    if include_alpha {
-        push_sample(&mut raw, pixel.a, bit_depth);
+fn oklch_file_name(
+    format: fn(f64) -> String,
+    (l, c, h): (f64, f64, f64),
+    a: Option<f64>,
+    extension: &str,
+) -> String {
+    let l_str = format(l);
+    let c_str = format(c);
+    let h_str = format(h);
+    if let Some(alpha) = a {
+        let a_str = format(alpha);
+        format!(
+            "oklch({} {} {} \u{2215} {}).{}",
+            l_str, c_str, h_str, a_str, extension
+        )
+    } else {
+        format!("oklch({} {} {}).{}", l_str, c_str, h_str, extension)
     }
+}
 
-    let compressed = zlib_compress(&raw)?;
-    write_chunk(&mut file, b"IDAT", &compressed)?;
-    write_chunk(&mut file, b"IEND", &[])?;
+/// Inserts `suffix` before the extension of `name`.
+fn with_name_suffix(name: &str, suffix: &str) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) => format!("{stem}{suffix}.{extension}"),
+        None => format!("{name}{suffix}"),
+    }
+}
 
-    Ok(())
+/// Makes `name` valid on Windows as well as Unix: characters Windows forbids become `_`,
+/// trailing dots and spaces are dropped, reserved device names get a `_` prefix, and the
+/// result is cut to [`MAX_FILE_NAME_LEN`] bytes.
+fn portable_file_name(name: String) -> String {
+    let mut name: String = name
+        .chars()
+        .map(|ch| {
+            if ch.is_control() || "<>:\"/\\|?*".contains(ch) {
+                '_'
+            } else {
+                ch
+            }
+        })
+        .collect();
+    let stem = name.split('.').next().unwrap_or_default();
+    if RESERVED_WINDOWS_NAMES
+        .iter()
+        .any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved))
+    {
+        name.insert(0, '_');
+    }
+    if name.len() > MAX_FILE_NAME_LEN {
+        let mut end = MAX_FILE_NAME_LEN;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+    }
+    let trimmed = name.trim_end_matches(['.', ' ']).len();
+    name.truncate(trimmed);
+    name
 }
 
-fn zlib_compress(data: &[u8]) -> io::Result<Vec<u8>> {
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-    encoder.write_all(data)?;
-    encoder.finish()
+/// The output format an `--output-file` extension names, if any (case-insensitive).
+fn format_from_extension(path: &str) -> Option<OutputFormat> {
+    let extension = Path::new(path).extension()?.to_str()?;
+    OutputFormat::value_variants()
+        .iter()
+        .copied()
+        .find(|format| format.extension().eq_ignore_ascii_case(extension))
 }
 
-fn push_sample(buf: &mut Vec<u8>, value: f64, bit_depth: u8) {
-    let clamped = value.clamp(0.0, 1.0);
-    match bit_depth {
-        8 => buf.push((clamped * 255.0).round() as u8),
-        16 => {
-            let sample = (clamped * 65535.0).round() as u16;
-            buf.extend_from_slice(&sample.to_be_bytes());
+/// Standard base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(
+                    ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize],
+                ));
+            } else {
+                encoded.push('=');
+            }
         }
-        _ => {}
     }
+    encoded
 }
 
-fn write_chunk<W: Write>(writer: &mut W, chunk_type: &[u8; 4], data: &[u8]) -> io::Result<()> {
-    let length = u32::try_from(data.len()).map_err(|_| {
-        io::Error::new(io::ErrorKind::InvalidInput, "chunk too large")
-    })?;
-    writer.write_all(&length.to_be_bytes())?;
-    writer.write_all(chunk_type)?;
-    writer.write_all(data)?;
-
-    let mut hasher = Hasher::new();
-    hasher.update(chunk_type);
-    hasher.update(data);
-    let crc = hasher.finalize();
-    writer.write_all(&crc.to_be_bytes())?;
-    Ok(())
+/// Decimal places kept by [`format_component_pinned`].
+const PINNED_DECIMALS: u32 = 6;
+
+/// Formats `value` rounded to [`PINNED_DECIMALS`] places, with trailing zeros dropped and
+/// never an exponent. Only the rounding is floating point; the digits come from integer
+/// formatting, so the output can't change with Rust's float formatting.
+fn format_component_pinned(value: f64) -> String {
+    let unit = 10i128.pow(PINNED_DECIMALS);
+    let scaled = (value * unit as f64).round();
+    // Past i128 the cast would saturate. Floats that large are whole numbers, and fixed
+    // precision formatting prints their exact digits.
+    if scaled.abs() >= i128::MAX as f64 {
+        return format_css_number(value);
+    }
+    let scaled = scaled as i128;
+    let (whole, fraction) = (scaled.abs() / unit, scaled.abs() % unit);
+    let sign = if scaled < 0 { "-" } else { "" };
+    let fraction = format!("{fraction:0width$}", width = PINNED_DECIMALS as usize);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{sign}{whole}")
+    } else {
+        format!("{sign}{whole}.{fraction}")
+    }
+}
+
+fn format_component(value: f64) -> String {
+    let mut s = format!("{value}");
+    if s == "-0" || s == "-0.0" {
+        s = "0".to_string();
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn portable_file_name_prefixes_reserved_device_names() {
+        for (name, expected) in [
+            ("CON", "_CON"),
+            ("con.png", "_con.png"),
+            ("NUL.tar.gz", "_NUL.tar.gz"),
+            ("LPT9 .png", "_LPT9 .png"),
+            ("CONSOLE.png", "CONSOLE.png"),
+            ("COM10.png", "COM10.png"),
+        ] {
+            assert_eq!(portable_file_name(name.to_string()), expected);
+        }
+    }
+
+    #[test]
+    fn portable_file_name_drops_trailing_dots_and_spaces() {
+        for (name, expected) in [
+            ("swatch.", "swatch"),
+            ("swatch. . ", "swatch"),
+            ("swatch.png  ", "swatch.png"),
+            ("a.b", "a.b"),
+        ] {
+            assert_eq!(portable_file_name(name.to_string()), expected);
+        }
+    }
+
+    #[test]
+    fn portable_file_name_replaces_forbidden_characters() {
+        assert_eq!(
+            portable_file_name("a<b>c:d\"e|f?g*h.png".to_string()),
+            "a_b_c_d_e_f_g_h.png"
+        );
+        assert_eq!(
+            portable_file_name("dir/x\\y\t.png".to_string()),
+            "dir_x_y_.png"
+        );
+        let oklch = "oklch(0.5 0.1 40 \u{2215} 0.5).png";
+        assert_eq!(portable_file_name(oklch.to_string()), oklch);
+    }
+
+    #[test]
+    fn portable_file_name_cuts_long_names_on_a_char_boundary() {
+        let name = portable_file_name("\u{e9}".repeat(200));
+        assert!(name.len() <= MAX_FILE_NAME_LEN);
+        assert_eq!(name, "\u{e9}".repeat(MAX_FILE_NAME_LEN / 2));
+    }
+
+    #[test]
+    fn format_component_pinned_rounds_to_six_places_without_exponents() {
+        for (value, expected) in [
+            (0.0, "0"),
+            (-0.0, "0"),
+            (0.5, "0.5"),
+            (0.1 + 0.2, "0.3"),
+            (1.0 / 3.0, "0.333333"),
+            (2.0 / 3.0, "0.666667"),
+            (1e-7, "0"),
+            (-1e-7, "0"),
+            (5e-7, "0.000001"),
+            (123456789.0, "123456789"),
+            (-12.25, "-12.25"),
+            (359.9999996, "360"),
+            (1e40, "10000000000000000303786028427003666890752"),
+            (-1e40, "-10000000000000000303786028427003666890752"),
+        ] {
+            assert_eq!(format_component_pinned(value), expected, "{value:e}");
+        }
+    }
+
+    #[test]
+    fn pinned_output_names_are_stable() {
+        for ((l, c, h, a), expected) in [
+            ((0.5, 0.1, 40.0, None), "oklch(0.5 0.1 40).png"),
+            (
+                (0.7, 0.1 + 0.2, 1.0 / 3.0, None),
+                "oklch(0.7 0.3 0.333333).png",
+            ),
+            (
+                (0.62796, 0.25768, 29.2339, Some(0.5)),
+                "oklch(0.62796 0.25768 29.2339 \u{2215} 0.5).png",
+            ),
+            ((1.0, 1e-9, 0.0, Some(1.0)), "oklch(1 0 0 \u{2215} 1).png"),
+        ] {
+            assert_eq!(pinned_output_name(l, c, h, a, "png"), expected);
+        }
+    }
+
+    #[test]
+    fn only_the_last_overwriting_line_writes_a_file() {
+        let lines: Vec<String> = [
+            "0.5 0.1 40, a",
+            "0.6 0.1 40",
+            "0.50 0.1 40, b",
+            "0.5 0.1 40",
+        ]
+        .map(String::from)
+        .into();
+        let shared = |policy| -> Vec<bool> {
+            plan_batch(&lines, policy)
+                .into_iter()
+                .map(|job| job.unwrap().shared)
+                .collect()
+        };
+        assert_eq!(shared(OnCollision::Overwrite), [true, false, true, false]);
+        assert_eq!(shared(OnCollision::Suffix), [false; 4]);
+    }
+
+    #[test]
+    fn concurrent_writes_of_one_path_each_get_a_temp_file() {
+        let dir = env::temp_dir().join(format!("oklch-pixel-{}-write-file", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shared.txt");
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..16u8)
+                .map(|n| {
+                    let path = &path;
+                    scope.spawn(move || write_file(path, |writer| writer.write_all(&[n; 4096])))
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap().unwrap();
+            }
+        });
+
+        let contents = fs::read(&path).unwrap();
+        assert_eq!(contents.len(), 4096);
+        assert!(contents.iter().all(|&byte| byte == contents[0]));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
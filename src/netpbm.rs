@@ -0,0 +1,140 @@
+use std::io::{self, Write};
+
+use crate::Image;
+use crate::sample::{ChannelOrder, image_samples, push_sample};
+
+/// The PAM `TUPLTYPE`, which fixes `DEPTH` and the channels written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PamTupleType {
+    Grayscale,
+    GrayscaleAlpha,
+    Rgb,
+    RgbAlpha,
+}
+
+impl PamTupleType {
+    /// The tuple type matching the PNG color type this image would get: `RGB_ALPHA` for
+    /// color type 6, `RGB` for color type 2.
+    pub fn for_alpha(include_alpha: bool) -> Self {
+        if include_alpha {
+            PamTupleType::RgbAlpha
+        } else {
+            PamTupleType::Rgb
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PamTupleType::Grayscale => "GRAYSCALE",
+            PamTupleType::GrayscaleAlpha => "GRAYSCALE_ALPHA",
+            PamTupleType::Rgb => "RGB",
+            PamTupleType::RgbAlpha => "RGB_ALPHA",
+        }
+    }
+
+    pub fn depth(self) -> u8 {
+        match self {
+            PamTupleType::Grayscale => 1,
+            PamTupleType::GrayscaleAlpha => 2,
+            PamTupleType::Rgb => 3,
+            PamTupleType::RgbAlpha => 4,
+        }
+    }
+
+    fn has_alpha(self) -> bool {
+        matches!(self, PamTupleType::GrayscaleAlpha | PamTupleType::RgbAlpha)
+    }
+}
+
+/// Writes a binary PPM (`P6`) or, when `pam` gives a tuple type, a PAM (`P7`) image. PPM
+/// has no alpha channel, so alpha is dropped; for PAM the tuple type decides which
+/// channels are written. Grayscale tuple types require every pixel to be neutral at
+/// `bit_depth`.
+pub fn write_netpbm<W: Write>(
+    writer: &mut W,
+    image: &Image,
+    bit_depth: u8,
+    pam: Option<PamTupleType>,
+) -> io::Result<()> {
+    let (width, height) = (image.width(), image.height());
+    let maxval = netpbm_maxval(bit_depth)?;
+    let header = match pam {
+        Some(tuple_type) => format!(
+            "P7\nWIDTH {width}\nHEIGHT {height}\nDEPTH {}\nMAXVAL {maxval}\n\
+             TUPLTYPE {}\nENDHDR\n",
+            tuple_type.depth(),
+            tuple_type.name()
+        ),
+        None => format!("P6\n{width} {height}\n{maxval}\n"),
+    };
+
+    let samples = match pam {
+        Some(tuple_type @ (PamTupleType::Grayscale | PamTupleType::GrayscaleAlpha)) => {
+            gray_samples(image, bit_depth, tuple_type.has_alpha())?
+        }
+        Some(tuple_type) => {
+            image_samples(image, bit_depth, tuple_type.has_alpha(), ChannelOrder::Rgba)
+        }
+        None => image_samples(image, bit_depth, false, ChannelOrder::Rgba),
+    };
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(&samples)
+}
+
+/// Writes a plain (ASCII) PPM (`P3`) image: the same samples as [`write_netpbm`] gives
+/// for `P6`, as decimal numbers with one pixel per line. Alpha is dropped.
+pub fn write_plain_ppm<W: Write>(writer: &mut W, image: &Image, bit_depth: u8) -> io::Result<()> {
+    let maxval = netpbm_maxval(bit_depth)?;
+    writeln!(writer, "P3\n{} {}\n{maxval}", image.width(), image.height())?;
+    let samples = image_samples(image, bit_depth, false, ChannelOrder::Rgba);
+    let sample_len = usize::from(bit_depth / 8);
+    for pixel in samples.chunks_exact(3 * sample_len) {
+        let values: Vec<String> = pixel
+            .chunks_exact(sample_len)
+            .map(|sample| match *sample {
+                [hi, lo] => u16::from_be_bytes([hi, lo]).to_string(),
+                _ => sample[0].to_string(),
+            })
+            .collect();
+        writeln!(writer, "{}", values.join(" "))?;
+    }
+    Ok(())
+}
+
+fn netpbm_maxval(bit_depth: u8) -> io::Result<u32> {
+    match bit_depth {
+        8 => Ok(255),
+        16 => Ok(65535),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Netpbm output needs a bit depth of 8 or 16, not {bit_depth}"),
+        )),
+    }
+}
+
+fn gray_samples(image: &Image, bit_depth: u8, include_alpha: bool) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let pixel = image.pixel(x, y);
+            let mut rgb = Vec::with_capacity(6);
+            for value in [pixel.r, pixel.g, pixel.b] {
+                push_sample(&mut rgb, value, bit_depth);
+            }
+            let sample_len = rgb.len() / 3;
+            if rgb[..sample_len] != rgb[sample_len..2 * sample_len]
+                || rgb[..sample_len] != rgb[2 * sample_len..]
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("pixel ({x}, {y}) is not gray, so it can't be written as GRAYSCALE"),
+                ));
+            }
+            buf.extend_from_slice(&rgb[..sample_len]);
+            if include_alpha {
+                push_sample(&mut buf, pixel.a, bit_depth);
+            }
+        }
+    }
+    Ok(buf)
+}
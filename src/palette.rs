@@ -0,0 +1,429 @@
+use std::io::{self, Write};
+
+/// Writes a Microsoft RIFF palette (`.pal`) holding 8-bit sRGB `colors`.
+pub fn write_ms_pal<W: Write>(writer: &mut W, colors: &[[u8; 3]]) -> io::Result<()> {
+    let count = u16::try_from(colors.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many palette entries"))?;
+    let data_size = 4 + 4 * u32::from(count);
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(4 + 8 + data_size).to_le_bytes())?;
+    writer.write_all(b"PAL ")?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    writer.write_all(&0x0300u16.to_le_bytes())?;
+    writer.write_all(&count.to_le_bytes())?;
+    for &[r, g, b] in colors {
+        writer.write_all(&[r, g, b, 0])?;
+    }
+    Ok(())
+}
+
+/// Writes a macOS color list (`.clr`) called `name`, holding one 8-bit sRGB color per
+/// entry under its key. The file is an `NSColorList` archived by `NSKeyedArchiver` as a
+/// binary property list. Keys should be unique; a color list keeps only the last color
+/// for a repeated key.
+pub fn write_apple_clr<W: Write>(
+    writer: &mut W,
+    name: &str,
+    colors: &[(String, [u8; 3])],
+) -> io::Result<()> {
+    let mut archive = KeyedArchive::default();
+    // Index 0 is always the nil placeholder.
+    archive.object(Plist::String("$null".to_string()));
+
+    let list_class = archive.class(&["NSColorList", "NSObject"]);
+    let array_class = archive.class(&["NSArray", "NSObject"]);
+    let color_class = archive.class(&["NSColor", "NSObject"]);
+    let space_class = archive.class(&["NSColorSpace", "NSObject"]);
+
+    // NSColorSpace 1 is calibrated RGB; the custom space with NSID 7 narrows it to sRGB.
+    let space = archive.instance(space_class, vec![("NSID", Plist::Integer(7))]);
+    let mut keys = Vec::with_capacity(colors.len());
+    let mut values = Vec::with_capacity(colors.len());
+    for (key, [r, g, b]) in colors {
+        keys.push(Plist::Uid(archive.object(Plist::String(key.clone()))));
+        let [r, g, b] = [r, g, b].map(|&byte| f64::from(byte) / 255.0);
+        values.push(Plist::Uid(archive.instance(
+            color_class,
+            vec![
+                ("NSColorSpace", Plist::Integer(1)),
+                ("NSRGB", Plist::Data(format!("{r} {g} {b}\0").into_bytes())),
+                (
+                    "NSComponents",
+                    Plist::Data(format!("{r} {g} {b} 1").into_bytes()),
+                ),
+                ("NSCustomColorSpace", Plist::Uid(space)),
+            ],
+        )));
+    }
+    let keys = archive.instance(array_class, vec![("NS.objects", Plist::Array(keys))]);
+    let values = archive.instance(array_class, vec![("NS.objects", Plist::Array(values))]);
+    let name = archive.object(Plist::String(name.to_string()));
+    let root = archive.instance(
+        list_class,
+        vec![
+            ("NSName", Plist::Uid(name)),
+            ("NSKeys", Plist::Uid(keys)),
+            ("NSColors", Plist::Uid(values)),
+        ],
+    );
+
+    let top = Plist::Dictionary(vec![
+        ("$version".to_string(), Plist::Integer(100_000)),
+        (
+            "$archiver".to_string(),
+            Plist::String("NSKeyedArchiver".to_string()),
+        ),
+        (
+            "$top".to_string(),
+            Plist::Dictionary(vec![("root".to_string(), Plist::Uid(root))]),
+        ),
+        ("$objects".to_string(), Plist::Array(archive.objects)),
+    ]);
+    writer.write_all(&binary_plist(&top))
+}
+
+/// A property list value, as far as a keyed archive needs.
+enum Plist {
+    Integer(u64),
+    String(String),
+    Data(Vec<u8>),
+    /// A reference to an entry of the archive's `$objects`.
+    Uid(u64),
+    Array(Vec<Plist>),
+    Dictionary(Vec<(String, Plist)>),
+}
+
+/// The `$objects` table of an `NSKeyedArchiver` archive, which objects refer to each
+/// other through by index.
+#[derive(Default)]
+struct KeyedArchive {
+    objects: Vec<Plist>,
+}
+
+impl KeyedArchive {
+    fn object(&mut self, value: Plist) -> u64 {
+        self.objects.push(value);
+        self.objects.len() as u64 - 1
+    }
+
+    /// Adds the class description for `classes`, the class name and then its superclasses.
+    fn class(&mut self, classes: &[&str]) -> u64 {
+        let names = classes
+            .iter()
+            .map(|&name| Plist::String(name.to_string()))
+            .collect();
+        self.object(Plist::Dictionary(vec![
+            (
+                "$classname".to_string(),
+                Plist::String(classes[0].to_string()),
+            ),
+            ("$classes".to_string(), Plist::Array(names)),
+        ]))
+    }
+
+    fn instance(&mut self, class: u64, fields: Vec<(&str, Plist)>) -> u64 {
+        let mut members = vec![("$class".to_string(), Plist::Uid(class))];
+        members.extend(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value)),
+        );
+        self.object(Plist::Dictionary(members))
+    }
+}
+
+/// Encodes `top` as a `bplist00` binary property list.
+fn binary_plist(top: &Plist) -> Vec<u8> {
+    let mut table = Vec::new();
+    flatten(top, &mut table);
+    let ref_size = byte_width(table.len() as u64 - 1);
+
+    let mut bytes = b"bplist00".to_vec();
+    let mut offsets = Vec::with_capacity(table.len());
+    let push_refs = |bytes: &mut Vec<u8>, refs: &[usize]| {
+        for &index in refs {
+            bytes.extend_from_slice(&(index as u64).to_be_bytes()[8 - ref_size..]);
+        }
+    };
+    for object in &table {
+        offsets.push(bytes.len() as u64);
+        match *object {
+            Flat::Integer(value) => push_int(&mut bytes, value),
+            Flat::String(text) if text.is_ascii() => {
+                push_marker(&mut bytes, 0x50, text.len());
+                bytes.extend_from_slice(text.as_bytes());
+            }
+            Flat::String(text) => {
+                let units: Vec<u16> = text.encode_utf16().collect();
+                push_marker(&mut bytes, 0x60, units.len());
+                bytes.extend(units.iter().flat_map(|unit| unit.to_be_bytes()));
+            }
+            Flat::Data(data) => {
+                push_marker(&mut bytes, 0x40, data.len());
+                bytes.extend_from_slice(data);
+            }
+            Flat::Uid(value) => {
+                let width = byte_width(value);
+                bytes.push(0x80 | (width as u8 - 1));
+                bytes.extend_from_slice(&value.to_be_bytes()[8 - width..]);
+            }
+            Flat::Array(ref items) => {
+                push_marker(&mut bytes, 0xa0, items.len());
+                push_refs(&mut bytes, items);
+            }
+            Flat::Dictionary(ref keys, ref values) => {
+                push_marker(&mut bytes, 0xd0, keys.len());
+                push_refs(&mut bytes, keys);
+                push_refs(&mut bytes, values);
+            }
+        }
+    }
+
+    let offset_table = bytes.len() as u64;
+    let offset_size = byte_width(offset_table);
+    for offset in offsets {
+        bytes.extend_from_slice(&offset.to_be_bytes()[8 - offset_size..]);
+    }
+    bytes.extend_from_slice(&[0; 6]);
+    bytes.extend_from_slice(&[offset_size as u8, ref_size as u8]);
+    bytes.extend_from_slice(&(table.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(&0u64.to_be_bytes()); // the top object
+    bytes.extend_from_slice(&offset_table.to_be_bytes());
+    bytes
+}
+
+/// One entry of a binary plist's object table; containers hold indices into the table.
+enum Flat<'a> {
+    Integer(u64),
+    String(&'a str),
+    Data(&'a [u8]),
+    Uid(u64),
+    Array(Vec<usize>),
+    Dictionary(Vec<usize>, Vec<usize>),
+}
+
+/// Appends `value` and everything under it to `table`, returning its index.
+fn flatten<'a>(value: &'a Plist, table: &mut Vec<Flat<'a>>) -> usize {
+    let index = table.len();
+    table.push(match value {
+        Plist::Integer(value) => Flat::Integer(*value),
+        Plist::String(text) => Flat::String(text),
+        Plist::Data(data) => Flat::Data(data),
+        Plist::Uid(value) => Flat::Uid(*value),
+        Plist::Array(_) => Flat::Array(Vec::new()),
+        Plist::Dictionary(_) => Flat::Dictionary(Vec::new(), Vec::new()),
+    });
+    match value {
+        Plist::Array(items) => {
+            let items = items.iter().map(|item| flatten(item, table)).collect();
+            table[index] = Flat::Array(items);
+        }
+        Plist::Dictionary(members) => {
+            let keys = members
+                .iter()
+                .map(|(key, _)| {
+                    table.push(Flat::String(key));
+                    table.len() - 1
+                })
+                .collect();
+            let values = members
+                .iter()
+                .map(|(_, value)| flatten(value, table))
+                .collect();
+            table[index] = Flat::Dictionary(keys, values);
+        }
+        _ => {}
+    }
+    index
+}
+
+/// The fewest of 1, 2, 4, or 8 bytes that hold `value`.
+fn byte_width(value: u64) -> usize {
+    match value {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xffff_ffff => 4,
+        _ => 8,
+    }
+}
+
+fn push_int(bytes: &mut Vec<u8>, value: u64) {
+    let width = byte_width(value);
+    bytes.push(0x10 | width.trailing_zeros() as u8);
+    bytes.extend_from_slice(&value.to_be_bytes()[8 - width..]);
+}
+
+/// A type marker with `len` in its low nibble, or in a following integer from 15 up.
+fn push_marker(bytes: &mut Vec<u8>, kind: u8, len: usize) {
+    if len < 15 {
+        bytes.push(kind | len as u8);
+    } else {
+        bytes.push(kind | 0x0f);
+        push_int(bytes, len as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ms_pal_has_a_riff_header_and_sizes() {
+        let mut bytes = Vec::new();
+        write_ms_pal(&mut bytes, &[[0xff, 0x80, 0x00], [0x01, 0x02, 0x03]]).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                b"RIFF".as_slice(),
+                &24u32.to_le_bytes(), // "PAL " plus the 20-byte data chunk
+                b"PAL data",
+                &12u32.to_le_bytes(), // version, count, and two entries
+                &[0x00, 0x03, 2, 0],
+                &[0xff, 0x80, 0x00, 0],
+                &[0x01, 0x02, 0x03, 0],
+            ]
+            .concat()
+        );
+        assert_eq!(bytes.len(), 8 + 24);
+    }
+
+    /// A decoded binary plist value, for checking what [`binary_plist`] wrote.
+    #[derive(Debug, PartialEq)]
+    enum Value {
+        Integer(u64),
+        String(String),
+        Data(Vec<u8>),
+        Uid(u64),
+        Array(Vec<Value>),
+        Dictionary(Vec<(String, Value)>),
+    }
+
+    impl Value {
+        fn get(&self, key: &str) -> &Value {
+            match self {
+                Value::Dictionary(members) => {
+                    &members.iter().find(|(name, _)| name == key).unwrap().1
+                }
+                _ => panic!("{self:?} is not a dictionary"),
+            }
+        }
+    }
+
+    fn read_plist(bytes: &[u8]) -> Value {
+        assert_eq!(&bytes[..8], b"bplist00");
+        let trailer = &bytes[bytes.len() - 32..];
+        let (offset_size, ref_size) = (usize::from(trailer[6]), usize::from(trailer[7]));
+        let number = |bytes: &[u8]| bytes.iter().fold(0u64, |n, &b| n << 8 | u64::from(b));
+        let count = number(&trailer[8..16]) as usize;
+        let top = number(&trailer[16..24]) as usize;
+        let table = number(&trailer[24..32]) as usize;
+        let offsets: Vec<usize> = (0..count)
+            .map(|i| number(&bytes[table + i * offset_size..][..offset_size]) as usize)
+            .collect();
+
+        fn read(bytes: &[u8], offsets: &[usize], ref_size: usize, index: usize) -> Value {
+            let number = |bytes: &[u8]| bytes.iter().fold(0u64, |n, &b| n << 8 | u64::from(b));
+            let at = offsets[index];
+            let (kind, low) = (bytes[at] >> 4, usize::from(bytes[at] & 0x0f));
+            let (len, start) = if low == 0x0f && kind != 0x1 {
+                let width = 1 << (bytes[at + 1] & 0x0f);
+                (number(&bytes[at + 2..][..width]) as usize, at + 2 + width)
+            } else {
+                (low, at + 1)
+            };
+            let refs = |start: usize, n: usize| -> Vec<Value> {
+                (0..n)
+                    .map(|i| {
+                        let index = number(&bytes[start + i * ref_size..][..ref_size]);
+                        read(bytes, offsets, ref_size, index as usize)
+                    })
+                    .collect()
+            };
+            match kind {
+                0x1 => Value::Integer(number(&bytes[at + 1..][..1 << low])),
+                0x4 => Value::Data(bytes[start..start + len].to_vec()),
+                0x5 => {
+                    Value::String(String::from_utf8(bytes[start..start + len].to_vec()).unwrap())
+                }
+                0x6 => Value::String(
+                    char::decode_utf16(
+                        bytes[start..start + 2 * len]
+                            .chunks(2)
+                            .map(|unit| u16::from_be_bytes([unit[0], unit[1]])),
+                    )
+                    .collect::<Result<_, _>>()
+                    .unwrap(),
+                ),
+                0x8 => Value::Uid(number(&bytes[at + 1..][..low + 1])),
+                0xa => Value::Array(refs(start, len)),
+                0xd => {
+                    let keys = refs(start, len);
+                    let values = refs(start + len * ref_size, len);
+                    let keys = keys.into_iter().map(|key| match key {
+                        Value::String(key) => key,
+                        key => panic!("{key:?} is not a string key"),
+                    });
+                    Value::Dictionary(keys.zip(values).collect())
+                }
+                _ => panic!("unexpected marker {:#04x}", bytes[at]),
+            }
+        }
+        read(bytes, &offsets, ref_size, top)
+    }
+
+    #[test]
+    fn apple_clr_is_a_keyed_archive_of_a_color_list() {
+        let colors: Vec<(String, [u8; 3])> = (0..40u8)
+            .map(|n| (format!("color {n}"), [n, 0x80, 0xff - n]))
+            .chain([("r\u{e9}sum\u{e9}".to_string(), [0xff, 0x00, 0x33])])
+            .collect();
+        let mut bytes = Vec::new();
+        write_apple_clr(&mut bytes, "Palette", &colors).unwrap();
+        let plist = read_plist(&bytes);
+
+        assert_eq!(
+            plist.get("$archiver"),
+            &Value::String("NSKeyedArchiver".to_string())
+        );
+        assert_eq!(plist.get("$version"), &Value::Integer(100_000));
+        let Value::Array(objects) = plist.get("$objects") else {
+            panic!("no $objects array");
+        };
+        assert_eq!(objects[0], Value::String("$null".to_string()));
+        let object = |value: &Value| match value {
+            Value::Uid(index) => &objects[*index as usize],
+            value => panic!("{value:?} is not a UID"),
+        };
+        let class_name = |value: &Value| object(value.get("$class")).get("$classname");
+
+        let list = object(plist.get("$top").get("root"));
+        assert_eq!(class_name(list), &Value::String("NSColorList".to_string()));
+        assert_eq!(
+            object(list.get("NSName")),
+            &Value::String("Palette".to_string())
+        );
+        let entries = |key: &str| match object(list.get(key)).get("NS.objects") {
+            Value::Array(items) => items.iter().map(object).collect::<Vec<_>>(),
+            value => panic!("{value:?} is not an array"),
+        };
+        let (keys, values) = (entries("NSKeys"), entries("NSColors"));
+        assert_eq!(keys.len(), colors.len());
+        assert_eq!(values.len(), colors.len());
+        for ((key, [r, g, b]), (archived_key, color)) in colors.iter().zip(keys.iter().zip(&values))
+        {
+            assert_eq!(*archived_key, &Value::String(key.clone()));
+            assert_eq!(class_name(color), &Value::String("NSColor".to_string()));
+            let [r, g, b] = [r, g, b].map(|&byte| f64::from(byte) / 255.0);
+            assert_eq!(
+                color.get("NSRGB"),
+                &Value::Data(format!("{r} {g} {b}\0").into_bytes())
+            );
+            assert_eq!(
+                object(color.get("NSCustomColorSpace")).get("NSID"),
+                &Value::Integer(7)
+            );
+        }
+    }
+}
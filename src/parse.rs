@@ -0,0 +1,314 @@
+use crate::Error;
+
+/// How far past either end of the lightness range, as a fraction of the range, input is
+/// still accepted (and clamped to the bound). This absorbs float noise from scripts that
+/// compute values like `100.00000001%`.
+const L_BOUND_TOLERANCE: f64 = 1e-6;
+
+/// Names accepted in place of a numeric hue, at the OKLCH hue of the matching sRGB
+/// primary or secondary.
+pub const HUE_NAMES: [(&str, f64); 6] = [
+    ("red", 29.2),
+    ("yellow", 109.8),
+    ("green", 142.5),
+    ("cyan", 194.8),
+    ("blue", 264.1),
+    ("magenta", 328.4),
+];
+
+/// The scale plain L and C numbers are read in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputScale {
+    /// L in 0..1 and C as in CSS `oklch()`, where 0.4 is a typical maximum.
+    #[default]
+    Css,
+    /// L in 0..100 and C in 0..100, where 100 means 0.4 as CSS percentages do.
+    Percent100,
+}
+
+/// The chroma that CSS `oklch()` treats as 100%.
+pub const CSS_CHROMA_PERCENT_REFERENCE: f64 = 0.4;
+
+/// Where the alpha digits sit in a 4- or 8-digit hex color.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HexAlphaPosition {
+    /// `#argb` / `#aarrggbb`, as Android and some design tools write it.
+    First,
+    /// `#rgba` / `#rrggbbaa`, as in CSS.
+    #[default]
+    Last,
+}
+
+/// Parses a 3, 4, 6, or 8-digit sRGB hex color, with or without a leading `#`, into its
+/// 8-bit channels and alpha in 0..1 (`None` for 3 and 6 digits).
+pub fn parse_hex(
+    input: &str,
+    alpha_position: HexAlphaPosition,
+) -> Result<([u8; 3], Option<f64>), Error> {
+    let digits = input.strip_prefix('#').unwrap_or(input);
+    if let Some((index, found)) = digits
+        .chars()
+        .enumerate()
+        .find(|(_, ch)| !ch.is_ascii_hexdigit())
+    {
+        return Err(Error::HexDigit {
+            position: index + 1,
+            found,
+        });
+    }
+    let nibbles: Vec<u8> = digits.bytes().map(hex_value).collect();
+    let mut channels: Vec<u8> = match nibbles.len() {
+        3 | 4 => nibbles.iter().map(|&n| n * 17).collect(),
+        6 | 8 => nibbles
+            .chunks(2)
+            .map(|pair| pair[0] * 16 + pair[1])
+            .collect(),
+        digits => return Err(Error::HexLength { digits }),
+    };
+    let alpha = match (channels.len(), alpha_position) {
+        (4, HexAlphaPosition::First) => Some(channels.remove(0)),
+        (4, HexAlphaPosition::Last) => channels.pop(),
+        _ => None,
+    };
+    Ok((
+        [channels[0], channels[1], channels[2]],
+        alpha.map(|a| f64::from(a) / 255.0),
+    ))
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+/// Parses `L C H [A]` strings, returning lightness normalized to 0..1.
+pub fn parse_color(
+    l: &str,
+    c: &str,
+    h: &str,
+    a: Option<&str>,
+) -> Result<(f64, f64, f64, Option<f64>), Error> {
+    parse_color_in(InputScale::Css, l, c, h, a)
+}
+
+/// [`parse_color`] with L and C read in `scale`. Lightness with a `%` suffix means the
+/// same in either scale.
+pub fn parse_color_in(
+    scale: InputScale,
+    l: &str,
+    c: &str,
+    h: &str,
+    a: Option<&str>,
+) -> Result<(f64, f64, f64, Option<f64>), Error> {
+    let (l, c) = match scale {
+        InputScale::Css => (parse_l(l)?, parse_non_negative(c, "C")?),
+        InputScale::Percent100 => {
+            let l = match l.strip_suffix('%') {
+                Some(_) => parse_l(l)?,
+                None => {
+                    let parsed = clamp_near_bounds(parse_f64(l, "L")?, 100.0);
+                    if !(0.0..=100.0).contains(&parsed) {
+                        return Err(out_of_range("L", parsed, 0.0, 100.0));
+                    }
+                    parsed / 100.0
+                }
+            };
+            let c = parse_non_negative(c, "C")?;
+            if c > 100.0 {
+                return Err(out_of_range("C", c, 0.0, 100.0));
+            }
+            (l, c / 100.0 * CSS_CHROMA_PERCENT_REFERENCE)
+        }
+    };
+    let h = parse_hue(h)?;
+    let a = a.map(|value| parse_unit_range(value, "A")).transpose()?;
+    Ok((l, c, h, a))
+}
+
+pub fn parse_l(input: &str) -> Result<f64, Error> {
+    if let Some(value) = input.strip_suffix('%') {
+        let parsed = clamp_near_bounds(parse_f64(value, "L%")?, 100.0);
+        if !(0.0..=100.0).contains(&parsed) {
+            return Err(out_of_range("L%", parsed, 0.0, 100.0));
+        }
+        Ok(parsed / 100.0)
+    } else {
+        let parsed = clamp_near_bounds(parse_f64(input, "L")?, 1.0);
+        if !(0.0..=1.0).contains(&parsed) {
+            return Err(out_of_range("L", parsed, 0.0, 1.0));
+        }
+        Ok(parsed)
+    }
+}
+
+/// Parses a hue in degrees, or one of the [`HUE_NAMES`] (case-insensitive).
+pub fn parse_hue(input: &str) -> Result<f64, Error> {
+    HUE_NAMES
+        .iter()
+        .find(|(name, _)| input.eq_ignore_ascii_case(name))
+        .map_or_else(|| parse_f64(input, "H"), |&(_, degrees)| Ok(degrees))
+}
+
+/// Snaps values within [`L_BOUND_TOLERANCE`] of `0..=max` onto the nearest bound and
+/// leaves everything else, including real out-of-range values, alone.
+fn clamp_near_bounds(value: f64, max: f64) -> f64 {
+    let tolerance = max * L_BOUND_TOLERANCE;
+    if value < 0.0 && value >= -tolerance {
+        0.0
+    } else if value > max && value <= max + tolerance {
+        max
+    } else {
+        value
+    }
+}
+
+pub fn parse_non_negative(input: &str, name: &str) -> Result<f64, Error> {
+    let value = parse_f64(input, name)?;
+    if value < 0.0 {
+        return Err(out_of_range(name, value, 0.0, f64::INFINITY));
+    }
+    Ok(value)
+}
+
+pub fn parse_unit_range(input: &str, name: &str) -> Result<f64, Error> {
+    let value = parse_f64(input, name)?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err(out_of_range(name, value, 0.0, 1.0));
+    }
+    Ok(value)
+}
+
+/// Parses a finite number. Subnormals are flushed to zero: they are far below anything
+/// the color math can resolve, and keeping them only invites underflow surprises later.
+pub fn parse_f64(input: &str, name: &str) -> Result<f64, Error> {
+    let value = input.parse::<f64>().map_err(|_| {
+        // `0,5` is a German or French user's 0.5, not garbage; say so.
+        if input.replacen(',', ".", 1).parse::<f64>().is_ok() {
+            Error::CommaDecimal {
+                name: name.to_string(),
+            }
+        } else {
+            Error::NotANumber {
+                name: name.to_string(),
+            }
+        }
+    })?;
+    if !value.is_finite() {
+        return Err(Error::NonFinite {
+            name: name.to_string(),
+        });
+    }
+    if value.is_subnormal() {
+        return Ok(0.0);
+    }
+    Ok(value)
+}
+
+fn out_of_range(name: &str, value: f64, min: f64, max: f64) -> Error {
+    Error::OutOfRange {
+        name: name.to_string(),
+        value,
+        min,
+        max,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_bound_values_snap_onto_the_bound() {
+        assert_eq!(clamp_near_bounds(100.00000001, 100.0), 100.0);
+        assert_eq!(clamp_near_bounds(1.0000000001, 1.0), 1.0);
+        assert_eq!(clamp_near_bounds(-1e-9, 1.0), 0.0);
+        assert_eq!(clamp_near_bounds(0.5, 1.0), 0.5);
+    }
+
+    #[test]
+    fn near_bound_lightness_parses() {
+        assert_eq!(parse_l("100.00000001%"), Ok(1.0));
+        assert_eq!(parse_l("1.0000000001"), Ok(1.0));
+        assert_eq!(parse_l("-1e-9"), Ok(0.0));
+        let (l, ..) =
+            parse_color_in(InputScale::Percent100, "100.00000001", "0", "0", None).unwrap();
+        assert_eq!(l, 1.0);
+    }
+
+    #[test]
+    fn real_out_of_range_lightness_is_still_rejected() {
+        for input in ["101%", "-0.5", "1.01"] {
+            assert!(
+                matches!(parse_l(input), Err(Error::OutOfRange { .. })),
+                "{input} was accepted"
+            );
+        }
+        assert!(parse_color_in(InputScale::Percent100, "101", "0", "0", None).is_err());
+    }
+
+    /// A few inputs from the edges of what `fuzz/fuzz_targets/parse.rs` explores, so the
+    /// no-panic and in-range properties are also checked on a plain `cargo test`.
+    #[test]
+    fn awkward_input_never_panics_and_accepted_values_are_in_range() {
+        let inputs = [
+            "",
+            " ",
+            "%",
+            "-%",
+            "100%%",
+            "1e309",
+            "-1e309",
+            "NaN",
+            "inf",
+            "-0",
+            "+0.5",
+            "0x10",
+            "1_000",
+            "0,5",
+            "5e-324",
+            "1.0000000001",
+            "-1e-9",
+            "\u{661}",
+            "\u{ff10}.5",
+            "#",
+            "##fff",
+            "#fff",
+            "#FFFF",
+            "#ff00ff",
+            "#ff00ff80",
+            "#ggg",
+            "#\u{e9}ff",
+            "fffffffff",
+            "RED",
+            "Blue ",
+            "magenta",
+            "\u{0}",
+            "\u{202e}1",
+        ];
+        for input in inputs {
+            if let Ok(l) = parse_l(input) {
+                assert!((0.0..=1.0).contains(&l), "{input:?} gave L {l}");
+            }
+            if let Ok(h) = parse_hue(input) {
+                assert!(h.is_finite(), "{input:?} gave H {h}");
+            }
+            if let Ok(a) = parse_unit_range(input, "A") {
+                assert!((0.0..=1.0).contains(&a), "{input:?} gave A {a}");
+            }
+            for position in [HexAlphaPosition::First, HexAlphaPosition::Last] {
+                if let Ok((_, Some(alpha))) = parse_hex(input, position) {
+                    assert!((0.0..=1.0).contains(&alpha), "{input:?} gave alpha {alpha}");
+                }
+            }
+            for scale in [InputScale::Css, InputScale::Percent100] {
+                if let Ok((l, c, h, a)) = parse_color_in(scale, input, input, input, Some(input)) {
+                    assert!((0.0..=1.0).contains(&l) && c >= 0.0 && h.is_finite());
+                    assert!(a.is_none_or(|a| (0.0..=1.0).contains(&a)));
+                }
+            }
+        }
+    }
+}
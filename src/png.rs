@@ -0,0 +1,756 @@
+use std::io::{self, Read, Write};
+
+use crc32fast::Hasher;
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::color::{ColorSpace, Transfer};
+use crate::sample::{ChannelOrder, SampleRange, push_row, push_sample};
+use crate::{Error, Image, Pixel};
+
+/// The zlib level image data is compressed at, after each row is filtered as
+/// [`PngOptions::filter`] says.
+pub const COMPRESSION_LEVEL: u32 = 6;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+const CICP_PRIMARIES_SRGB: u8 = 1;
+const CICP_PRIMARIES_DISPLAY_P3: u8 = 12;
+const CICP_TRANSFER_LINEAR: u8 = 8;
+const CICP_TRANSFER_UNSPECIFIED: u8 = 2;
+const CICP_TRANSFER_SRGB: u8 = 13;
+const CICP_MATRIX_IDENTITY: u8 = 0;
+const CICP_FULL_RANGE: u8 = 1;
+const CICP_LIMITED_RANGE: u8 = 0;
+
+const EXIF_TAG_ORIENTATION: u16 = 0x0112;
+const EXIF_TAG_EXIF_IFD: u16 = 0x8769;
+const EXIF_TAG_EXIF_VERSION: u16 = 0x9000;
+const EXIF_TAG_COLOR_SPACE: u16 = 0xA001;
+const EXIF_TYPE_UNDEFINED: u16 = 7;
+const EXIF_TYPE_SHORT: u16 = 3;
+const EXIF_TYPE_LONG: u16 = 4;
+const EXIF_ORIENTATION_TOP_LEFT: u16 = 1;
+const EXIF_COLOR_SPACE_SRGB: u16 = 1;
+const EXIF_COLOR_SPACE_UNCALIBRATED: u16 = 0xFFFF;
+
+/// The rendering intent recorded in an `sRGB` chunk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual,
+    #[default]
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+impl RenderingIntent {
+    /// The intent byte defined by the PNG specification.
+    fn code(self) -> u8 {
+        match self {
+            RenderingIntent::Perceptual => 0,
+            RenderingIntent::RelativeColorimetric => 1,
+            RenderingIntent::Saturation => 2,
+            RenderingIntent::AbsoluteColorimetric => 3,
+        }
+    }
+}
+
+/// How a PNG is tagged, plus any optional ancillary chunks.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PngOptions {
+    pub color_space: ColorSpace,
+    pub exif: bool,
+    /// Write only the critical chunks. The image is left untagged, so whatever reads it
+    /// has to know out-of-band that the samples are in `color_space`.
+    pub minimal: bool,
+    /// Only written for sRGB; cICP has no intent field.
+    pub rendering_intent: RenderingIntent,
+    /// The encoding the samples are already in, recorded in cICP. The `sRGB` chunk
+    /// always implies the sRGB curve, whatever this says.
+    pub transfer: Transfer,
+    /// The range the samples are already in, recorded in cICP.
+    pub range: SampleRange,
+    /// The filter applied to each row before compression.
+    pub filter: RowFilter,
+    /// A backdrop to suggest to viewers in a `bKGD` chunk, already encoded like the
+    /// image's samples. Left out with `minimal`.
+    pub suggested_background: Option<Pixel>,
+}
+
+/// A PNG row filter. Filtering costs time when writing but lets zlib find more
+/// repetition in images that vary from pixel to pixel; solid images compress just as well
+/// unfiltered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RowFilter {
+    #[default]
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+    /// Tries every filter on each row and keeps the one whose output has the smallest sum
+    /// of absolute values (as signed bytes), the heuristic the PNG specification
+    /// suggests.
+    Adaptive,
+}
+
+impl RowFilter {
+    const FIXED: [RowFilter; 5] = [
+        RowFilter::None,
+        RowFilter::Sub,
+        RowFilter::Up,
+        RowFilter::Average,
+        RowFilter::Paeth,
+    ];
+
+    /// The filter-type byte that starts a row, or `None` for `Adaptive`, which picks
+    /// one per row.
+    pub fn code(self) -> Option<u8> {
+        match self {
+            RowFilter::None => Some(0),
+            RowFilter::Sub => Some(1),
+            RowFilter::Up => Some(2),
+            RowFilter::Average => Some(3),
+            RowFilter::Paeth => Some(4),
+            RowFilter::Adaptive => None,
+        }
+    }
+}
+
+/// The cICP (ITU-T H.273) color-primaries code for `color_space`. sRGB PNGs are tagged
+/// with an `sRGB` chunk instead, but the code is still its cICP equivalent.
+pub fn cicp_primaries(color_space: ColorSpace) -> u8 {
+    match color_space {
+        ColorSpace::DisplayP3 => CICP_PRIMARIES_DISPLAY_P3,
+        ColorSpace::Srgb => CICP_PRIMARIES_SRGB,
+    }
+}
+
+/// The cICP (ITU-T H.273) transfer-characteristics code for `transfer`.
+pub fn cicp_transfer(transfer: Transfer) -> u8 {
+    match transfer {
+        Transfer::Srgb => CICP_TRANSFER_SRGB,
+        Transfer::Linear => CICP_TRANSFER_LINEAR,
+        Transfer::Custom(_) => CICP_TRANSFER_UNSPECIFIED,
+    }
+}
+
+/// The four cICP bytes for `options`: primaries, transfer, matrix, and full-range flag.
+pub fn cicp_bytes(options: &PngOptions) -> [u8; 4] {
+    [
+        cicp_primaries(options.color_space),
+        cicp_transfer(options.transfer),
+        CICP_MATRIX_IDENTITY,
+        match options.range {
+            SampleRange::Full => CICP_FULL_RANGE,
+            SampleRange::Limited => CICP_LIMITED_RANGE,
+        },
+    ]
+}
+
+/// Checks that each cICP byte decodes back to what `options` says the samples are,
+/// catching a code table that was not updated along with a new space or transfer.
+pub fn validate_cicp(cicp: [u8; 4], options: &PngOptions) -> Result<(), Error> {
+    let mismatch = |field: &str, code: u8| {
+        Err(Error::CicpMismatch {
+            field: field.to_string(),
+            code,
+        })
+    };
+    let [primaries, transfer, matrix, full_range] = cicp;
+    let decoded_space = match primaries {
+        CICP_PRIMARIES_DISPLAY_P3 => Some(ColorSpace::DisplayP3),
+        CICP_PRIMARIES_SRGB => Some(ColorSpace::Srgb),
+        _ => None,
+    };
+    if decoded_space != Some(options.color_space) {
+        return mismatch("primaries", primaries);
+    }
+    let decoded_transfer = match transfer {
+        CICP_TRANSFER_SRGB => Some(Transfer::Srgb),
+        CICP_TRANSFER_LINEAR => Some(Transfer::Linear),
+        // Unspecified can't name the curve, so any custom one matches it.
+        CICP_TRANSFER_UNSPECIFIED => match options.transfer {
+            Transfer::Custom(_) => Some(options.transfer),
+            _ => None,
+        },
+        _ => None,
+    };
+    if decoded_transfer != Some(options.transfer) {
+        return mismatch("transfer", transfer);
+    }
+    if matrix != CICP_MATRIX_IDENTITY {
+        return mismatch("matrix", matrix);
+    }
+    let decoded_range = match full_range {
+        CICP_FULL_RANGE => Some(SampleRange::Full),
+        CICP_LIMITED_RANGE => Some(SampleRange::Limited),
+        _ => None,
+    };
+    if decoded_range != Some(options.range) {
+        return mismatch("range", full_range);
+    }
+    Ok(())
+}
+
+pub fn write_png<W: Write>(
+    writer: &mut W,
+    image: &Image,
+    bit_depth: u8,
+    include_alpha: bool,
+    options: &PngOptions,
+) -> io::Result<()> {
+    write_png_with_extra_chunks(writer, image, bit_depth, include_alpha, options, &[])
+}
+
+/// [`write_png`], plus `extra` ancillary chunks written in order just before the image
+/// data. Each chunk type is checked with [`check_extra_chunk_type`] before anything is
+/// written.
+pub fn write_png_with_extra_chunks<W: Write>(
+    writer: &mut W,
+    image: &Image,
+    bit_depth: u8,
+    include_alpha: bool,
+    options: &PngOptions,
+    extra: &[([u8; 4], &[u8])],
+) -> io::Result<()> {
+    for (chunk_type, _) in extra {
+        check_extra_chunk_type(chunk_type)?;
+    }
+    writer.write_all(&PNG_SIGNATURE)?;
+
+    let color_type = if include_alpha { 6 } else { 2 };
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&image.width().to_be_bytes());
+    ihdr.extend_from_slice(&image.height().to_be_bytes());
+    ihdr.push(bit_depth);
+    ihdr.push(color_type);
+    ihdr.push(0);
+    ihdr.push(0);
+    ihdr.push(0);
+    write_chunk(writer, b"IHDR", &ihdr)?;
+
+    if !options.minimal {
+        match options.color_space {
+            ColorSpace::DisplayP3 => {
+                let cicp = cicp_bytes(options);
+                debug_assert_eq!(validate_cicp(cicp, options), Ok(()));
+                write_chunk(writer, b"cICP", &cicp)?;
+            }
+            ColorSpace::Srgb => {
+                write_chunk(writer, b"sRGB", &[options.rendering_intent.code()])?;
+            }
+        }
+
+        if options.exif {
+            write_chunk(writer, b"eXIf", &exif_payload(options.color_space))?;
+        }
+
+        if let Some(background) = options.suggested_background {
+            write_chunk(writer, b"bKGD", &bkgd_payload(background, bit_depth))?;
+        }
+    }
+
+    for (chunk_type, data) in extra {
+        write_chunk(writer, chunk_type, data)?;
+    }
+
+    let channels = if include_alpha { 4 } else { 3 };
+    let bytes_per_pixel = channels * usize::from(bit_depth / 8);
+    // Rows go to the encoder as they're filtered, so only two of them are held at a
+    // time; the compressed stream is kept whole so it can go out as one IDAT chunk.
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(COMPRESSION_LEVEL));
+    let mut row = Vec::new();
+    let mut previous: Option<Vec<u8>> = None;
+    for y in 0..image.height() {
+        row.clear();
+        push_row(
+            &mut row,
+            image,
+            y,
+            bit_depth,
+            include_alpha,
+            ChannelOrder::Rgba,
+        );
+        let (code, filtered) =
+            filter_row(options.filter, &row, previous.as_deref(), bytes_per_pixel);
+        encoder.write_all(&[code])?;
+        encoder.write_all(&filtered)?;
+        match &mut previous {
+            Some(previous) => std::mem::swap(previous, &mut row),
+            None => previous = Some(std::mem::take(&mut row)),
+        }
+    }
+
+    let compressed = encoder.finish()?;
+    write_chunk(writer, b"IDAT", &compressed)?;
+    write_chunk(writer, b"IEND", &[])?;
+
+    Ok(())
+}
+
+/// A truecolor `bKGD` payload: red, green, and blue as two big-endian bytes each, in the
+/// image's sample range (so an 8-bit image's samples have a zero high byte).
+fn bkgd_payload(background: Pixel, bit_depth: u8) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(6);
+    for value in [background.r, background.g, background.b] {
+        if bit_depth == 8 {
+            payload.push(0);
+        }
+        push_sample(&mut payload, value, bit_depth);
+    }
+    payload
+}
+
+/// Checks that `chunk_type` can be added by [`write_png_with_extra_chunks`]: four ASCII
+/// letters, ancillary (lowercase first letter, so decoders may skip it), with the
+/// reserved third letter uppercase, and not a chunk this writer emits itself. The
+/// private bit (second letter) may be either case, but a lowercase one is the right
+/// choice for proprietary data.
+pub fn check_extra_chunk_type(chunk_type: &[u8; 4]) -> io::Result<()> {
+    let invalid = |reason: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "chunk type {:?} {reason}",
+                String::from_utf8_lossy(chunk_type)
+            ),
+        )
+    };
+    if !chunk_type.iter().all(u8::is_ascii_alphabetic) {
+        return Err(invalid("must be four ASCII letters"));
+    }
+    if chunk_type[0].is_ascii_uppercase() {
+        return Err(invalid(
+            "is critical; only ancillary (lowercase first letter) chunks can be added",
+        ));
+    }
+    if chunk_type[2].is_ascii_lowercase() {
+        return Err(invalid("has a lowercase third letter, which PNG reserves"));
+    }
+    if [b"cICP", b"sRGB", b"eXIf", b"bKGD"].contains(&chunk_type) {
+        return Err(invalid("is written by the encoder itself"));
+    }
+    Ok(())
+}
+
+/// A PNG read back by [`read_png`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedPng {
+    pub image: Image,
+    pub bit_depth: u8,
+    pub has_alpha: bool,
+    /// The space the file is tagged with, if it has a `cICP` or `sRGB` chunk this crate
+    /// understands.
+    pub color_space: Option<ColorSpace>,
+}
+
+/// Reads a non-interlaced 8- or 16-bit RGB or RGBA PNG, such as the ones [`write_png`]
+/// produces, or a grayscale one (with or without alpha), whose gray level fills all
+/// three channels. Other PNGs are rejected with `InvalidData`.
+pub fn read_png<R: Read>(reader: &mut R) -> io::Result<DecodedPng> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let mut signature = [0; 8];
+    reader.read_exact(&mut signature)?;
+    if signature != PNG_SIGNATURE {
+        return Err(invalid("not a PNG file"));
+    }
+
+    let mut header = None;
+    let mut color_space = None;
+    let mut compressed = Vec::new();
+    loop {
+        let (chunk_type, data) = read_chunk(reader)?;
+        match &chunk_type {
+            b"IHDR" => {
+                let [
+                    w0,
+                    w1,
+                    w2,
+                    w3,
+                    h0,
+                    h1,
+                    h2,
+                    h3,
+                    bit_depth,
+                    color_type,
+                    _,
+                    _,
+                    interlace,
+                ] = <[u8; 13]>::try_from(data.as_slice()).map_err(|_| invalid("bad IHDR"))?;
+                if !matches!(bit_depth, 8 | 16)
+                    || !matches!(color_type, 0 | 2 | 4 | 6)
+                    || interlace != 0
+                {
+                    return Err(invalid(
+                        "only non-interlaced 8- or 16-bit grayscale, RGB and RGBA PNGs are \
+                         supported",
+                    ));
+                }
+                let width = u32::from_be_bytes([w0, w1, w2, w3]);
+                let height = u32::from_be_bytes([h0, h1, h2, h3]);
+                header = Some((width, height, bit_depth, color_type));
+            }
+            b"cICP" if data.first() == Some(&CICP_PRIMARIES_DISPLAY_P3) => {
+                color_space = Some(ColorSpace::DisplayP3);
+            }
+            b"sRGB" => color_space = Some(ColorSpace::Srgb),
+            b"IDAT" => compressed.extend_from_slice(&data),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let (width, height, bit_depth, color_type) = header.ok_or_else(|| invalid("missing IHDR"))?;
+    let has_alpha = color_type & 4 != 0;
+    let gray = color_type & 2 == 0;
+    let channels = match (gray, has_alpha) {
+        (true, false) => 1,
+        (true, true) => 2,
+        (false, false) => 3,
+        (false, true) => 4,
+    };
+    let bytes_per_pixel = channels * usize::from(bit_depth / 8);
+    let stride = width as usize * bytes_per_pixel;
+
+    let mut raw = Vec::new();
+    ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut raw)?;
+    if raw.len() != (stride + 1) * height as usize {
+        return Err(invalid("image data has the wrong length"));
+    }
+
+    let mut rows: Vec<Vec<u8>> = Vec::with_capacity(height as usize);
+    for line in raw.chunks_exact(stride + 1) {
+        let previous = rows.last().map(Vec::as_slice);
+        rows.push(
+            unfilter_row(line[0], &line[1..], previous, bytes_per_pixel)
+                .ok_or_else(|| invalid("bad filter type"))?,
+        );
+    }
+
+    let max = if bit_depth == 16 { 65535.0 } else { 255.0 };
+    let sample = |row: &[u8], index: usize| -> f64 {
+        let value = if bit_depth == 16 {
+            u16::from_be_bytes([row[2 * index], row[2 * index + 1]])
+        } else {
+            u16::from(row[index])
+        };
+        f64::from(value) / max
+    };
+    let image = Image::from_fn(width, height, |x, y| {
+        let row = &rows[y as usize];
+        let base = x as usize * channels;
+        let (r, g, b) = if gray {
+            let level = sample(row, base);
+            (level, level, level)
+        } else {
+            (
+                sample(row, base),
+                sample(row, base + 1),
+                sample(row, base + 2),
+            )
+        };
+        Pixel {
+            r,
+            g,
+            b,
+            a: if has_alpha {
+                sample(row, base + channels - 1)
+            } else {
+                1.0
+            },
+        }
+    });
+
+    Ok(DecodedPng {
+        image,
+        bit_depth,
+        has_alpha,
+        color_space,
+    })
+}
+
+/// Applies `filter` to one row of samples, given the unfiltered row above it (`None` for
+/// the first row) and the bytes per pixel. Returns the filter-type byte to write before
+/// the row and the filtered bytes.
+pub fn filter_row(
+    filter: RowFilter,
+    line: &[u8],
+    previous: Option<&[u8]>,
+    bpp: usize,
+) -> (u8, Vec<u8>) {
+    let Some(code) = filter.code() else {
+        let cost = |row: &[u8]| -> u64 {
+            row.iter()
+                .map(|&byte| u64::from((byte as i8).unsigned_abs()))
+                .sum()
+        };
+        return RowFilter::FIXED
+            .iter()
+            .map(|&filter| filter_row(filter, line, previous, bpp))
+            .min_by_key(|(_, row)| cost(row))
+            .expect("there is always a fixed filter to try");
+    };
+    let mut row = Vec::with_capacity(line.len());
+    for i in 0..line.len() {
+        let (left, up, up_left) = neighbors(line, previous, i, bpp);
+        row.push(line[i].wrapping_sub(predict(code, left, up, up_left)));
+    }
+    (code, row)
+}
+
+/// Undoes one row's PNG filter, given the already-unfiltered row above it. Returns
+/// `None` for an unknown filter type.
+pub fn unfilter_row(
+    filter: u8,
+    line: &[u8],
+    previous: Option<&[u8]>,
+    bpp: usize,
+) -> Option<Vec<u8>> {
+    if filter > 4 {
+        return None;
+    }
+    let mut row = line.to_vec();
+    for i in 0..row.len() {
+        let (left, up, up_left) = neighbors(&row, previous, i, bpp);
+        row[i] = row[i].wrapping_add(predict(filter, left, up, up_left));
+    }
+    Some(row)
+}
+
+/// The bytes to the left, above, and above-left of byte `i`, as the filters see them.
+fn neighbors(row: &[u8], previous: Option<&[u8]>, i: usize, bpp: usize) -> (u8, u8, u8) {
+    let left = if i >= bpp { row[i - bpp] } else { 0 };
+    let up = previous.map_or(0, |previous| previous[i]);
+    let up_left = if i >= bpp {
+        previous.map_or(0, |previous| previous[i - bpp])
+    } else {
+        0
+    };
+    (left, up, up_left)
+}
+
+/// The value filter type `filter` (0 to 4) predicts for a byte.
+fn predict(filter: u8, left: u8, up: u8, up_left: u8) -> u8 {
+    match filter {
+        1 => left,
+        2 => up,
+        3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+        4 => paeth(left, up, up_left),
+        _ => 0,
+    }
+}
+
+/// The Paeth predictor: whichever of left (`a`), up (`b`) and up-left (`c`) is closest
+/// to `a + b - c`, with ties going in that order.
+pub fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = (
+        (p - i16::from(a)).abs(),
+        (p - i16::from(b)).abs(),
+        (p - i16::from(c)).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Reads one chunk, checking its CRC.
+fn read_chunk<R: Read>(reader: &mut R) -> io::Result<([u8; 4], Vec<u8>)> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let mut chunk_type = [0; 4];
+    reader.read_exact(&mut chunk_type)?;
+    let length = u32::from_be_bytes(length);
+    let mut data = Vec::new();
+    reader.take(u64::from(length)).read_to_end(&mut data)?;
+    if data.len() != length as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let mut crc = [0; 4];
+    reader.read_exact(&mut crc)?;
+
+    let mut hasher = Hasher::new();
+    hasher.update(&chunk_type);
+    hasher.update(&data);
+    if hasher.finalize() != u32::from_be_bytes(crc) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "chunk CRC mismatch",
+        ));
+    }
+    Ok((chunk_type, data))
+}
+
+/// Builds a big-endian TIFF structure holding IFD0 (orientation plus a pointer to the
+/// Exif IFD) and an Exif IFD (version and color space). Display P3 has no Exif
+/// ColorSpace code, so it is declared "uncalibrated" as cameras do for wide gamuts.
+fn exif_payload(color_space: ColorSpace) -> Vec<u8> {
+    const IFD0_OFFSET: u32 = 8;
+    const IFD0_ENTRIES: u16 = 2;
+    const EXIF_IFD_OFFSET: u32 = IFD0_OFFSET + 2 + 12 * IFD0_ENTRIES as u32 + 4;
+
+    let exif_color_space = match color_space {
+        ColorSpace::DisplayP3 => EXIF_COLOR_SPACE_UNCALIBRATED,
+        ColorSpace::Srgb => EXIF_COLOR_SPACE_SRGB,
+    };
+
+    let mut tiff = Vec::new();
+    tiff.extend_from_slice(b"MM");
+    tiff.extend_from_slice(&42u16.to_be_bytes());
+    tiff.extend_from_slice(&IFD0_OFFSET.to_be_bytes());
+
+    tiff.extend_from_slice(&IFD0_ENTRIES.to_be_bytes());
+    push_ifd_entry(
+        &mut tiff,
+        EXIF_TAG_ORIENTATION,
+        EXIF_TYPE_SHORT,
+        1,
+        short_value(EXIF_ORIENTATION_TOP_LEFT),
+    );
+    push_ifd_entry(
+        &mut tiff,
+        EXIF_TAG_EXIF_IFD,
+        EXIF_TYPE_LONG,
+        1,
+        EXIF_IFD_OFFSET.to_be_bytes(),
+    );
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+
+    tiff.extend_from_slice(&2u16.to_be_bytes());
+    push_ifd_entry(
+        &mut tiff,
+        EXIF_TAG_EXIF_VERSION,
+        EXIF_TYPE_UNDEFINED,
+        4,
+        *b"0232",
+    );
+    push_ifd_entry(
+        &mut tiff,
+        EXIF_TAG_COLOR_SPACE,
+        EXIF_TYPE_SHORT,
+        1,
+        short_value(exif_color_space),
+    );
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+
+    tiff
+}
+
+fn short_value(value: u16) -> [u8; 4] {
+    let [hi, lo] = value.to_be_bytes();
+    [hi, lo, 0, 0]
+}
+
+fn push_ifd_entry(buf: &mut Vec<u8>, tag: u16, field_type: u16, count: u32, value: [u8; 4]) {
+    buf.extend_from_slice(&tag.to_be_bytes());
+    buf.extend_from_slice(&field_type.to_be_bytes());
+    buf.extend_from_slice(&count.to_be_bytes());
+    buf.extend_from_slice(&value);
+}
+
+fn write_chunk<W: Write>(writer: &mut W, chunk_type: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let length = u32::try_from(data.len()).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "chunk too large")
+    })?;
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(chunk_type)?;
+    writer.write_all(data)?;
+
+    let mut hasher = Hasher::new();
+    hasher.update(chunk_type);
+    hasher.update(data);
+    let crc = hasher.finalize();
+    writer.write_all(&crc.to_be_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::CustomTransfer;
+
+    #[test]
+    fn cicp_transfer_byte_names_the_curve() {
+        let custom = Transfer::Custom(CustomTransfer::new(2.2, 0.0031308, 12.92).unwrap());
+        for (transfer, code) in [(Transfer::Srgb, 13), (Transfer::Linear, 8), (custom, 2)] {
+            assert_eq!(cicp_transfer(transfer), code);
+            for color_space in [ColorSpace::Srgb, ColorSpace::DisplayP3] {
+                let options = PngOptions {
+                    color_space,
+                    transfer,
+                    ..PngOptions::default()
+                };
+                let cicp = cicp_bytes(&options);
+                assert_eq!(cicp[1], code);
+                assert_eq!(validate_cicp(cicp, &options), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn validate_cicp_rejects_a_transfer_byte_for_another_curve() {
+        let options = PngOptions {
+            transfer: Transfer::Linear,
+            ..PngOptions::default()
+        };
+        let [primaries, _, matrix, range] = cicp_bytes(&options);
+        for code in [CICP_TRANSFER_SRGB, CICP_TRANSFER_UNSPECIFIED] {
+            assert_eq!(
+                validate_cicp([primaries, code, matrix, range], &options),
+                Err(Error::CicpMismatch {
+                    field: "transfer".to_string(),
+                    code,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn every_filter_round_trips() {
+        let previous: Vec<u8> = (0..24u8).map(|i| i.wrapping_mul(37)).collect();
+        let line: Vec<u8> = (0..24u8).map(|i| 255 - i.wrapping_mul(11)).collect();
+        for bpp in [1, 3, 4, 6, 8] {
+            for filter in RowFilter::FIXED.into_iter().chain([RowFilter::Adaptive]) {
+                for above in [None, Some(previous.as_slice())] {
+                    let (code, filtered) = filter_row(filter, &line, above, bpp);
+                    if let Some(fixed) = filter.code() {
+                        assert_eq!(code, fixed);
+                    }
+                    assert_eq!(
+                        unfilter_row(code, &filtered, above, bpp),
+                        Some(line.clone()),
+                        "{filter:?} at {bpp} bytes per pixel"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_filter_type_is_rejected() {
+        assert_eq!(unfilter_row(5, &[0; 4], None, 1), None);
+    }
+
+    #[test]
+    fn paeth_breaks_ties_left_then_up() {
+        // All three equally close: left.
+        assert_eq!(paeth(7, 7, 7), 7);
+        // Up-left halfway between left and up predicts itself exactly.
+        assert_eq!(paeth(10, 20, 15), 15);
+        // Left and up-left tie ahead of up.
+        assert_eq!(paeth(1, 4, 3), 1);
+        // Up and up-left tie ahead of left.
+        assert_eq!(paeth(4, 1, 3), 1);
+        // No ties.
+        assert_eq!(paeth(10, 200, 10), 200);
+        assert_eq!(paeth(100, 50, 0), 100);
+    }
+}
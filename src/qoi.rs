@@ -0,0 +1,109 @@
+use std::io::{self, Write};
+
+use crate::Image;
+use crate::color::Transfer;
+use crate::sample::{ChannelOrder, image_samples};
+
+const QOI_MAGIC: &[u8; 4] = b"qoif";
+const QOI_END: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+const QOI_OP_INDEX: u8 = 0x00;
+const QOI_OP_DIFF: u8 = 0x40;
+const QOI_OP_LUMA: u8 = 0x80;
+const QOI_OP_RUN: u8 = 0xc0;
+const QOI_OP_RGB: u8 = 0xfe;
+const QOI_OP_RGBA: u8 = 0xff;
+
+/// The longest run one `QOI_OP_RUN` can encode.
+const MAX_RUN: u8 = 62;
+
+/// Writes an 8-bit QOI image. The header has 3 channels, or 4 with `include_alpha`, and
+/// a colorspace byte of 0 (sRGB curve) or 1 (linear) from `transfer`; a custom curve
+/// can't be recorded, so it is refused. QOI can't say which primaries the samples use,
+/// so Display P3 samples are written as they are and read back as sRGB by most decoders.
+pub fn write_qoi<W: Write>(
+    writer: &mut W,
+    image: &Image,
+    include_alpha: bool,
+    transfer: Transfer,
+) -> io::Result<()> {
+    let channels: u8 = if include_alpha { 4 } else { 3 };
+    let colorspace = match transfer {
+        Transfer::Srgb => 0,
+        Transfer::Linear => 1,
+        Transfer::Custom(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "QOI can only record the sRGB curve or linear light",
+            ));
+        }
+    };
+    let mut buf = Vec::with_capacity(14 + QOI_END.len());
+    buf.extend_from_slice(QOI_MAGIC);
+    buf.extend_from_slice(&image.width().to_be_bytes());
+    buf.extend_from_slice(&image.height().to_be_bytes());
+    buf.extend_from_slice(&[channels, colorspace]);
+
+    let samples = image_samples(image, 8, include_alpha, ChannelOrder::Rgba);
+    let mut index = [[0u8; 4]; 64];
+    let mut previous = [0, 0, 0, 255];
+    let mut run = 0;
+    for sample in samples.chunks_exact(usize::from(channels)) {
+        let pixel = [
+            sample[0],
+            sample[1],
+            sample[2],
+            if include_alpha { sample[3] } else { 255 },
+        ];
+        if pixel == previous {
+            run += 1;
+            if run == MAX_RUN {
+                buf.push(QOI_OP_RUN | (run - 1));
+                run = 0;
+            }
+            continue;
+        }
+        if run > 0 {
+            buf.push(QOI_OP_RUN | (run - 1));
+            run = 0;
+        }
+
+        let position = hash(pixel);
+        if index[position] == pixel {
+            buf.push(QOI_OP_INDEX | position as u8);
+        } else if pixel[3] == previous[3] {
+            let dr = pixel[0].wrapping_sub(previous[0]) as i8;
+            let dg = pixel[1].wrapping_sub(previous[1]) as i8;
+            let db = pixel[2].wrapping_sub(previous[2]) as i8;
+            let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+            if (-2..=1).contains(&dr) && (-2..=1).contains(&dg) && (-2..=1).contains(&db) {
+                buf.push(
+                    QOI_OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8,
+                );
+            } else if (-32..=31).contains(&dg)
+                && (-8..=7).contains(&dr_dg)
+                && (-8..=7).contains(&db_dg)
+            {
+                buf.push(QOI_OP_LUMA | (dg + 32) as u8);
+                buf.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+            } else {
+                buf.extend_from_slice(&[QOI_OP_RGB, pixel[0], pixel[1], pixel[2]]);
+            }
+        } else {
+            buf.push(QOI_OP_RGBA);
+            buf.extend_from_slice(&pixel);
+        }
+        index[position] = pixel;
+        previous = pixel;
+    }
+    if run > 0 {
+        buf.push(QOI_OP_RUN | (run - 1));
+    }
+    buf.extend_from_slice(&QOI_END);
+    writer.write_all(&buf)
+}
+
+/// The spec's index position for a color.
+fn hash([r, g, b, a]: [u8; 4]) -> usize {
+    (usize::from(r) * 3 + usize::from(g) * 5 + usize::from(b) * 7 + usize::from(a) * 11) % 64
+}
@@ -0,0 +1,84 @@
+use std::io::{self, Write};
+
+use crate::Image;
+use crate::sample::{ChannelOrder, Endian, image_samples};
+
+/// Writes bare samples with no header; 16-bit samples are in `endian` byte order.
+pub fn write_raw<W: Write>(
+    writer: &mut W,
+    image: &Image,
+    bit_depth: u8,
+    include_alpha: bool,
+    order: ChannelOrder,
+    endian: Endian,
+) -> io::Result<()> {
+    let mut samples = image_samples(image, bit_depth, include_alpha, order);
+    if bit_depth == 16 && endian == Endian::Little {
+        // image_samples always writes big-endian, so swap each sample's two bytes.
+        for sample in samples.chunks_exact_mut(2) {
+            sample.swap(0, 1);
+        }
+    }
+    writer.write_all(&samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pixel;
+
+    #[test]
+    fn sixteen_bit_samples_round_trip_in_both_byte_orders() {
+        let code = 0x1234u16;
+        let value = f64::from(code) / 65535.0;
+        let image = Image::solid(
+            1,
+            1,
+            Pixel {
+                r: value,
+                g: 0.0,
+                b: 1.0,
+                a: 1.0,
+            },
+        );
+        let write = |endian| {
+            let mut buf = Vec::new();
+            write_raw(&mut buf, &image, 16, false, ChannelOrder::Rgba, endian).unwrap();
+            buf
+        };
+
+        let big = write(Endian::Big);
+        assert_eq!(big, [0x12, 0x34, 0x00, 0x00, 0xff, 0xff]);
+        assert_eq!(u16::from_be_bytes([big[0], big[1]]), code);
+        let little = write(Endian::Little);
+        assert_eq!(little, [0x34, 0x12, 0x00, 0x00, 0xff, 0xff]);
+        assert_eq!(u16::from_le_bytes([little[0], little[1]]), code);
+    }
+
+    #[test]
+    fn byte_order_leaves_eight_bit_samples_alone() {
+        let image = Image::solid(
+            1,
+            1,
+            Pixel {
+                r: 1.0,
+                g: 0.0,
+                b: 0.5,
+                a: 1.0,
+            },
+        );
+        let mut big = Vec::new();
+        write_raw(&mut big, &image, 8, true, ChannelOrder::Rgba, Endian::Big).unwrap();
+        let mut little = Vec::new();
+        write_raw(
+            &mut little,
+            &image,
+            8,
+            true,
+            ChannelOrder::Rgba,
+            Endian::Little,
+        )
+        .unwrap();
+        assert_eq!(big, little);
+    }
+}
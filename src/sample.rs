@@ -0,0 +1,208 @@
+use crate::{Image, Pixel};
+
+/// The order in which a pixel's samples are emitted. Alpha is only emitted when the
+/// image has it; the color samples keep their relative order either way.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ChannelOrder {
+    /// Red first. PNG, PAM, and PPM always use this order.
+    #[default]
+    Rgba,
+    /// Blue first, for consumers that want BGRA (or BGR) memory layouts.
+    Bgra,
+    /// Alpha first, then red, green, blue.
+    Argb,
+}
+
+/// Byte order of 16-bit samples. PNG and Netpbm are always big-endian.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Endian {
+    #[default]
+    Big,
+    Little,
+}
+
+/// Whether samples use the whole code range or the video "legal" subset.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SampleRange {
+    #[default]
+    Full,
+    /// 16..=235 at 8 bits, scaled by 256 at 16 bits.
+    Limited,
+}
+
+/// Maps a 0..1 color sample into `range`, still as a 0..1 fraction of the full code range
+/// so [`push_sample`] rounds it onto the right code. Alpha is never range-limited.
+pub fn apply_range(value: f64, bit_depth: u8, range: SampleRange) -> f64 {
+    match range {
+        SampleRange::Full => value,
+        SampleRange::Limited => {
+            let (scale, max) = if bit_depth == 16 {
+                (256.0, 65535.0)
+            } else {
+                (1.0, 255.0)
+            };
+            (16.0 * scale + 219.0 * scale * value.clamp(0.0, 1.0)) / max
+        }
+    }
+}
+
+/// The inverse of [`apply_range`]: a stored 0..1 fraction of the full code range back to
+/// the 0..1 color sample. Codes outside a limited range come out below 0 or above 1.
+pub fn remove_range(value: f64, bit_depth: u8, range: SampleRange) -> f64 {
+    match range {
+        SampleRange::Full => value,
+        SampleRange::Limited => {
+            let (scale, max) = if bit_depth == 16 {
+                (256.0, 65535.0)
+            } else {
+                (1.0, 255.0)
+            };
+            (value * max - 16.0 * scale) / (219.0 * scale)
+        }
+    }
+}
+
+/// [`apply_range`] for a Cb or Cr sample centered on 0.5, whose limited range is
+/// 16..=240 at 8 bits.
+pub fn apply_chroma_range(value: f64, bit_depth: u8, range: SampleRange) -> f64 {
+    match range {
+        SampleRange::Full => value,
+        SampleRange::Limited => {
+            let (scale, max) = if bit_depth == 16 {
+                (256.0, 65535.0)
+            } else {
+                (1.0, 255.0)
+            };
+            (16.0 * scale + 224.0 * scale * value.clamp(0.0, 1.0)) / max
+        }
+    }
+}
+
+/// Appends one pixel's samples in `order`. The samples come from [`Pixel`]'s named
+/// fields, never from position, so the only way R and B can trade places is an explicit
+/// [`ChannelOrder::Bgra`].
+pub fn push_pixel(
+    buf: &mut Vec<u8>,
+    pixel: Pixel,
+    bit_depth: u8,
+    include_alpha: bool,
+    order: ChannelOrder,
+) {
+    let rgb = match order {
+        ChannelOrder::Rgba | ChannelOrder::Argb => [pixel.r, pixel.g, pixel.b],
+        ChannelOrder::Bgra => [pixel.b, pixel.g, pixel.r],
+    };
+    if include_alpha && matches!(order, ChannelOrder::Argb) {
+        push_sample(buf, pixel.a, bit_depth);
+    }
+    for value in rgb {
+        push_sample(buf, value, bit_depth);
+    }
+    if include_alpha && !matches!(order, ChannelOrder::Argb) {
+        push_sample(buf, pixel.a, bit_depth);
+    }
+}
+
+/// Appends one big-endian sample; bit depths other than 8 and 16 append nothing.
+pub fn push_sample(buf: &mut Vec<u8>, value: f64, bit_depth: u8) {
+    match bit_depth {
+        8 => buf.push(quantize(value, 255.0) as u8),
+        16 => {
+            let sample = quantize(value, 65535.0) as u16;
+            buf.extend_from_slice(&sample.to_be_bytes());
+        }
+        _ => {}
+    }
+}
+
+pub fn to_byte(value: f64) -> u8 {
+    quantize(value, 255.0) as u8
+}
+
+/// The nearest web-safe byte: `value` quantized to six levels, each a multiple of 0x33.
+pub fn to_web_safe_byte(value: f64) -> u8 {
+    quantize(value, 5.0) as u8 * 0x33
+}
+
+/// Clamps `value` to 0..1 and scales it to 0..`max`, rounded. `-0.0` (which `clamp` keeps)
+/// becomes `+0.0` first, so every sample comes from the same path whatever the sign of
+/// a zero the conversion produced.
+fn quantize(value: f64, max: f64) -> f64 {
+    ((value.clamp(0.0, 1.0) + 0.0) * max).round()
+}
+
+/// Appends row `y` of `image` with no per-row framing.
+pub fn push_row(
+    buf: &mut Vec<u8>,
+    image: &Image,
+    y: u32,
+    bit_depth: u8,
+    include_alpha: bool,
+    order: ChannelOrder,
+) {
+    for x in 0..image.width() {
+        push_pixel(buf, image.pixel(x, y), bit_depth, include_alpha, order);
+    }
+}
+
+/// Every row of `image`, top to bottom, with no per-row framing.
+pub fn image_samples(
+    image: &Image,
+    bit_depth: u8,
+    include_alpha: bool,
+    order: ChannelOrder,
+) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for y in 0..image.height() {
+        push_row(&mut buf, image, y, bit_depth, include_alpha, order);
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{ColorSpace, P3Matrix, encoded_pixel};
+
+    #[test]
+    fn channel_order_puts_red_where_asked() {
+        let (red, _) =
+            encoded_pixel(ColorSpace::Srgb, P3Matrix::Baked, 0.628, 0.2577, 29.23, 1.0).unwrap();
+        for bit_depth in [8, 16] {
+            let width = usize::from(bit_depth / 8);
+            let samples = |order| {
+                let mut buf = Vec::new();
+                push_pixel(&mut buf, red, bit_depth, false, order);
+                buf.chunks(width).map(|s| s[0]).collect::<Vec<_>>()
+            };
+
+            let rgba = samples(ChannelOrder::Rgba);
+            assert!(rgba[0] > 0xf0 && rgba[2] < 0x10, "{rgba:?}");
+            let bgra = samples(ChannelOrder::Bgra);
+            assert!(bgra[0] < 0x10 && bgra[2] > 0xf0, "{bgra:?}");
+        }
+    }
+
+    #[test]
+    fn negative_zero_quantizes_like_zero() {
+        assert_eq!(
+            quantize(-0.0, 255.0).to_bits(),
+            quantize(0.0, 255.0).to_bits()
+        );
+        assert_eq!(quantize(-0.0, 65535.0).to_bits(), 0.0f64.to_bits());
+        for bit_depth in [8, 16] {
+            let samples = |zero: f64| {
+                let pixel = Pixel {
+                    r: zero,
+                    g: 0.5,
+                    b: zero,
+                    a: 1.0,
+                };
+                let mut buf = Vec::new();
+                push_pixel(&mut buf, pixel, bit_depth, true, ChannelOrder::Rgba);
+                buf
+            };
+            assert_eq!(samples(-0.0), samples(0.0));
+        }
+    }
+}
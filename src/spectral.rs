@@ -0,0 +1,112 @@
+//! Monochromatic light as a color: wavelengths through the CIE 1931 2° standard observer
+//! color matching functions to XYZ, and from there to OKLCH.
+
+use crate::Error;
+use crate::color::xyz_to_oklab;
+
+/// The shortest wavelength accepted, in nanometers.
+pub const MIN_WAVELENGTH: f64 = 380.0;
+/// The longest wavelength accepted, in nanometers.
+pub const MAX_WAVELENGTH: f64 = 700.0;
+
+/// The spacing of [`CMF`], in nanometers.
+const CMF_STEP: f64 = 5.0;
+
+/// x̄, ȳ, and z̄ from [`MIN_WAVELENGTH`] to [`MAX_WAVELENGTH`] every [`CMF_STEP`] nm.
+const CMF: [[f64; 3]; 65] = [
+    [0.001368, 0.000039, 0.00645],
+    [0.002236, 0.000064, 0.01055],
+    [0.004243, 0.00012, 0.02005],
+    [0.00765, 0.000217, 0.03621],
+    [0.01431, 0.000396, 0.06785],
+    [0.02319, 0.00064, 0.1102],
+    [0.04351, 0.00121, 0.2074],
+    [0.07763, 0.00218, 0.3713],
+    [0.13438, 0.004, 0.6456],
+    [0.21477, 0.0073, 1.03905],
+    [0.2839, 0.0116, 1.3856],
+    [0.3285, 0.01684, 1.62296],
+    [0.34828, 0.023, 1.74706],
+    [0.34806, 0.0298, 1.7826],
+    [0.3362, 0.038, 1.77211],
+    [0.3187, 0.048, 1.7441],
+    [0.2908, 0.06, 1.6692],
+    [0.2511, 0.0739, 1.5281],
+    [0.19536, 0.09098, 1.28764],
+    [0.1421, 0.1126, 1.0419],
+    [0.09564, 0.13902, 0.81295],
+    [0.05795, 0.1693, 0.6162],
+    [0.03201, 0.20802, 0.46518],
+    [0.0147, 0.2586, 0.3533],
+    [0.0049, 0.323, 0.272],
+    [0.0024, 0.4073, 0.2123],
+    [0.0093, 0.503, 0.1582],
+    [0.0291, 0.6082, 0.1117],
+    [0.06327, 0.71, 0.07825],
+    [0.1096, 0.7932, 0.05725],
+    [0.1655, 0.862, 0.04216],
+    [0.22575, 0.91485, 0.02984],
+    [0.2904, 0.954, 0.0203],
+    [0.3597, 0.9803, 0.0134],
+    [0.43345, 0.99495, 0.00875],
+    [0.51205, 1.0, 0.00575],
+    [0.5945, 0.995, 0.0039],
+    [0.6784, 0.9786, 0.00275],
+    [0.7621, 0.952, 0.0021],
+    [0.8425, 0.9154, 0.0018],
+    [0.9163, 0.87, 0.00165],
+    [0.9786, 0.8163, 0.0014],
+    [1.0263, 0.757, 0.0011],
+    [1.0567, 0.6949, 0.001],
+    [1.0622, 0.631, 0.0008],
+    [1.0456, 0.5668, 0.0006],
+    [1.0026, 0.503, 0.00034],
+    [0.9384, 0.4412, 0.00024],
+    [0.85445, 0.381, 0.00019],
+    [0.7514, 0.321, 0.0001],
+    [0.6424, 0.265, 0.00005],
+    [0.5419, 0.217, 0.00003],
+    [0.4479, 0.175, 0.00002],
+    [0.3608, 0.1382, 0.00001],
+    [0.2835, 0.107, 0.0],
+    [0.2187, 0.0816, 0.0],
+    [0.1649, 0.061, 0.0],
+    [0.1212, 0.04458, 0.0],
+    [0.0874, 0.032, 0.0],
+    [0.0636, 0.0232, 0.0],
+    [0.04677, 0.017, 0.0],
+    [0.0329, 0.01192, 0.0],
+    [0.0227, 0.00821, 0.0],
+    [0.01584, 0.005723, 0.0],
+    [0.011359, 0.004102, 0.0],
+];
+
+/// The CIE 1931 tristimulus values of one unit of light at `nm`, interpolated linearly
+/// between the 5 nm rows of the table. `nm` is clamped to the table's range.
+pub fn wavelength_to_xyz(nm: f64) -> (f64, f64, f64) {
+    let position = (nm.clamp(MIN_WAVELENGTH, MAX_WAVELENGTH) - MIN_WAVELENGTH) / CMF_STEP;
+    let index = (position.floor() as usize).min(CMF.len() - 2);
+    let t = position - index as f64;
+    let [x, y, z] = [0, 1, 2].map(|i| CMF[index][i] * (1.0 - t) + CMF[index + 1][i] * t);
+    (x, y, z)
+}
+
+/// The OKLCH color of light at `nm`, made brighter or dimmer until its Oklab lightness
+/// is `l`. Scaling XYZ by k scales all of Oklab by the cube root of k, so this keeps the
+/// hue and the chroma-to-lightness ratio of the spectral color exactly. Wavelengths
+/// outside [`MIN_WAVELENGTH`]..=[`MAX_WAVELENGTH`] are refused.
+pub fn wavelength_to_oklch(nm: f64, l: f64) -> Result<(f64, f64, f64), Error> {
+    if !(MIN_WAVELENGTH..=MAX_WAVELENGTH).contains(&nm) {
+        return Err(Error::OutOfRange {
+            name: "wavelength".to_string(),
+            value: nm,
+            min: MIN_WAVELENGTH,
+            max: MAX_WAVELENGTH,
+        });
+    }
+    let (x, y, z) = wavelength_to_xyz(nm);
+    let (spectral_l, a, b) = xyz_to_oklab(x, y, z);
+    let scale = l / spectral_l;
+    let (a, b) = (a * scale, b * scale);
+    Ok((l, a.hypot(b), b.atan2(a).to_degrees().rem_euclid(360.0)))
+}
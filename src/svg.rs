@@ -0,0 +1,55 @@
+use std::io::{self, Write};
+
+use crate::Pixel;
+
+/// Writes a `width` by `height` SVG filled with `pixel` as Display P3, falling back to
+/// `fallback_hex` in renderers without `color()` support.
+pub fn write_svg<W: Write>(
+    writer: &mut W,
+    width: u32,
+    height: u32,
+    include_alpha: bool,
+    pixel: Pixel,
+    fallback_hex: &str,
+) -> io::Result<()> {
+    // The presentation attribute is the sRGB fallback; renderers that understand
+    // color() pick up the Display P3 value from the style declaration instead.
+    let p3 = css_display_p3(pixel, include_alpha);
+
+    writeln!(
+        writer,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\">"
+    )?;
+    writeln!(
+        writer,
+        "  <rect width=\"{width}\" height=\"{height}\" fill=\"{fallback_hex}\" \
+         style=\"fill: {p3}\"/>"
+    )?;
+    writeln!(writer, "</svg>")?;
+    Ok(())
+}
+
+/// `pixel` as a CSS `color(display-p3 ...)` value, with its alpha when `include_alpha`.
+pub fn css_display_p3(pixel: Pixel, include_alpha: bool) -> String {
+    let r = format_css_number(pixel.r);
+    let g = format_css_number(pixel.g);
+    let b = format_css_number(pixel.b);
+    if include_alpha {
+        let a = format_css_number(pixel.a);
+        format!("color(display-p3 {r} {g} {b} / {a})")
+    } else {
+        format!("color(display-p3 {r} {g} {b})")
+    }
+}
+
+/// Formats a 0..1 value with at most five decimals and no trailing zeros.
+pub fn format_css_number(value: f64) -> String {
+    let s = format!("{value:.5}");
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s.is_empty() || s == "-0" {
+        "0".to_string()
+    } else {
+        s.to_string()
+    }
+}
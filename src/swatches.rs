@@ -0,0 +1,570 @@
+//! Reading Procreate `.swatches` palettes: a zip archive holding a `Swatches.json` file.
+
+use std::io::{self, Read};
+
+use flate2::read::DeflateDecoder;
+
+use crate::Pixel;
+use crate::color::{ColorSpace, srgb_to_display_p3};
+
+const ZIP_END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP_CENTRAL_DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+const ZIP_LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+const ZIP_METHOD_STORED: u16 = 0;
+const ZIP_METHOD_DEFLATE: u16 = 8;
+
+const SWATCHES_JSON: &str = "Swatches.json";
+
+/// How deeply arrays and objects may nest. `Swatches.json` needs three levels; the limit
+/// only keeps a hostile file from overflowing the stack.
+const MAX_JSON_DEPTH: usize = 32;
+
+/// One HSB swatch, with every component in 0..1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Swatch {
+    pub hue: f64,
+    pub saturation: f64,
+    pub brightness: f64,
+    pub alpha: f64,
+    /// Procreate's `colorSpace`: 0 for sRGB, 1 for Display P3.
+    pub color_space: ColorSpace,
+}
+
+impl Swatch {
+    /// The swatch as encoded Display P3 samples. sRGB swatches are re-encoded; Display P3
+    /// ones are used as is, with no trip through OKLCH.
+    pub fn display_p3_pixel(&self) -> Pixel {
+        let (r, g, b) = hsb_to_rgb(self.hue, self.saturation, self.brightness);
+        let (r, g, b) = match self.color_space {
+            ColorSpace::Srgb => srgb_to_display_p3(r, g, b),
+            ColorSpace::DisplayP3 => (r, g, b),
+        };
+        Pixel {
+            r,
+            g,
+            b,
+            a: self.alpha,
+        }
+    }
+}
+
+/// Reads every swatch in a `.swatches` file, across all of its palettes. Empty slots in
+/// Procreate's fixed grid are `None`, so indices match the slots in the app.
+pub fn read_swatches<R: Read>(reader: &mut R) -> io::Result<Vec<Option<Swatch>>> {
+    let mut archive = Vec::new();
+    reader.read_to_end(&mut archive)?;
+    let json = zip_entry(&archive, SWATCHES_JSON)?;
+    let json = String::from_utf8(json).map_err(|_| invalid("Swatches.json is not UTF-8"))?;
+    let value = Json::parse(&json)?;
+
+    let palettes = match value {
+        Json::Array(palettes) => palettes,
+        palette => vec![palette],
+    };
+    let mut swatches = Vec::new();
+    for palette in palettes {
+        let Some(Json::Array(entries)) = palette.get("swatches") else {
+            return Err(invalid("a palette has no swatches array"));
+        };
+        for entry in entries {
+            swatches.push(match entry {
+                Json::Null => None,
+                entry => Some(swatch(entry)?),
+            });
+        }
+    }
+    Ok(swatches)
+}
+
+fn swatch(entry: &Json) -> io::Result<Swatch> {
+    let component = |name: &str, default: Option<f64>| match entry.get(name) {
+        Some(&Json::Number(value)) if (0.0..=1.0).contains(&value) => Ok(value),
+        None => default.ok_or_else(|| invalid(&format!("a swatch has no {name}"))),
+        Some(_) => Err(invalid(&format!("a swatch {name} is not a number in 0..1"))),
+    };
+    let color_space = match entry.get("colorSpace") {
+        None => ColorSpace::Srgb,
+        Some(&Json::Number(0.0)) => ColorSpace::Srgb,
+        Some(&Json::Number(1.0)) => ColorSpace::DisplayP3,
+        Some(_) => return Err(invalid("a swatch has an unknown colorSpace")),
+    };
+    Ok(Swatch {
+        hue: component("hue", None)?,
+        saturation: component("saturation", None)?,
+        brightness: component("brightness", None)?,
+        alpha: component("alpha", Some(1.0))?,
+        color_space,
+    })
+}
+
+fn hsb_to_rgb(hue: f64, saturation: f64, brightness: f64) -> (f64, f64, f64) {
+    let sector = (hue * 6.0).rem_euclid(6.0);
+    let chroma = brightness * saturation;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = brightness - chroma;
+    (r + m, g + m, b + m)
+}
+
+/// The uncompressed contents of the zip entry called `name`, located through the
+/// central directory so entries written with data descriptors are read correctly.
+fn zip_entry(archive: &[u8], name: &str) -> io::Result<Vec<u8>> {
+    let u16_at = |offset: usize| -> io::Result<u16> {
+        archive
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .ok_or_else(|| invalid("truncated zip archive"))
+    };
+    let u32_at = |offset: usize| -> io::Result<u32> {
+        archive
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .ok_or_else(|| invalid("truncated zip archive"))
+    };
+
+    let end = (0..archive.len().saturating_sub(21))
+        .rev()
+        .find(|&offset| u32_at(offset).ok() == Some(ZIP_END_OF_CENTRAL_DIRECTORY))
+        .ok_or_else(|| invalid("not a zip archive"))?;
+    let entries = u16_at(end + 10)?;
+    let mut offset = u32_at(end + 16)? as usize;
+
+    for _ in 0..entries {
+        if u32_at(offset)? != ZIP_CENTRAL_DIRECTORY_ENTRY {
+            return Err(invalid("corrupt zip central directory"));
+        }
+        let method = u16_at(offset + 10)?;
+        let crc = u32_at(offset + 16)?;
+        let compressed_size = u32_at(offset + 20)? as usize;
+        let name_len = u16_at(offset + 28)? as usize;
+        let extra_len = u16_at(offset + 30)? as usize;
+        let comment_len = u16_at(offset + 32)? as usize;
+        let local_header = u32_at(offset + 42)? as usize;
+        let entry_name = archive
+            .get(offset + 46..offset + 46 + name_len)
+            .ok_or_else(|| invalid("truncated zip archive"))?;
+        offset += 46 + name_len + extra_len + comment_len;
+        if entry_name != name.as_bytes() {
+            continue;
+        }
+
+        if u32_at(local_header)? != ZIP_LOCAL_FILE_HEADER {
+            return Err(invalid("corrupt zip local header"));
+        }
+        let data_start = local_header
+            + 30
+            + u16_at(local_header + 26)? as usize
+            + u16_at(local_header + 28)? as usize;
+        let data = archive
+            .get(data_start..data_start + compressed_size)
+            .ok_or_else(|| invalid("truncated zip archive"))?;
+        let contents = match method {
+            ZIP_METHOD_STORED => data.to_vec(),
+            ZIP_METHOD_DEFLATE => {
+                let mut contents = Vec::new();
+                DeflateDecoder::new(data).read_to_end(&mut contents)?;
+                contents
+            }
+            _ => return Err(invalid("unsupported zip compression method")),
+        };
+        if crc32fast::hash(&contents) != crc {
+            return Err(invalid(&format!("CRC mismatch in {name}")));
+        }
+        return Ok(contents);
+    }
+    Err(invalid(&format!("no {name} in the archive")))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Just enough JSON to read `Swatches.json`.
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Number(f64),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+    /// A string or boolean; nothing in the file needs their values.
+    Other,
+}
+
+impl Json {
+    fn parse(input: &str) -> io::Result<Json> {
+        let mut parser = JsonParser {
+            text: input,
+            bytes: input.as_bytes(),
+            position: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position != parser.bytes.len() {
+            return Err(parser.error());
+        }
+        Ok(value)
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    bytes: &'a [u8],
+    position: usize,
+    /// Arrays and objects open around `position`.
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self) -> io::Error {
+        invalid(&format!(
+            "malformed Swatches.json at byte {}",
+            self.position
+        ))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> io::Result<()> {
+        if self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn value(&mut self) -> io::Result<Json> {
+        self.skip_whitespace();
+        match self.bytes.get(self.position) {
+            Some(b'n') => self.expect("null").map(|()| Json::Null),
+            Some(b't') => self.expect("true").map(|()| Json::Other),
+            Some(b'f') => self.expect("false").map(|()| Json::Other),
+            Some(b'"') => self.string().map(|_| Json::Other),
+            Some(b'[' | b'{') => {
+                if self.depth == MAX_JSON_DEPTH {
+                    return Err(invalid(&format!(
+                        "Swatches.json nests deeper than {MAX_JSON_DEPTH} levels at byte {}",
+                        self.position
+                    )));
+                }
+                self.depth += 1;
+                let value = if self.bytes[self.position] == b'[' {
+                    self.array()
+                } else {
+                    self.object()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some(_) => self.number(),
+            None => Err(self.error()),
+        }
+    }
+
+    fn array(&mut self) -> io::Result<Json> {
+        self.position += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.position) == Some(&b']') {
+            self.position += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.bytes.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    fn object(&mut self) -> io::Result<Json> {
+        self.position += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.position) == Some(&b'}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.bytes.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    fn number(&mut self) -> io::Result<Json> {
+        let start = self.position;
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(|&b| b.is_ascii_digit() || matches!(b, b'-' | b'+' | b'.' | b'e' | b'E'))
+        {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|text| text.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| self.error())
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        self.expect("\"")?;
+        let mut text = String::new();
+        loop {
+            let mut chars = self.text[self.position..].chars();
+            match chars.next() {
+                Some('"') => {
+                    self.position += 1;
+                    return Ok(text);
+                }
+                Some('\\') => {
+                    let escaped = chars.next().ok_or_else(|| self.error())?;
+                    // Point at the escaped character if it isn't one JSON allows.
+                    self.position += 1;
+                    text.push(match escaped {
+                        '"' | '\\' | '/' => escaped,
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let hex = self
+                                .bytes
+                                .get(self.position + 1..self.position + 5)
+                                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .ok_or_else(|| self.error())?;
+                            self.position += 4;
+                            char::from_u32(hex).unwrap_or('\u{fffd}')
+                        }
+                        _ => return Err(self.error()),
+                    });
+                    self.position += escaped.len_utf8();
+                }
+                Some(ch) => {
+                    self.position += ch.len_utf8();
+                    text.push(ch);
+                }
+                None => return Err(self.error()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::DeflateEncoder;
+    use std::io::Write;
+
+    const PALETTE: &str = r#"{"name": "Test \"palette\" é", "swatches": [
+        {"hue": 0.5, "saturation": 1, "brightness": 0.5, "alpha": 0.25, "colorSpace": 1},
+        null,
+        {"hue": 0, "saturation": 0, "brightness": 1}
+    ]}"#;
+
+    /// A one-entry zip archive holding `contents` as `name`, with no data descriptor.
+    fn zip(name: &str, contents: &[u8], method: u16, crc: u32) -> Vec<u8> {
+        let data = match method {
+            ZIP_METHOD_DEFLATE => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(contents).unwrap();
+                encoder.finish().unwrap()
+            }
+            _ => contents.to_vec(),
+        };
+        let sizes = |archive: &mut Vec<u8>| {
+            archive.extend_from_slice(&method.to_le_bytes());
+            archive.extend_from_slice(&[0; 4]); // modification time and date
+            archive.extend_from_slice(&crc.to_le_bytes());
+            archive.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            archive.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            archive.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            archive.extend_from_slice(&[0; 2]); // extra field length
+        };
+
+        let mut archive = ZIP_LOCAL_FILE_HEADER.to_le_bytes().to_vec();
+        archive.extend_from_slice(&[20, 0, 0, 0]); // version needed, flags
+        sizes(&mut archive);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(&data);
+
+        let directory = archive.len();
+        archive.extend_from_slice(&ZIP_CENTRAL_DIRECTORY_ENTRY.to_le_bytes());
+        archive.extend_from_slice(&[20, 0, 20, 0, 0, 0]); // versions, flags
+        sizes(&mut archive);
+        archive.extend_from_slice(&[0; 10]); // comment length, disk, attributes
+        archive.extend_from_slice(&0u32.to_le_bytes()); // local header offset
+        archive.extend_from_slice(name.as_bytes());
+        let directory_len = archive.len() - directory;
+
+        archive.extend_from_slice(&ZIP_END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        archive.extend_from_slice(&[0; 4]); // disk numbers
+        archive.extend_from_slice(&[1, 0, 1, 0]); // entry counts
+        archive.extend_from_slice(&(directory_len as u32).to_le_bytes());
+        archive.extend_from_slice(&(directory as u32).to_le_bytes());
+        archive.extend_from_slice(&[0; 2]); // comment length
+        archive
+    }
+
+    fn swatches_file(method: u16) -> Vec<u8> {
+        let contents = PALETTE.as_bytes();
+        zip(SWATCHES_JSON, contents, method, crc32fast::hash(contents))
+    }
+
+    fn read(archive: &[u8]) -> io::Result<Vec<Option<Swatch>>> {
+        read_swatches(&mut &archive[..])
+    }
+
+    fn expected() -> Vec<Option<Swatch>> {
+        vec![
+            Some(Swatch {
+                hue: 0.5,
+                saturation: 1.0,
+                brightness: 0.5,
+                alpha: 0.25,
+                color_space: ColorSpace::DisplayP3,
+            }),
+            None,
+            Some(Swatch {
+                hue: 0.0,
+                saturation: 0.0,
+                brightness: 1.0,
+                alpha: 1.0,
+                color_space: ColorSpace::Srgb,
+            }),
+        ]
+    }
+
+    #[test]
+    fn reads_a_stored_entry() {
+        assert_eq!(read(&swatches_file(ZIP_METHOD_STORED)).unwrap(), expected());
+    }
+
+    #[test]
+    fn reads_a_deflated_entry() {
+        let archive = swatches_file(ZIP_METHOD_DEFLATE);
+        assert!(archive.len() < swatches_file(ZIP_METHOD_STORED).len());
+        assert_eq!(read(&archive).unwrap(), expected());
+    }
+
+    #[test]
+    fn null_slots_stay_in_place() {
+        let json = br#"{"swatches": [null, null, {"hue": 0, "saturation": 0, "brightness": 0}]}"#;
+        let archive = zip(
+            SWATCHES_JSON,
+            json,
+            ZIP_METHOD_STORED,
+            crc32fast::hash(json),
+        );
+        let swatches = read(&archive).unwrap();
+        assert_eq!(swatches.len(), 3);
+        assert_eq!(swatches[..2], [None, None]);
+        assert!(swatches[2].is_some());
+    }
+
+    #[test]
+    fn rejects_a_bad_crc() {
+        let contents = PALETTE.as_bytes();
+        let crc = crc32fast::hash(contents) ^ 1;
+        for method in [ZIP_METHOD_STORED, ZIP_METHOD_DEFLATE] {
+            let err = read(&zip(SWATCHES_JSON, contents, method, crc)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().contains("CRC mismatch"), "{err}");
+        }
+    }
+
+    #[test]
+    fn rejects_a_truncated_archive() {
+        let archive = swatches_file(ZIP_METHOD_DEFLATE);
+        for len in 0..archive.len() {
+            let err = read(&archive[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{len} bytes: {err}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_escapes() {
+        for text in [
+            r#""\é""#,
+            r#""\x""#,
+            r#""\u12""#,
+            r#""\u+fff""#,
+            r#""\"#,
+            r#""\u"#,
+        ] {
+            let err = Json::parse(text).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{text}");
+        }
+        let text = r#""a\/\\\"é\n\u00e9""#;
+        let mut parser = JsonParser {
+            text,
+            bytes: text.as_bytes(),
+            position: 0,
+            depth: 0,
+        };
+        assert_eq!(parser.string().unwrap(), "a/\\\"\u{e9}\n\u{e9}");
+        assert_eq!(parser.position, text.len());
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let deep = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        let err = Json::parse(&deep).unwrap_err();
+        assert!(err.to_string().contains("nests deeper"), "{err}");
+
+        let shallow = format!(
+            "{}{}",
+            "[".repeat(MAX_JSON_DEPTH),
+            "]".repeat(MAX_JSON_DEPTH)
+        );
+        assert!(Json::parse(&shallow).is_ok());
+    }
+}
@@ -0,0 +1,265 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// A fresh, empty directory under the system temp dir, unique to this test.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("oklch-pixel-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs the binary in `dir`, with the global config location pointed inside it so a
+/// developer's own config can't leak in.
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_oklch-pixel"))
+        .args(args)
+        .current_dir(dir)
+        .env("XDG_CONFIG_HOME", dir.join("xdg"))
+        .output()
+        .unwrap()
+}
+
+/// [`run`] with `stdin` piped to the binary.
+fn run_with_stdin(dir: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_oklch-pixel"))
+        .args(args)
+        .current_dir(dir)
+        .env("XDG_CONFIG_HOME", dir.join("xdg"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn config_output_dir_yields_to_output_file() {
+    let dir = scratch_dir("config-output-file");
+    fs::write(dir.join("oklch-pixel.toml"), "output-dir = \"outdir\"\n").unwrap();
+
+    let output = run(&dir, &["0.5", "0.1", "40", "--output-file", "foo.png"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(dir.join("foo.png").is_file());
+    assert!(!dir.join("outdir").exists());
+}
+
+#[test]
+fn config_output_dir_yields_to_append_to() {
+    let dir = scratch_dir("config-append-to");
+    fs::write(dir.join("oklch-pixel.toml"), "output-dir = \"outdir\"\n").unwrap();
+
+    let output = run(&dir, &["0.5", "0.1", "40", "--append-to", "sheet.png"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(dir.join("sheet.png").is_file());
+    assert!(!dir.join("outdir").exists());
+}
+
+#[test]
+fn config_output_dir_still_applies_by_default() {
+    let dir = scratch_dir("config-default");
+    fs::write(dir.join("oklch-pixel.toml"), "output-dir = \"outdir\"\n").unwrap();
+
+    let output = run(&dir, &["0.5", "0.1", "40"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(dir.join("outdir").join("oklch(0.5 0.1 40).png").is_file());
+}
+
+#[test]
+fn raw_endian_is_rejected_for_png_output() {
+    let dir = scratch_dir("raw-endian-png");
+
+    let output = run(&dir, &["0.5", "0.1", "40", "--raw-endian", "little"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--raw-endian only applies to raw output"),
+        "{stderr}"
+    );
+    assert!(!dir.join("oklch(0.5 0.1 40).png").exists());
+}
+
+#[test]
+fn raw_endian_little_swaps_sixteen_bit_samples() {
+    let dir = scratch_dir("raw-endian-raw");
+    let args = [
+        "0.5",
+        "0.1",
+        "40",
+        "--output-format",
+        "raw",
+        "--bit-depth",
+        "16",
+    ];
+
+    let mut big = args.to_vec();
+    big.extend(["--output-file", "big.raw"]);
+    assert!(run(&dir, &big).status.success());
+    let mut little = args.to_vec();
+    little.extend(["--raw-endian", "little", "--output-file", "little.raw"]);
+    assert!(run(&dir, &little).status.success());
+
+    let big = fs::read(dir.join("big.raw")).unwrap();
+    let little = fs::read(dir.join("little.raw")).unwrap();
+    assert_eq!(big.len(), 6);
+    let swapped: Vec<u8> = big.chunks(2).flat_map(|s| [s[1], s[0]]).collect();
+    assert_eq!(little, swapped);
+}
+
+#[test]
+fn parallel_batch_overwrites_of_one_file_all_succeed() {
+    let dir = scratch_dir("batch-overwrite");
+    // The lines differ only in their names, so they all claim one file.
+    let lines: String = (0..64)
+        .map(|n| format!("0.5 0.1 40, swatch-{n}\n"))
+        .collect();
+
+    let output = run_with_stdin(
+        &dir,
+        &["batch", "--jobs", "16", "--on-collision", "overwrite"],
+        &lines,
+    );
+    assert!(output.status.success(), "{output:?}");
+    let entries: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name != "xdg")
+        .collect();
+    assert_eq!(entries, ["oklch(0.5 0.1 40).png"]);
+}
+
+#[test]
+fn sidecar_srgb_rejects_samples_its_srgb_chunk_would_mislabel() {
+    let dir = scratch_dir("sidecar-guards");
+    for (flag, value, message) in [
+        (
+            "--transfer",
+            "linear",
+            "--transfer linear with --sidecar-srgb",
+        ),
+        (
+            "--range",
+            "limited",
+            "--range limited can't be used with --sidecar-srgb",
+        ),
+    ] {
+        let output = run(&dir, &["0.5", "0.1", "40", "--sidecar-srgb", flag, value]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(message), "{stderr}");
+    }
+    assert!(!dir.join("oklch(0.5 0.1 40).png").exists());
+}
+
+#[test]
+fn sidecar_srgb_records_the_rendering_intent() {
+    let dir = scratch_dir("sidecar-intent");
+    let args = [
+        "0.5",
+        "0.1",
+        "40",
+        "--sidecar-srgb",
+        "--rendering-intent",
+        "perceptual",
+    ];
+    let output = run(&dir, &args);
+    assert!(output.status.success(), "{output:?}");
+
+    let sidecar = fs::read(dir.join("oklch(0.5 0.1 40).srgb.png")).unwrap();
+    let chunk = sidecar.windows(4).position(|w| w == b"sRGB").unwrap();
+    assert_eq!(sidecar[chunk + 4], 0, "perceptual is intent 0");
+}
+
+#[test]
+fn config_cannot_choose_the_input_color() {
+    let dir = scratch_dir("config-input");
+    for setting in [
+        "hex = \"#ff0000\"",
+        "color-file = \"colors.txt\"",
+        "color_env = \"COLOR\"",
+        "import-swatches = \"palette.swatches\"",
+        "emit = \"all\"",
+        "explain = true",
+        "output-file = \"out.png\"",
+    ] {
+        fs::write(dir.join("oklch-pixel.toml"), format!("{setting}\n")).unwrap();
+        let output = run(&dir, &["0.5", "0.1", "40"]);
+        assert!(!output.status.success(), "{setting}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("can't be set in a config file"), "{stderr}");
+    }
+    assert!(!dir.join("oklch(0.5 0.1 40).png").exists());
+}
+
+#[test]
+fn batch_clip_report_lists_clipped_colors_in_input_order() {
+    let dir = scratch_dir("batch-clip-report");
+    let lines: String = (0..24)
+        .map(|n| format!("0.7 {} {}\n", if n % 3 == 0 { 0.05 } else { 0.4 }, n * 15))
+        .collect();
+
+    let args = ["batch", "--jobs", "8", "--clip-report-file", "clip.csv"];
+    let output = run_with_stdin(&dir, &args, &lines);
+    assert!(output.status.success(), "{output:?}");
+
+    let report = fs::read_to_string(dir.join("clip.csv")).unwrap();
+    let mut rows = report.lines();
+    assert_eq!(
+        rows.next(),
+        Some("l,c,h,alpha,r_overshoot,g_overshoot,b_overshoot,output")
+    );
+    let hues: Vec<u32> = rows
+        .map(|row| row.split(',').nth(2).unwrap().parse().unwrap())
+        .collect();
+    let expected: Vec<u32> = (0..24).filter(|n| n % 3 != 0).map(|n| n * 15).collect();
+    assert_eq!(hues, expected);
+}
+
+#[test]
+fn warn_degenerate_ignores_the_transfer_and_range() {
+    let dir = scratch_dir("warn-degenerate");
+    let base = ["1", "0.2", "30", "--gamut-map", "css", "--warn-degenerate"];
+    for extra in [
+        &[][..],
+        &["--range", "limited"],
+        &["--transfer", "linear"],
+        &["--bit-depth", "16", "--range", "limited"],
+    ] {
+        let output = run(&dir, &[&base[..], extra].concat());
+        assert!(output.status.success(), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("came out pure white"),
+            "{extra:?}: {stderr}"
+        );
+    }
+}
+
+#[test]
+fn roundtrip_rejects_compositing() {
+    let dir = scratch_dir("roundtrip-compositing");
+    for extra in [&["--background", "1 0 0"][..], &["--premultiply"]] {
+        let args = [
+            &["0.5", "0.1", "40", "0.5", "--emit", "roundtrip"][..],
+            extra,
+        ]
+        .concat();
+        let output = run(&dir, &args);
+        assert!(!output.status.success(), "{extra:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("can't be combined with --background or --premultiply"),
+            "{stderr}"
+        );
+    }
+    assert!(!dir.join("oklch(0.5 0.1 40 \u{2215} 0.5).png").exists());
+}
@@ -0,0 +1,234 @@
+//! Byte-exact output for a fixed set of colors, stored as CRC-32s of the whole PNG. A
+//! change to sample quantization, the conversion matrices, or chunk writing that alters
+//! any file fails here; if the change is intended, update the hashes alongside it.
+
+use std::process::Command;
+
+use oklch_pixel::color::{ColorSpace, CustomTransfer, Transfer};
+use oklch_pixel::png::PngOptions;
+use oklch_pixel::render_to_bytes;
+use oklch_pixel::sample::SampleRange;
+
+/// L, C, H and optional alpha, indexed by the first column of [`GOLDEN`].
+const COLORS: [((f64, f64, f64), Option<f64>); 4] = [
+    ((0.0, 0.0, 0.0), None),
+    ((1.0, 0.0, 0.0), None),
+    // CSS `red`, which Display P3 holds but sRGB has to clip.
+    ((0.62796, 0.25768, 29.2339), None),
+    ((0.7, 0.15, 145.0), Some(0.5)),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Curve {
+    Srgb,
+    Linear,
+    Custom,
+}
+
+impl Curve {
+    fn transfer(self) -> Transfer {
+        match self {
+            Curve::Srgb => Transfer::Srgb,
+            Curve::Linear => Transfer::Linear,
+            Curve::Custom => Transfer::Custom(CustomTransfer::new(2.2, 0.0031308, 12.92).unwrap()),
+        }
+    }
+}
+
+/// Color index, color space, transfer, sample range, bit depth, and the CRC-32 of the PNG.
+#[rustfmt::skip]
+const GOLDEN: [(usize, ColorSpace, Curve, SampleRange, u8, u32); 96] = [
+    (0, ColorSpace::Srgb, Curve::Srgb, SampleRange::Full, 8, 0x4c9afe32),
+    (0, ColorSpace::Srgb, Curve::Srgb, SampleRange::Full, 16, 0x4a8eb86e),
+    (0, ColorSpace::Srgb, Curve::Linear, SampleRange::Full, 8, 0x4c9afe32),
+    (0, ColorSpace::Srgb, Curve::Linear, SampleRange::Full, 16, 0x4a8eb86e),
+    (0, ColorSpace::Srgb, Curve::Custom, SampleRange::Full, 8, 0x4c9afe32),
+    (0, ColorSpace::Srgb, Curve::Custom, SampleRange::Full, 16, 0x4a8eb86e),
+    (0, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Full, 8, 0xcf12bd35),
+    (0, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Full, 16, 0x9748b4b5),
+    (0, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Full, 8, 0x38df7a4c),
+    (0, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Full, 16, 0x38d89021),
+    (0, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Full, 8, 0xf878a89b),
+    (0, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Full, 16, 0x2566ea5e),
+    (1, ColorSpace::Srgb, Curve::Srgb, SampleRange::Full, 8, 0xfa025052),
+    (1, ColorSpace::Srgb, Curve::Srgb, SampleRange::Full, 16, 0x45be00ce),
+    (1, ColorSpace::Srgb, Curve::Linear, SampleRange::Full, 8, 0xfa025052),
+    (1, ColorSpace::Srgb, Curve::Linear, SampleRange::Full, 16, 0x45be00ce),
+    (1, ColorSpace::Srgb, Curve::Custom, SampleRange::Full, 8, 0xfa025052),
+    (1, ColorSpace::Srgb, Curve::Custom, SampleRange::Full, 16, 0x45be00ce),
+    (1, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Full, 8, 0x798a1355),
+    (1, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Full, 16, 0x98780c15),
+    (1, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Full, 8, 0x8e47d42c),
+    (1, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Full, 16, 0x37e82881),
+    (1, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Full, 8, 0x4ee006fb),
+    (1, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Full, 16, 0x2a5652fe),
+    (2, ColorSpace::Srgb, Curve::Srgb, SampleRange::Full, 8, 0x31259bfa),
+    (2, ColorSpace::Srgb, Curve::Srgb, SampleRange::Full, 16, 0x669cdfd0),
+    (2, ColorSpace::Srgb, Curve::Linear, SampleRange::Full, 8, 0x31259bfa),
+    (2, ColorSpace::Srgb, Curve::Linear, SampleRange::Full, 16, 0x56a7f8aa),
+    (2, ColorSpace::Srgb, Curve::Custom, SampleRange::Full, 8, 0x31259bfa),
+    (2, ColorSpace::Srgb, Curve::Custom, SampleRange::Full, 16, 0x669cdfd0),
+    (2, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Full, 8, 0x48f76410),
+    (2, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Full, 16, 0xe9a10033),
+    (2, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Full, 8, 0x9dea8d35),
+    (2, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Full, 16, 0xa8718f18),
+    (2, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Full, 8, 0x36dc7803),
+    (2, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Full, 16, 0x1118a0d0),
+    (3, ColorSpace::Srgb, Curve::Srgb, SampleRange::Full, 8, 0xe2fda9bb),
+    (3, ColorSpace::Srgb, Curve::Srgb, SampleRange::Full, 16, 0x977e011e),
+    (3, ColorSpace::Srgb, Curve::Linear, SampleRange::Full, 8, 0x68bc9710),
+    (3, ColorSpace::Srgb, Curve::Linear, SampleRange::Full, 16, 0x1e844955),
+    (3, ColorSpace::Srgb, Curve::Custom, SampleRange::Full, 8, 0xdd1a2587),
+    (3, ColorSpace::Srgb, Curve::Custom, SampleRange::Full, 16, 0x59d8086b),
+    (3, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Full, 8, 0xcfbc0a40),
+    (3, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Full, 16, 0x51d7cc7b),
+    (3, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Full, 8, 0xa867f60f),
+    (3, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Full, 16, 0x301e8867),
+    (3, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Full, 8, 0x1229dac0),
+    (3, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Full, 16, 0x0cbc9250),
+    (0, ColorSpace::Srgb, Curve::Srgb, SampleRange::Limited, 8, 0xeb74d197),
+    (0, ColorSpace::Srgb, Curve::Srgb, SampleRange::Limited, 16, 0x996de317),
+    (0, ColorSpace::Srgb, Curve::Linear, SampleRange::Limited, 8, 0xeb74d197),
+    (0, ColorSpace::Srgb, Curve::Linear, SampleRange::Limited, 16, 0x996de317),
+    (0, ColorSpace::Srgb, Curve::Custom, SampleRange::Limited, 8, 0xeb74d197),
+    (0, ColorSpace::Srgb, Curve::Custom, SampleRange::Limited, 16, 0x996de317),
+    (0, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Limited, 8, 0x9f661ca0),
+    (0, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Limited, 16, 0x7f2ba077),
+    (0, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Limited, 8, 0x68abdbd9),
+    (0, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Limited, 16, 0x88e6670e),
+    (0, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Limited, 8, 0xa80c090e),
+    (0, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Limited, 16, 0x4841b5d9),
+    (1, ColorSpace::Srgb, Curve::Srgb, SampleRange::Limited, 8, 0xf4d7a1a6),
+    (1, ColorSpace::Srgb, Curve::Srgb, SampleRange::Limited, 16, 0xbbe1a814),
+    (1, ColorSpace::Srgb, Curve::Linear, SampleRange::Limited, 8, 0xf4d7a1a6),
+    (1, ColorSpace::Srgb, Curve::Linear, SampleRange::Limited, 16, 0xbbe1a814),
+    (1, ColorSpace::Srgb, Curve::Custom, SampleRange::Limited, 8, 0xf4d7a1a6),
+    (1, ColorSpace::Srgb, Curve::Custom, SampleRange::Limited, 16, 0xbbe1a814),
+    (1, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Limited, 8, 0x80c56c91),
+    (1, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Limited, 16, 0x5da7eb74),
+    (1, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Limited, 8, 0x7708abe8),
+    (1, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Limited, 16, 0xaa6a2c0d),
+    (1, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Limited, 8, 0xb7af793f),
+    (1, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Limited, 16, 0x6acdfeda),
+    (2, ColorSpace::Srgb, Curve::Srgb, SampleRange::Limited, 8, 0xdd949e1b),
+    (2, ColorSpace::Srgb, Curve::Srgb, SampleRange::Limited, 16, 0xf64f89f6),
+    (2, ColorSpace::Srgb, Curve::Linear, SampleRange::Limited, 8, 0xdd949e1b),
+    (2, ColorSpace::Srgb, Curve::Linear, SampleRange::Limited, 16, 0xebf570f9),
+    (2, ColorSpace::Srgb, Curve::Custom, SampleRange::Limited, 8, 0xdd949e1b),
+    (2, ColorSpace::Srgb, Curve::Custom, SampleRange::Limited, 16, 0xf64f89f6),
+    (2, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Limited, 8, 0x715f3f7d),
+    (2, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Limited, 16, 0xf8468923),
+    (2, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Limited, 8, 0x4dc54065),
+    (2, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Limited, 16, 0x289f603e),
+    (2, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Limited, 8, 0x84095bd9),
+    (2, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Limited, 16, 0x1977d68c),
+    (3, ColorSpace::Srgb, Curve::Srgb, SampleRange::Limited, 8, 0x7b07f356),
+    (3, ColorSpace::Srgb, Curve::Srgb, SampleRange::Limited, 16, 0xc6670ecd),
+    (3, ColorSpace::Srgb, Curve::Linear, SampleRange::Limited, 8, 0x0e9fe75f),
+    (3, ColorSpace::Srgb, Curve::Linear, SampleRange::Limited, 16, 0x9d9c8583),
+    (3, ColorSpace::Srgb, Curve::Custom, SampleRange::Limited, 8, 0x24b36e67),
+    (3, ColorSpace::Srgb, Curve::Custom, SampleRange::Limited, 16, 0x17318969),
+    (3, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Limited, 8, 0x20dc3de5),
+    (3, ColorSpace::DisplayP3, Curve::Srgb, SampleRange::Limited, 16, 0x4a6cc8e3),
+    (3, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Limited, 8, 0xb5ae419c),
+    (3, ColorSpace::DisplayP3, Curve::Linear, SampleRange::Limited, 16, 0x960a80d2),
+    (3, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Limited, 8, 0x3b2968bd),
+    (3, ColorSpace::DisplayP3, Curve::Custom, SampleRange::Limited, 16, 0xef01cc7c),
+];
+
+fn options(color_space: ColorSpace, curve: Curve, range: SampleRange) -> PngOptions {
+    PngOptions {
+        color_space,
+        transfer: curve.transfer(),
+        range,
+        ..PngOptions::default()
+    }
+}
+
+#[test]
+fn rendered_pngs_match_the_golden_hashes() {
+    let mut mismatches = Vec::new();
+    for (index, color_space, curve, range, bit_depth, expected) in GOLDEN {
+        let (lch, alpha) = COLORS[index];
+        let options = options(color_space, curve, range);
+        let bytes = render_to_bytes(lch, alpha, bit_depth, &options).unwrap();
+        let actual = crc32fast::hash(&bytes);
+        if actual != expected {
+            mismatches.push(format!(
+                "color {index}, {color_space:?}, {curve:?}, {range:?}, {bit_depth}-bit: \
+                 expected {expected:#010x}, got {actual:#010x}"
+            ));
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+#[test]
+fn every_combination_has_a_golden_hash() {
+    for index in 0..COLORS.len() {
+        for color_space in [ColorSpace::Srgb, ColorSpace::DisplayP3] {
+            for curve in [Curve::Srgb, Curve::Linear, Curve::Custom] {
+                for range in [SampleRange::Full, SampleRange::Limited] {
+                    for bit_depth in [8, 16] {
+                        let count = GOLDEN
+                            .iter()
+                            .filter(|&&(i, space, c, r, depth, _)| {
+                                (i, space, c, r, depth)
+                                    == (index, color_space, curve, range, bit_depth)
+                            })
+                            .count();
+                        assert_eq!(
+                            count, 1,
+                            "{index} {color_space:?} {curve:?} {range:?} {bit_depth}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn render_to_bytes_matches_the_binary_default() {
+    let dir = std::env::temp_dir().join(format!("oklch-pixel-{}-golden", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (range, range_name) in [
+        (SampleRange::Full, "full"),
+        (SampleRange::Limited, "limited"),
+    ] {
+        for bit_depth in [8, 16] {
+            let output = Command::new(env!("CARGO_BIN_EXE_oklch-pixel"))
+                .args(["0.62796", "0.25768", "29.2339", "--output-file", "out.png"])
+                .args(["--bit-depth", &bit_depth.to_string(), "--range", range_name])
+                .current_dir(&dir)
+                .env("XDG_CONFIG_HOME", dir.join("xdg"))
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{output:?}");
+            let expected = render_to_bytes(
+                COLORS[2].0,
+                None,
+                bit_depth,
+                &options(ColorSpace::DisplayP3, Curve::Srgb, range),
+            )
+            .unwrap();
+            assert_eq!(
+                std::fs::read(dir.join("out.png")).unwrap(),
+                expected,
+                "{range_name} range, {bit_depth}-bit"
+            );
+        }
+    }
+}
+
+#[test]
+fn render_to_bytes_rejects_unsupported_bit_depths() {
+    for bit_depth in [0, 1, 12, 32] {
+        let err =
+            render_to_bytes((0.5, 0.1, 40.0), None, bit_depth, &PngOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+    let err = render_to_bytes((0.5, 1e300, 40.0), None, 8, &PngOptions::default()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}